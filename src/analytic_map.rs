@@ -0,0 +1,104 @@
+use crate::rrt::{Reachable, RRTFuncs};
+use crate::prm_graph::{PRMNode, PRMFuncs};
+use crate::common::*;
+use bitvec::prelude::*;
+
+type StateValidator = Box<dyn Fn(&[f64; 2]) -> bool>;
+type TransitionValidator = Box<dyn Fn(&[f64; 2], &[f64; 2]) -> bool>;
+
+/// Unbounded map whose obstacles are defined analytically, the sampler bounds are the only limits of the space
+pub struct AnalyticMap {
+	state_validator: StateValidator,
+	transition_validator: TransitionValidator,
+}
+
+impl AnalyticMap {
+	/// transitions are checked by testing states along the segment every resolution
+	pub fn new(state_validator: impl Fn(&[f64; 2]) -> bool + 'static, resolution: f64) -> Self {
+		assert!(resolution > 0.0, "resolution should be strictly positive");
+
+		let state_validator = std::rc::Rc::new(state_validator);
+		let segment_validator = state_validator.clone();
+
+		Self {
+			state_validator: Box::new(move |state| state_validator(state)),
+			transition_validator: Box::new(move |a, b| sample_segment(a, b, resolution).all(|state| segment_validator(&state))),
+		}
+	}
+
+	pub fn with_transition_validator(state_validator: impl Fn(&[f64; 2]) -> bool + 'static,
+									 transition_validator: impl Fn(&[f64; 2], &[f64; 2]) -> bool + 'static) -> Self {
+		Self {
+			state_validator: Box::new(state_validator),
+			transition_validator: Box::new(transition_validator),
+		}
+	}
+
+	pub fn is_state_valid(&self, state: &[f64; 2]) -> bool {
+		(self.state_validator)(state)
+	}
+
+	pub fn is_transition_valid(&self, a: &[f64; 2], b: &[f64; 2]) -> bool {
+		(self.transition_validator)(a, b)
+	}
+}
+
+impl RRTFuncs<2> for AnalyticMap {
+	fn state_validator(&self, state: &[f64; 2]) -> Reachable<'_> {
+		if self.is_state_valid(state) { Reachable::Always } else { Reachable::Never }
+	}
+
+	fn transition_validator(&self, a: &[f64; 2], b: &[f64; 2]) -> Reachable<'_> {
+		if self.is_transition_valid(a, b) { Reachable::Always } else { Reachable::Never }
+	}
+}
+
+impl PRMFuncs<2> for AnalyticMap {
+	fn state_validity(&self, state: &[f64; 2]) -> Option<WorldMask> {
+		if self.is_state_valid(state) { Some(bitvec![1]) } else { None }
+	}
+
+	fn transition_validator(&self, from: &PRMNode<2>, to: &PRMNode<2>) -> Option<WorldMask> {
+		if self.is_transition_valid(&from.state, &to.state) { Some(bitvec![1]) } else { None }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+use super::*;
+use crate::prm::*;
+use crate::sample_space::*;
+
+#[test]
+fn test_validators() {
+	let m = AnalyticMap::new(|state| norm2(state, &[0.0, 0.0]) > 0.3, 0.01);
+
+	assert!(m.is_state_valid(&[0.5, 0.0]));
+	assert!(!m.is_state_valid(&[0.1, 0.1]));
+	assert!(m.is_transition_valid(&[-0.5, 0.5], &[0.5, 0.5]));
+	assert!(!m.is_transition_valid(&[-0.5, 0.0], &[0.5, 0.0]));
+
+	// far away from any image bounds
+	assert!(m.is_state_valid(&[1000.0, -1000.0]));
+}
+
+#[test]
+fn test_plan_around_circular_obstacle() {
+	let m = AnalyticMap::new(|state| norm2(state, &[0.0, 0.0]) > 0.3, 0.01);
+
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &m);
+
+	prm.grow_graph(&[-0.6, 0.0], ball_goal([0.6, 0.0], 0.05, 1), 0.1, 5.0, 500, 10000).expect("graph not grown up to solution");
+	let policy = prm.plan_belief_space(&vec![1.0]).expect("invalid start belief state");
+
+	let path = policy.path_to_leaf(0);
+	assert!(norm2(&path[path.len() - 1], &[0.6, 0.0]) < 0.05);
+
+	for state in densify_path(&path, 0.01) {
+		assert!(norm2(&state, &[0.0, 0.0]) > 0.3);
+	}
+}
+}
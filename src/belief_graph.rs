@@ -1,4 +1,4 @@
-use itertools::{all, enumerate, izip, merge, zip};
+use itertools::{all, enumerate, izip, merge, zip, Itertools};
 
 use crate::common::*;
 use crate::nearest_neighbor::*;
@@ -6,7 +6,7 @@ use crate::sample_space::*;
 use crate::map_io::*; // tests only
 use bitvec::prelude::*;
 use priority_queue::PriorityQueue;
-use std::{collections::BTreeMap, ops::Index};
+use std::{cmp::Reverse, collections::BTreeMap, ops::Index};
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum BeliefNodeType {
@@ -22,6 +22,7 @@ pub struct BeliefNode<const N: usize> {
 	pub parents: Vec<usize>,
     pub children: Vec<usize>,
     pub node_type: BeliefNodeType,
+    pub outcome_probabilities: BTreeMap<usize, f64>, // observation nodes: probability of each observed belief id, see outcome_probability
 }
 
 pub struct BeliefGraph<const N: usize> {
@@ -40,6 +41,7 @@ impl<const N: usize> BeliefGraph<N> {
                 parents: Vec::new(),
                 children: Vec::new(),
                 node_type,
+                outcome_probabilities: BTreeMap::new(),
             }
         );
         id
@@ -49,23 +51,251 @@ impl<const N: usize> BeliefGraph<N> {
 		self.nodes[from_id].children.push(to_id);
 		self.nodes[to_id].parents.push(from_id);
     }
+
+    /// edge from an observation node to one of its outcomes, of the probability given by PRMFuncs::observe_with_probabilities
+    pub fn add_observation_edge(&mut self, from_id: usize, to_id: usize, probability: f64) {
+        self.add_edge(from_id, to_id);
+        let belief_id = self.nodes[to_id].belief_id;
+        self.nodes[from_id].outcome_probabilities.insert(belief_id, probability);
+    }
+
+    /// some outcome probabilities aren't the transition_probability of the beliefs (see PRMFuncs::observe_with_probabilities)
+    pub fn has_soft_observations(&self) -> bool {
+        self.nodes.iter().any(|node| node.outcome_probabilities.iter().any(|(&belief_id, &p)| {
+            (p - transition_probability(&node.belief_state, &self.reachable_belief_states[belief_id])).abs() > BELIEF_STATE_SUM_TOLERANCE
+        }))
+    }
+
+    /// probability of the outcome to_id of the observation from_id, transition_probability when it wasn't given (e.g. graphs built by hand)
+    /// 1 along the action edges
+    pub fn outcome_probability(&self, from_id: usize, to_id: usize) -> f64 {
+        let (from, to) = (&self.nodes[from_id], &self.nodes[to_id]);
+        from.outcome_probabilities.get(&to.belief_id).copied()
+            .unwrap_or_else(|| transition_probability(&from.belief_state, &to.belief_state))
+    }
+
+    /// observation edges change the belief, action edges keep it (an observation node can have both, see ObservationPolicy)
+    pub fn is_observation_edge(&self, from_id: usize, to_id: usize) -> bool {
+        self.nodes[from_id].node_type == BeliefNodeType::Observation && self.nodes[from_id].belief_id != self.nodes[to_id].belief_id
+    }
     
     #[allow(clippy::style)]
     pub fn belief_id(&self, belief_state: &BeliefState) -> usize {
-        self.reachable_belief_states.iter().position(|belief| belief == belief_state).expect("belief state should be found here") // TODO: improve
+        self.reachable_belief_states.iter().position(|belief| beliefs_equal(belief, belief_state, BELIEF_STATE_EQUALITY_TOLERANCE)).expect("belief state should be found here") // TODO: improve
+    }
+
+    /// keeps the nodes flagged in keep (order is preserved) and remaps the edges, returns the new id of each node
+    pub fn retain_nodes(&mut self, keep: &[bool]) -> Vec<Option<usize>> {
+        let mut new_ids = vec![None; self.nodes.len()];
+        let mut n_kept = 0;
+        for (id, &k) in keep.iter().enumerate() {
+            if k {
+                new_ids[id] = Some(n_kept);
+                n_kept += 1;
+            }
+        }
+
+        let remap = |ids: &Vec<usize>| -> Vec<usize> { ids.iter().filter_map(|&id| new_ids[id]).collect() };
+
+        let nodes = std::mem::take(&mut self.nodes);
+        self.nodes = nodes.into_iter().zip(keep)
+            .filter(|(_, &k)| k)
+            .map(|(node, _)| BeliefNode{
+                parents: remap(&node.parents),
+                children: remap(&node.children),
+                ..node
+            })
+            .collect();
+
+        new_ids
+    }
+
+    /// strongly connected components (Tarjan), in reverse topological order: a component only has edges towards the previous ones
+    /// the observations are irreversible, so a component shouldn't span several beliefs
+    pub fn condensation(&self) -> Vec<Vec<usize>> {
+        let n = self.nodes.len();
+        let mut index = vec![None; n];
+        let mut lowlink = vec![0; n];
+        let mut on_stack = vec![false; n];
+        let mut stack = Vec::new();
+        let mut components = Vec::new();
+        let mut next_index = 0;
+
+        for root_id in 0..n {
+            if index[root_id].is_some() {
+                continue;
+            }
+
+            // iterative dfs, (node, position of the next child to visit)
+            let mut call_stack = vec![(root_id, 0)];
+            index[root_id] = Some(next_index); lowlink[root_id] = next_index; next_index += 1;
+            stack.push(root_id); on_stack[root_id] = true;
+
+            while let Some((v_id, child_pos)) = call_stack.pop() {
+                if let Some(&w_id) = self.nodes[v_id].children.get(child_pos) {
+                    call_stack.push((v_id, child_pos + 1));
+                    match index[w_id] {
+                        None => {
+                            index[w_id] = Some(next_index); lowlink[w_id] = next_index; next_index += 1;
+                            stack.push(w_id); on_stack[w_id] = true;
+                            call_stack.push((w_id, 0));
+                        },
+                        Some(w_index) => if on_stack[w_id] {
+                            lowlink[v_id] = lowlink[v_id].min(w_index);
+                        }
+                    }
+                    continue;
+                }
+
+                // all the children of v are visited
+                if let Some(&(parent_id, _)) = call_stack.last() {
+                    lowlink[parent_id] = lowlink[parent_id].min(lowlink[v_id]);
+                }
+
+                if Some(lowlink[v_id]) == index[v_id] {
+                    let mut component = Vec::new();
+                    while let Some(w_id) = stack.pop() {
+                        on_stack[w_id] = false;
+                        component.push(w_id);
+                        if w_id == v_id {
+                            break;
+                        }
+                    }
+                    components.push(component);
+                }
+            }
+        }
+
+        components
+    }
+
+    /// returns the number of (Unknown, Action, Observation) nodes.
+    /// Unknown nodes remaining after construction usually reveal a modeling bug.
+    pub fn node_count_by_type(&self) -> (usize, usize, usize) {
+        self.nodes.iter().fold((0, 0, 0), |(unknown, action, observation), node| match node.node_type {
+            BeliefNodeType::Unknown => (unknown + 1, action, observation),
+            BeliefNodeType::Action => (unknown, action + 1, observation),
+            BeliefNodeType::Observation => (unknown, action, observation + 1),
+        })
     }
 }
 
+impl<const N: usize> GraphNode<N> for BeliefNode<N> {
+	fn state(&self) -> &[f64; N] {
+		&self.state
+	}
+}
+
+impl<const N: usize> Graph<N> for BeliefGraph<N> {
+    type Neighbors<'a> = std::iter::Copied<std::slice::Iter<'a, usize>>;
+
+	fn node(&self, id:usize) -> &dyn GraphNode<N> {
+		&self.nodes[id]
+	}
+	fn n_nodes(&self) -> usize {
+		self.nodes.len()
+	}
+	fn children(&self, id: usize) -> Self::Neighbors<'_> {
+		self.nodes[id].children.iter().copied()
+	}
+	fn parents(&self, id: usize) -> Self::Neighbors<'_> {
+		self.nodes[id].parents.iter().copied()
+	}
+}
+
+/// probability of the outcome child_bs of an observation revealing a set of worlds (child_bs is parent_bs restricted to its support)
+/// the soft observations give their probabilities instead, see PRMFuncs::observe_with_probabilities
 #[allow(clippy::style)]
 pub fn transition_probability(parent_bs: &BeliefState, child_bs: &BeliefState) -> f64 {
     child_bs.iter().zip(parent_bs).fold(0.0, |s, (p, q)| s + if *p > 0.0 { *q } else { 0.0 } )
 }
 
-pub fn conditional_dijkstra<const N: usize>(graph: &BeliefGraph<N>, final_node_ids: &[usize], cost_evaluator: impl Fn(&[f64; N], &[f64; N]) -> f64) -> Vec<f64> {
+/// probabilities of the outcomes of an observation node, renormalized in case the least probable ones were dropped
+pub fn observation_probabilities<const N: usize>(graph: &BeliefGraph<N>, node_id: usize) -> Vec<(usize, f64)> {
+    let node = &graph.nodes[node_id];
+    let probabilities: Vec<(usize, f64)> = node.children.iter()
+        .filter(|&&child_id| graph.is_observation_edge(node_id, child_id))
+        .map(|&child_id| (child_id, graph.outcome_probability(node_id, child_id)))
+        .collect();
+
+    let sum: f64 = probabilities.iter().map(|(_, p)| p).sum();
+    if sum > 0.0 && (sum - 1.0).abs() > BELIEF_STATE_SUM_TOLERANCE {
+        probabilities.into_iter().map(|(id, p)| (id, p / sum)).collect()
+    } else {
+        probabilities
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransitionError {
+    InconsistentWorld(usize), // the child puts mass on a world excluded by the parent
+}
+
+/// same as transition_probability, but checks that the child belief is a refinement of the parent belief
+#[allow(clippy::style)]
+pub fn transition_probability_checked(parent_bs: &BeliefState, child_bs: &BeliefState) -> Result<f64, TransitionError> {
+    if let Some(world) = child_bs.iter().zip(parent_bs).position(|(p, q)| *p > 0.0 && *q == 0.0) {
+        return Err(TransitionError::InconsistentWorld(world));
+    }
+
+    Ok(transition_probability(parent_bs, child_bs))
+}
+
+/// adapter for the cost evaluators which don't depend on the belief state
+pub fn ignore_belief<const N: usize>(cost_evaluator: impl Fn(&[f64; N], &[f64; N]) -> f64) -> impl Fn(&[f64; N], &[f64; N], &BeliefState) -> f64 {
+    move |a, b, _| cost_evaluator(a, b)
+}
+
+/// the cost of a transition can depend on the belief state in which it is taken
+pub fn conditional_dijkstra<const N: usize>(graph: &BeliefGraph<N>, final_node_ids: &[usize], cost_evaluator: impl Fn(&[f64; N], &[f64; N], &BeliefState) -> f64) -> Vec<f64> {
+    conditional_dijkstra_with_edge_costs(graph, final_node_ids, |u_id, v_id| {
+        let (u, v) = (&graph.nodes[u_id], &graph.nodes[v_id]);
+        cost_evaluator(&u.state, &v.state, &u.belief_state)
+    }, 0.0)
+}
+
+/// same as conditional_dijkstra, the cost of the transitions is given from the belief node ids (e.g. to read costs cached in the roadmap)
+/// observation_cost is the fixed cost of sensing, charged at each observation node in addition to the cost of its edges
+pub fn conditional_dijkstra_with_edge_costs<const N: usize>(graph: &BeliefGraph<N>, final_node_ids: &[usize], edge_cost: impl Fn(usize, usize) -> f64, observation_cost: f64) -> Vec<f64> {
+    conditional_weighted_astar(graph, final_node_ids, edge_cost, observation_cost, None, |_| 0.0, 1.0).costs
+}
+
+/// alternative objective: expected number of observations to reach the goals, the travel costs are ignored
+/// each action counts for 1e-6 so that the policies still end at the final nodes (only they have a cost of 0),
+/// between routes with as many observations, the one with the fewest steps is preferred
+pub fn conditional_dijkstra_min_observations<const N: usize>(graph: &BeliefGraph<N>, final_node_ids: &[usize]) -> Vec<f64> {
+    conditional_dijkstra_with_edge_costs(graph, final_node_ids, |u_id, v_id| if graph.is_observation_edge(u_id, v_id) { 0.0 } else { 1e-6 }, 1.0)
+}
+
+/// result of conditional_weighted_astar
+pub struct SearchResult {
+    pub costs: Vec<f64>,
+    pub expanded: Vec<bool>, // the costs of the nodes never expanded are only upper bounds (infinite if never reached)
+    pub n_expansions: usize, // an observation node can be expanded more than once
+}
+
+/// weighted A* version of conditional_dijkstra_with_edge_costs, the search goes backward and stops once start_id is expanded
+/// heuristic(id) should be a lower bound of the cost from start_id to id, the nodes are expanded by p * (dist + weight * heuristic)
+/// where p is the probability of reaching their belief from the start belief (the share of their cost in the expected cost of start_id)
+/// this only orders the search: on the AND-OR belief graph, the cost of start_id is the one of a valid policy but isn't guaranteed
+/// to be within a factor weight of the optimal one, even for weight 1. Without start_id, the search is exhaustive and the costs are exact
+/// ties are broken by node id, lowest first, so the relaxation order and the costs are reproducible
+/// PriorityQueue::push on a node already queued updates its priority (decrease-key), there are no stale entries,
+/// with weight 1, a node can still be expanded again if its cost improves after it was popped (e.g. an observation node whose outcome improves),
+/// with weight > 1, the expanded nodes keep their cost
+pub fn conditional_weighted_astar<const N: usize>(graph: &BeliefGraph<N>, final_node_ids: &[usize], edge_cost: impl Fn(usize, usize) -> f64, observation_cost: f64,
+    start_id: Option<usize>, heuristic: impl Fn(usize) -> f64, weight: f64) -> SearchResult {
 	// https://fr.wikipedia.org/wiki/Algorithme_de_Dijkstra
 	// complexité n log n ;graph.nodes.len()
+    assert!(weight >= 1.0, "the heuristic weight should be greater than 1");
     let mut dist = vec![std::f64::INFINITY; graph.nodes.len()];
+    let mut expanded = vec![false; graph.nodes.len()];
 	let mut q = PriorityQueue::new();
+
+    let priority = |id: usize, dist: f64| {
+        let p = start_id.map_or(1.0, |start_id| transition_probability(&graph.nodes[start_id].belief_state, &graph.nodes[id].belief_state));
+        (Priority{prio: p * (dist + weight * heuristic(id))}, Reverse(id))
+    };
     
     // debug
     println!("number of belief nodes:{}", graph.nodes.len());
@@ -73,13 +303,18 @@ pub fn conditional_dijkstra<const N: usize>(graph: &BeliefGraph<N>, final_node_i
 
 	for &id in final_node_ids {
 		dist[id] = 0.0;
-        q.push(id, Priority{prio: 0.0});
+        q.push(id, priority(id, 0.0));
 	}
 
     let mut it = 0;
 	while !q.is_empty() {
         it+=1;
         let (v_id, _) = q.pop().unwrap();
+        expanded[v_id] = true;
+
+        if start_id == Some(v_id) {
+            break;
+        }
         
         // debug
         if it % 10000 == 0 {
@@ -91,19 +326,16 @@ pub fn conditional_dijkstra<const N: usize>(graph: &BeliefGraph<N>, final_node_i
             let u = &graph.nodes[u_id];
 
             let mut alternative = 0.0;
-            if u.node_type == BeliefNodeType::Action {
-                let v = &graph.nodes[v_id];
-                alternative += cost_evaluator(&u.state, &v.state) + dist[v_id]
+            if u.node_type == BeliefNodeType::Action || u.node_type == BeliefNodeType::Observation && !graph.is_observation_edge(u_id, v_id) {
+                alternative += edge_cost(u_id, v_id) + dist[v_id]
             }
             else if u.node_type == BeliefNodeType::Observation {
-                for &vv_id in &u.children {
-                    let vv = &graph.nodes[vv_id];
-                    let p = transition_probability(&graph.nodes[u_id].belief_state, &graph.nodes[vv_id].belief_state);
-
+                alternative += observation_cost;
+                for (vv_id, p) in observation_probabilities(graph, u_id) {
                     //println!("belief avant:{:?} apres:{:?}", graph.belief_state(u_id), graph.belief_state(vv_id));
                     //assert_eq!(u.children().len(), 2);
 
-                    alternative += p * (cost_evaluator(&u.state, &vv.state) + dist[vv_id]);
+                    alternative += p * (edge_cost(u_id, vv_id) + dist[vv_id]);
                 }
 
                 //println!("alternative for : {} = {}", u_id, alternative);
@@ -112,9 +344,10 @@ pub fn conditional_dijkstra<const N: usize>(graph: &BeliefGraph<N>, final_node_i
                 panic!("node type should be know at this stage!");
             }
 
-			if alternative < dist[u_id] {
+			// as in weighted A*, the expanded nodes aren't reopened when the heuristic is inflated
+			if alternative < dist[u_id] && (weight == 1.0 || !expanded[u_id]) {
                 dist[u_id] = alternative;
-                q.push(u_id, Priority{prio: alternative});
+                q.push(u_id, priority(u_id, alternative)); // updates the priority if u_id is already queued
             }
 		}
     }
@@ -150,10 +383,18 @@ pub fn conditional_dijkstra<const N: usize>(graph: &BeliefGraph<N>, final_node_i
     println!("conditional dijkstra finished..");
     // 
 
-	dist
+	SearchResult{costs: dist, expanded, n_expansions: it}
 }
 
 pub fn extract_policy<const N: usize>(graph: &BeliefGraph<N>, expected_costs_to_goals: &[f64]) -> Policy<N> {
+    extract_policy_with_edge_filter(graph, expected_costs_to_goals, 0.0, |_, _| true)
+}
+
+/// same as extract_policy, the edges (from, to) rejected by edge_filter are never taken (the edges pruned from the DP)
+/// observation_cost is the one of the DP, see decision_children
+/// the nodes from which the goal can't be reached (infinite cost) or without children left are dead-end leafs (reaches_goal is false),
+/// except the observations, which are still made so that the worlds in which the goal can be reached reach it
+pub fn extract_policy_with_edge_filter<const N: usize>(graph: &BeliefGraph<N>, expected_costs_to_goals: &[f64], observation_cost: f64, edge_filter: impl Fn(usize, usize) -> bool) -> Policy<N> {
     if graph.nodes.is_empty() {
         panic!("no belief state graph!");
     }
@@ -161,19 +402,30 @@ pub fn extract_policy<const N: usize>(graph: &BeliefGraph<N>, expected_costs_to_
     let mut policy: Policy<N> = Policy{nodes: Vec::new(), leafs: Vec::new()};
     let mut lifo: Vec<(usize, usize)> = Vec::new(); // policy_node, belief_graph_node
 
-    policy.add_node(&graph.nodes[0].state, &graph.nodes[0].belief_state, false);
+    policy.add_node(&graph.nodes[0].state, &graph.nodes[0].belief_state, false, false);
+    policy.nodes[0].cost_to_goal = expected_costs_to_goals[0];
 
     lifo.push((0, 0));
 
     while !lifo.is_empty() {
         let (policy_node_id, belief_node_id) = lifo.pop().unwrap();
 
-        let children_ids = get_best_expected_children(graph, belief_node_id, expected_costs_to_goals);
+        let mut children_ids = best_expected_children(graph, belief_node_id, expected_costs_to_goals, observation_cost, &edge_filter);
+        if expected_costs_to_goals[belief_node_id].is_infinite() {
+            children_ids.retain(|&child_id| graph.is_observation_edge(belief_node_id, child_id));
+        }
+
+        if children_ids.is_empty() {
+            policy.leafs.push(policy_node_id); // dead-end
+            continue;
+        }
 
         for child_id in children_ids {
             let child = &graph.nodes[child_id];
             let is_leaf = expected_costs_to_goals[child_id] == 0.0;
-            let child_policy_id = policy.add_node(&child.state, &graph.nodes[child_id].belief_state, is_leaf);
+            let child_policy_id = policy.add_node(&child.state, &graph.nodes[child_id].belief_state, is_leaf, is_leaf);
+            policy.nodes[child_policy_id].cost_to_goal = expected_costs_to_goals[child_id];
+            policy.nodes[child_policy_id].outcome_probability = Some(graph.outcome_probability(belief_node_id, child_id));
             policy.add_edge(policy_node_id, child_policy_id);
 
             //println!("add node, belief {:?}, cost: {:?}", &graph.belief_state(child_id), &expected_costs_to_goals[child_id]);
@@ -183,30 +435,162 @@ pub fn extract_policy<const N: usize>(graph: &BeliefGraph<N>, expected_costs_to_
             }
         }
     }
+
+    if cfg!(debug_assertions) {
+        policy.validate().expect("malformed policy");
+    }
+
     policy
 }
 
-pub fn get_best_expected_children<const N: usize>(graph: &BeliefGraph<N>, belief_node_id: usize, expected_costs_to_goals: &[f64]) -> Vec<usize> {    
+/// all the policies whose children are within epsilon of the best ones, the greedy policy of extract_policy comes first
+pub fn extract_all_optimal_policies<const N: usize>(graph: &BeliefGraph<N>, expected_costs_to_goals: &[f64], epsilon: f64, max_policies: usize) -> Vec<Policy<N>> {
+    if graph.nodes.is_empty() {
+        panic!("no belief state graph!");
+    }
+
+    assert!(max_policies > 0, "at least one policy should be returned");
+
+    let mut policy: Policy<N> = Policy{nodes: Vec::new(), leafs: Vec::new()};
+    policy.add_node(&graph.nodes[0].state, &graph.nodes[0].belief_state, false, false);
+    policy.nodes[0].cost_to_goal = expected_costs_to_goals[0];
+
+    let mut policies: Vec<Policy<N>> = Vec::new();
+    let mut pending: Vec<(Policy<N>, Vec<(usize, usize)>)> = vec![(policy, vec![(0, 0)])]; // policy, lifo of (policy_node, belief_graph_node)
+
+    while let Some((policy, mut lifo)) = pending.pop() {
+        let (policy_node_id, belief_node_id) = match lifo.pop() {
+            Some(ids) => ids,
+            None => {
+                policies.push(policy);
+                if policies.len() == max_policies {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        // one policy per combination of co-optimal children, as long as the cap isn't reached
+        let mut alternatives = get_optimal_expected_children(graph, belief_node_id, expected_costs_to_goals, epsilon);
+        if expected_costs_to_goals[belief_node_id].is_infinite() {
+            alternatives.retain(|children| graph.is_observation_edge(belief_node_id, children[0]));
+        }
+
+        if alternatives.is_empty() {
+            // dead-end, as in extract_policy_with_edge_filter
+            let mut policy = policy;
+            policy.leafs.push(policy_node_id);
+            pending.push((policy, lifo));
+            continue;
+        }
+
+        let available = max_policies - policies.len() - pending.len();
+        let combinations: Vec<Vec<usize>> = alternatives.into_iter()
+            .multi_cartesian_product()
+            .take(available)
+            .collect();
+
+        let mut branches = Vec::new();
+        for children_ids in combinations {
+            let (mut policy, mut lifo) = (policy.clone(), lifo.clone());
+
+            for child_id in children_ids {
+                let child = &graph.nodes[child_id];
+                let is_leaf = expected_costs_to_goals[child_id] == 0.0;
+                let child_policy_id = policy.add_node(&child.state, &child.belief_state, is_leaf, is_leaf);
+                policy.nodes[child_policy_id].cost_to_goal = expected_costs_to_goals[child_id];
+                policy.nodes[child_policy_id].outcome_probability = Some(graph.outcome_probability(belief_node_id, child_id));
+                policy.add_edge(policy_node_id, child_policy_id);
+
+                if ! is_leaf {
+                    lifo.push((child_policy_id, child_id));
+                }
+            }
+
+            branches.push((policy, lifo));
+        }
+
+        // first combination processed first
+        branches.reverse();
+        pending.extend(branches);
+    }
+
+    policies
+}
+
+/// children among which the policy chooses: at an observation node that can also act, either the observation outcomes
+/// or the action children, whichever the DP picked: observing if its cost (observation_cost plus the expected cost of the outcomes,
+/// the observation edges don't move) is the cost of the node, acting otherwise, so ties go to the observation
+/// the action edges rejected by edge_filter are dropped, and so is the observation if one of its outcomes is rejected
+fn decision_children<const N: usize>(graph: &BeliefGraph<N>, belief_node_id: usize, expected_costs_to_goals: &[f64], observation_cost: f64, edge_filter: &impl Fn(usize, usize) -> bool) -> Vec<usize> {
+    let children = &graph.nodes[belief_node_id].children;
+    let (mut observation_children, action_children): (Vec<usize>, Vec<usize>) = children.iter().partition(|&&child_id| graph.is_observation_edge(belief_node_id, child_id));
+    let action_children: Vec<usize> = action_children.into_iter().filter(|&child_id| edge_filter(belief_node_id, child_id)).collect();
+
+    if !observation_children.iter().all(|&child_id| edge_filter(belief_node_id, child_id)) {
+        observation_children.clear();
+    }
+
+    if observation_children.is_empty() || action_children.is_empty() {
+        return [observation_children, action_children].concat();
+    }
+
+    let observe_cost: f64 = observation_cost + observation_probabilities(graph, belief_node_id).iter().map(|&(child_id, p)| p * expected_costs_to_goals[child_id]).sum::<f64>();
+    let cost = expected_costs_to_goals[belief_node_id];
+
+    // the sums of the DP are in another order
+    if observe_cost <= cost + 1e-9 * cost.abs().max(1.0) { observation_children } else { action_children }
+}
+
+/// per reachable belief, the children within epsilon of the best one, the best one first
+pub fn get_optimal_expected_children<const N: usize>(graph: &BeliefGraph<N>, belief_node_id: usize, expected_costs_to_goals: &[f64], epsilon: f64) -> Vec<Vec<usize>> {
+    let mut belief_to_children = BTreeMap::new();
+    for child_id in decision_children(graph, belief_node_id, expected_costs_to_goals, 0.0, &|_, _| true) {
+        let child = &graph.nodes[child_id];
+        belief_to_children.entry(child.belief_id).or_insert_with(Vec::new).push((child_id, expected_costs_to_goals[child_id]));
+    }
+
+    belief_to_children.values_mut()
+        .map(|children| {
+            children.sort_by(|(id_a, cost_a), (id_b, cost_b)| cost_a.partial_cmp(cost_b).unwrap().then(id_a.cmp(id_b)));
+            let best_cost = children[0].1;
+            children.iter()
+                .take_while(|(_, cost)| *cost <= best_cost + epsilon)
+                .map(|(child_id, _)| *child_id)
+                .collect()
+        })
+        .collect()
+}
+
+pub fn get_best_expected_children<const N: usize>(graph: &BeliefGraph<N>, belief_node_id: usize, expected_costs_to_goals: &[f64]) -> Vec<usize> {
+    best_expected_children(graph, belief_node_id, expected_costs_to_goals, 0.0, &|_, _| true)
+}
+
+fn best_expected_children<const N: usize>(graph: &BeliefGraph<N>, belief_node_id: usize, expected_costs_to_goals: &[f64], observation_cost: f64, edge_filter: &impl Fn(usize, usize) -> bool) -> Vec<usize> {
     // cluster children by target belief state
     let mut belief_to_children = BTreeMap::new();
-    for &child_id in &graph.nodes[belief_node_id].children {
+    for child_id in decision_children(graph, belief_node_id, expected_costs_to_goals, observation_cost, edge_filter) {
         let child = &graph.nodes[child_id];
 
         belief_to_children.entry(child.belief_id).or_insert_with(Vec::new);
         belief_to_children.get_mut(&child.belief_id).unwrap().push((child_id, expected_costs_to_goals[child_id]));
     }
 
-    // choose the best for each belief state
+    // choose the best for each belief state (children sorted by id so that ties don't depend on the edge insertion order)
     let mut best_children: Vec<usize> = Vec::new();
 
+    for children in belief_to_children.values_mut() {
+        children.sort_by_key(|&(child_id, _)| child_id);
+    }
+
     for belief_id in belief_to_children.keys() {
         let mut best_id = belief_to_children[belief_id][0].0;
-        let p = transition_probability(&graph.nodes[belief_node_id].belief_state, &graph.nodes[best_id].belief_state);
+        let p = graph.outcome_probability(belief_node_id, best_id);
 
         assert!(p > 0.0);
         
         let mut best_cost = p * belief_to_children[belief_id][0].1;
-        for (child_id, cost) in belief_to_children[belief_id].iter().skip(0) {
+        for (child_id, cost) in belief_to_children[belief_id].iter().skip(1) {
             if p * *cost < best_cost {
                 best_cost = p * *cost;
                 best_id = *child_id;
@@ -452,13 +836,34 @@ fn create_graph_2(belief_states: &Vec<Vec<f64>>) -> BeliefGraph<2> {
     belief_graph
 }
 
+#[test]
+fn test_policy_length_stats_on_graph_1() {
+    let belief_states = vec![vec![0.4, 0.6], vec![1.0, 0.0], vec![0.0, 1.0]];
+
+    let graph = create_graph_1(&belief_states);
+    let dists = conditional_dijkstra(&graph, &vec![3, 10, 16], ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
+    let policy = extract_policy(&graph, &dists);
+
+    // observation in 4, then 5 -> 10 in world 0 and 11 -> 16 (detour by 15) in world 1
+    let length = |ids: &[usize]| pairwise_iter(ids).map(|(&a, &b)| norm2(&graph.nodes[a].state, &graph.nodes[b].state)).sum::<f64>();
+    let lengths = [length(&[0, 4, 5, 6, 7, 9, 10]), length(&[0, 4, 11, 12, 14, 15, 16])];
+    assert!(lengths[1] > lengths[0]);
+
+    let mean = 0.4 * lengths[0] + 0.6 * lengths[1];
+    let variance = 0.4 * (lengths[0] - mean).powi(2) + 0.6 * (lengths[1] - mean).powi(2);
+
+    let (policy_mean, policy_variance) = policy.length_stats();
+    assert!((policy_mean - mean).abs() < 0.000001);
+    assert!((policy_variance - variance).abs() < 0.000001);
+}
+
 #[test]
 fn test_conditional_dijkstra_and_extract_policy_on_graph_1() {
     let belief_states = vec![vec![0.4, 0.6], vec![1.0, 0.0], vec![0.0, 1.0]];
 
     let graph = create_graph_1(&belief_states);
     
-    let dists = conditional_dijkstra(&graph, &vec![3, 10, 16], |a: &[f64; 2], b: &[f64; 2]| norm2(a, b) );
+    let dists = conditional_dijkstra(&graph, &vec![3, 10, 16], ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
     let policy = extract_policy(&graph, &dists);
 
     // distance decrease when going towards the goal
@@ -497,13 +902,341 @@ fn test_conditional_dijkstra_and_extract_policy_on_graph_1() {
     assert_eq!(path_1, vec![[0.0, 1.0], [0.0, 0.0], [0.0, 0.0], [0.0, 1.0], [-1.0, 2.0], [-1.0, 3.0], [0.0, 4.0]]); // on the left
 }
 
+#[test]
+fn test_conditional_dijkstra_is_reproducible() {
+    let belief_states = vec![vec![0.4, 0.6], vec![1.0, 0.0], vec![0.0, 1.0]];
+
+    let graph = create_graph_1(&belief_states);
+    let cost = ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b));
+
+    let dists = conditional_dijkstra(&graph, &vec![3, 10, 16], &cost);
+    let dists_again = conditional_dijkstra(&graph, &vec![3, 10, 16], &cost);
+
+    let bits = |dists: &Vec<f64>| dists.iter().map(|d| d.to_bits()).collect::<Vec<_>>();
+    assert_eq!(bits(&dists), bits(&dists_again));
+}
+
+#[test]
+fn test_relaxed_node_is_expanded_once_with_its_minimum_cost() {
+    /*
+        4
+      / | \
+     1  2  3    4 is relaxed by 1, 2 and 3 in this order, with decreasing costs
+      \ | /
+        0
+    */
+    let mut graph = BeliefGraph{nodes: Vec::new(), reachable_belief_states: vec![vec![1.0]]};
+    for id in 0..5 {
+        graph.add_node([id as f64, 0.0], vec![1.0], 0, BeliefNodeType::Action);
+    }
+    for id in 1..4 {
+        graph.add_edge(id, 0);
+        graph.add_edge(4, id);
+    }
+
+    let edge_cost = |u_id: usize, v_id: usize| match (u_id, v_id) {
+        (4, 1) => 10.0,
+        (4, 2) => 5.0,
+        (4, 3) => 0.5,
+        (u_id, _) => u_id as f64,
+    };
+
+    let result = conditional_weighted_astar(&graph, &vec![0], edge_cost, 0.0, None, |_| 0.0, 1.0);
+    assert_eq!(result.costs, vec![0.0, 1.0, 2.0, 3.0, 3.5]);
+    assert_eq!(result.n_expansions, 5);
+    assert!(result.expanded.iter().all(|&expanded| expanded));
+}
+
+#[test]
+fn test_bfs_on_prm_and_belief_graphs() {
+    let mut prm_graph = crate::prm_graph::PRMGraph{nodes: Vec::new()};
+    prm_graph.add_node([0.0, 0.0], bitvec![1]);
+    prm_graph.add_node([1.0, 0.0], bitvec![1]);
+    prm_graph.add_node([0.0, 1.0], bitvec![1]);
+    prm_graph.add_node([5.0, 5.0], bitvec![1]); // isolated
+    prm_graph.add_edge(0, 1, bitvec![1]);
+    prm_graph.add_edge(1, 2, bitvec![1]);
+    prm_graph.add_edge(0, 2, bitvec![1]);
+
+    assert_eq!(bfs(&prm_graph, 0), vec![0, 1, 2]);
+
+    let belief_states = vec![vec![0.4, 0.6], vec![1.0, 0.0], vec![0.0, 1.0]];
+    let belief_graph = create_graph_1(&belief_states);
+
+    let order = bfs(&belief_graph, 0);
+    assert_eq!(order[0], 0);
+    assert!(order.contains(&10) && order.contains(&16)); // goals of the resolved beliefs
+    assert!(!order.contains(&3)); // can't be reached without observing
+    assert_eq!(order.iter().unique().count(), order.len());
+}
+
+#[test]
+fn test_condensation() {
+    let belief_states = vec![vec![0.4, 0.6], vec![1.0, 0.0], vec![0.0, 1.0]];
+    let graph = create_graph_1(&belief_states);
+    let components = graph.condensation();
+
+    // every node in exactly one component
+    let mut ids: Vec<usize> = components.iter().flatten().copied().collect();
+    ids.sort_unstable();
+    assert_eq!(ids, (0..graph.nodes.len()).collect::<Vec<_>>());
+
+    // no component spans two beliefs
+    for component in &components {
+        assert!(component.iter().all(|&id| graph.nodes[id].belief_id == graph.nodes[component[0]].belief_id));
+    }
+
+    // the edges only go towards the previous components
+    let mut component_of = vec![0; graph.nodes.len()];
+    for (component_id, component) in components.iter().enumerate() {
+        for &id in component {
+            component_of[id] = component_id;
+        }
+    }
+    for (id, node) in graph.nodes.iter().enumerate() {
+        assert!(node.children.iter().all(|&child_id| component_of[child_id] <= component_of[id]));
+    }
+
+    // the actions of belief 0 are reversible
+    assert!(components.iter().any(|component| component.len() == 3 && component.contains(&0) && component.contains(&1) && component.contains(&2)));
+}
+
+#[test]
+fn test_extract_all_optimal_policies() {
+    /*
+       3
+      / \
+     1   2
+      \ /
+       0
+    */
+    let mut graph = BeliefGraph{nodes: Vec::new(), reachable_belief_states: vec![vec![1.0]]};
+    graph.add_node([0.0, 0.0], vec![1.0], 0, BeliefNodeType::Action); // 0
+    graph.add_node([1.0, 1.0], vec![1.0], 0, BeliefNodeType::Action); // 1
+    graph.add_node([1.0, -1.0], vec![1.0], 0, BeliefNodeType::Action); // 2
+    graph.add_node([2.0, 0.0], vec![1.0], 0, BeliefNodeType::Action); // 3
+    graph.add_edge(0, 1);
+    graph.add_edge(0, 2);
+    graph.add_edge(1, 3);
+    graph.add_edge(2, 3);
+
+    let dists = conditional_dijkstra(&graph, &vec![3], ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
+    let policies = extract_all_optimal_policies(&graph, &dists, 0.000001, 10);
+
+    assert_eq!(policies.len(), 2);
+    assert_eq!(policies[0].path_to_leaf(0), extract_policy(&graph, &dists).path_to_leaf(0));
+    assert_eq!(policies[0].path_to_leaf(0), vec![[0.0, 0.0], [1.0, 1.0], [2.0, 0.0]]);
+    assert_eq!(policies[1].path_to_leaf(0), vec![[0.0, 0.0], [1.0, -1.0], [2.0, 0.0]]);
+
+    // cap
+    assert_eq!(extract_all_optimal_policies(&graph, &dists, 0.000001, 1).len(), 1);
+
+    // no tie anymore
+    let mut graph = graph;
+    graph.nodes[2].state = [1.0, -1.5];
+    let dists = conditional_dijkstra(&graph, &vec![3], ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
+    assert_eq!(extract_all_optimal_policies(&graph, &dists, 0.000001, 10).len(), 1);
+}
+
+#[test]
+fn test_dead_end_leafs() {
+    /*
+       2   4
+       |   |
+       1   3
+        \ /
+        (0)
+    */
+    // the goal can only be reached in the first world
+    let belief_states = vec![vec![0.4, 0.6], vec![1.0, 0.0], vec![0.0, 1.0]];
+    let mut graph = BeliefGraph{nodes: Vec::new(), reachable_belief_states: belief_states.clone()};
+    graph.add_node([0.0, 0.0], belief_states[0].clone(), 0, BeliefNodeType::Observation); // 0
+    graph.add_node([0.0, 0.0], belief_states[1].clone(), 1, BeliefNodeType::Action); // 1
+    graph.add_node([0.0, 1.0], belief_states[1].clone(), 1, BeliefNodeType::Action); // 2
+    graph.add_node([0.0, 0.0], belief_states[2].clone(), 2, BeliefNodeType::Action); // 3
+    graph.add_node([1.0, 0.0], belief_states[2].clone(), 2, BeliefNodeType::Action); // 4
+    graph.add_edge(0, 1);
+    graph.add_edge(0, 3);
+    graph.add_edge(1, 2);
+    graph.add_edge(3, 4); graph.add_edge(4, 3);
+
+    let dists = conditional_dijkstra(&graph, &[2], ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
+    assert_eq!(dists[0], f64::INFINITY);
+
+    for policy in [extract_policy(&graph, &dists), extract_all_optimal_policies(&graph, &dists, 0.000001, 10).remove(0)] {
+        assert_eq!(policy.validate(), Ok(()));
+        assert_eq!(policy.leafs.len(), 2);
+
+        let (goal_leafs, dead_ends): (Vec<usize>, Vec<usize>) = policy.leafs.iter().partition(|&&id| policy.nodes[id].reaches_goal);
+        assert_eq!(policy.nodes[goal_leafs[0]].state, [0.0, 1.0]);
+        assert_eq!(policy.nodes[dead_ends[0]].state, [0.0, 0.0]);
+        assert_eq!(policy.nodes[dead_ends[0]].belief_state, belief_states[2]);
+        assert!(policy.nodes[dead_ends[0]].children.is_empty());
+
+        assert!((policy.success_probability() - 0.4).abs() < 1e-9);
+    }
+
+    // no goal at all, the observation is all the policy does
+    let dists = conditional_dijkstra(&graph, &[], ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
+    let policy = extract_policy(&graph, &dists);
+    assert_eq!(policy.nodes.len(), 3);
+    assert_eq!(policy.leafs.len(), 2);
+    assert_eq!(policy.success_probability(), 0.0);
+}
+
+#[test]
+fn test_belief_dependent_costs() {
+    // same transition, taken with an uncertain belief (0 -> 1) and with a resolved belief (2 -> 3)
+    let mut graph = BeliefGraph{nodes: Vec::new(), reachable_belief_states: vec![vec![0.5, 0.5], vec![1.0, 0.0]]};
+    graph.add_node([0.0, 0.0], vec![0.5, 0.5], 0, BeliefNodeType::Action); // 0
+    graph.add_node([1.0, 0.0], vec![0.5, 0.5], 0, BeliefNodeType::Action); // 1
+    graph.add_node([0.0, 0.0], vec![1.0, 0.0], 1, BeliefNodeType::Action); // 2
+    graph.add_node([1.0, 0.0], vec![1.0, 0.0], 1, BeliefNodeType::Action); // 3
+    graph.add_edge(0, 1);
+    graph.add_edge(2, 3);
+
+    let dists = conditional_dijkstra(&graph, &vec![1, 3], ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
+    assert_eq!(dists[0], dists[2]);
+
+    let uncertainty_penalty = |a: &[f64; 2], b: &[f64; 2], belief_state: &BeliefState| {
+        let uncertain = belief_state.iter().filter(|&&p| p > 0.0).count() > 1;
+        norm2(a, b) * if uncertain { 2.0 } else { 1.0 }
+    };
+    let dists = conditional_dijkstra(&graph, &vec![1, 3], uncertainty_penalty);
+    assert_eq!(dists[0], 2.0);
+    assert_eq!(dists[2], 1.0);
+}
+
+#[test]
+fn test_policy_to_decision_tree() {
+    let belief_states = vec![vec![0.4, 0.6], vec![1.0, 0.0], vec![0.0, 1.0]];
+
+    let graph = create_graph_1(&belief_states);
+    let dists = conditional_dijkstra(&graph, &vec![3, 10, 16], ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
+    let policy = extract_policy(&graph, &dists);
+    let tree = policy.to_decision_tree();
+
+    // root -> observation -> 2 leafs
+    assert_eq!(tree.nodes.len(), 4);
+    let root = &tree.nodes[0];
+    assert_eq!(root.branches.len(), 1);
+    assert_eq!(root.branches[0].probability, 1.0);
+    assert!(root.goal.is_none());
+
+    let observation = &tree.nodes[root.branches[0].child_index];
+    assert_eq!(observation.belief_state, belief_states[0]);
+    assert_eq!(observation.branches.len(), 2);
+
+    for branch in &observation.branches {
+        let expected_probability = if branch.belief_state == belief_states[1] { belief_states[0][0] } else { belief_states[0][1] };
+        assert_eq!(branch.probability, expected_probability);
+
+        let leaf = &tree.nodes[branch.child_index];
+        assert!(leaf.branches.is_empty());
+        assert_eq!(leaf.goal, Some([0.0, 4.0]));
+    }
+}
+
+#[test]
+fn test_policy_common_prefix() {
+    let belief_states = vec![vec![0.4, 0.6], vec![1.0, 0.0], vec![0.0, 1.0]];
+
+    let graph = create_graph_1(&belief_states);
+    let dists = conditional_dijkstra(&graph, &vec![3, 10, 16], ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
+    let policy = extract_policy(&graph, &dists);
+
+    let prefix = policy.common_prefix();
+    assert_eq!(prefix, vec![[0.0, 1.0], [0.0, 0.0]]); // ends at the observation point
+
+    for leaf_id in 0..policy.leafs.len() {
+        assert_eq!(policy.path_to_leaf(leaf_id)[..prefix.len()], prefix[..]);
+    }
+}
+
+#[test]
+fn test_policy_branch_segments() {
+    let belief_states = vec![vec![0.4, 0.6], vec![1.0, 0.0], vec![0.0, 1.0]];
+
+    let graph = create_graph_1(&belief_states);
+    let dists = conditional_dijkstra(&graph, &vec![3, 10, 16], ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
+    let policy = extract_policy(&graph, &dists);
+    let segments = policy.branch_segments();
+
+    // one action run from the root, then one per branch
+    let n_branches: usize = policy.nodes.iter().filter(|node| node.children.len() > 1).map(|node| node.children.len()).sum();
+    assert_eq!(segments.len(), 1 + n_branches);
+    assert_eq!(segments.len(), 3);
+
+    let (root_belief, root_waypoints) = &segments[0];
+    assert_eq!(root_belief, &belief_states[0]);
+    assert_eq!(root_waypoints[0], policy.nodes[0].state);
+
+    for (belief, waypoints) in &segments[1..] {
+        assert!(belief == &belief_states[1] || belief == &belief_states[2]);
+        assert_eq!(waypoints[0], *root_waypoints.last().unwrap()); // observation point
+        assert_eq!(*waypoints.last().unwrap(), [0.0, 4.0]);
+    }
+
+    // all the waypoints are exported once
+    let n_waypoints: usize = segments.iter().map(|(_, waypoints)| waypoints.len()).sum();
+    assert_eq!(n_waypoints, policy.nodes.len());
+}
+
+#[test]
+fn test_subpolicy_after_observation() {
+    let belief_states = vec![vec![0.4, 0.6], vec![1.0, 0.0], vec![0.0, 1.0]];
+
+    let graph = create_graph_1(&belief_states);
+    let dists = conditional_dijkstra(&graph, &vec![3, 10, 16], ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
+    let policy = extract_policy(&graph, &dists);
+
+    // left branch, world 0
+    let subpolicy = policy.subpolicy_after_observation(&belief_states[1]).unwrap();
+    assert_eq!(subpolicy.validate(), Ok(()));
+    assert_eq!(subpolicy.nodes[0].belief_state, belief_states[1]);
+    assert_eq!(subpolicy.nodes[0].state, [0.0, 0.0]); // observation point
+    assert!(subpolicy.nodes.iter().all(|node| node.belief_state == belief_states[1]));
+    assert_eq!(subpolicy.leafs.len(), 1);
+    assert_eq!(subpolicy.leaf(0).state, [0.0, 4.0]);
+    assert_eq!(subpolicy.path_to_leaf(0), vec![[0.0, 0.0], [0.0, 1.0], [-1.0, 2.0], [-1.0, 3.0], [0.0, 4.0]]);
+    assert_eq!(subpolicy.nodes[0].cost_to_goal, dists[5]);
+
+    assert!(policy.subpolicy_after_observation(&belief_states[2]).is_some());
+    assert!(policy.subpolicy_after_observation(&vec![0.5, 0.5]).is_none());
+}
+
+#[test]
+fn test_policy_cost_to_goal() {
+    let belief_states = vec![vec![0.4, 0.6], vec![1.0, 0.0], vec![0.0, 1.0]];
+
+    for (graph, final_node_ids) in &[(create_graph_1(&belief_states), vec![3, 10, 16]), (create_graph_2(&belief_states), vec![8, 17, 27])] {
+        let dists = conditional_dijkstra(graph, final_node_ids, ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
+        let policy = extract_policy(graph, &dists);
+
+        assert_eq!(policy.nodes[0].cost_to_goal, dists[0]);
+
+        // non-increasing along the paths, after an observation it is the weighted cost of the child that doesn't increase
+        for parent in &policy.nodes {
+            for &child_id in &parent.children {
+                let child = &policy.nodes[child_id];
+                let p = transition_probability(&parent.belief_state, &child.belief_state);
+                assert!(p * child.cost_to_goal <= parent.cost_to_goal + 0.000001);
+            }
+        }
+
+        for &leaf_id in &policy.leafs {
+            assert_eq!(policy.nodes[leaf_id].cost_to_goal, 0.0);
+        }
+    }
+}
+
 #[test]
 fn test_conditional_dijkstra_and_extract_policy_on_graph_2() {
     let belief_states = vec![vec![0.4, 0.6], vec![1.0, 0.0], vec![0.0, 1.0]];
 
     let graph = create_graph_2(&belief_states);
     
-    let dists = conditional_dijkstra(&graph, &vec![8, 17, 27], |a: &[f64; 2], b: &[f64; 2]| norm2(a, b) );
+    let dists = conditional_dijkstra(&graph, &vec![8, 17, 27], ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
     let policy = extract_policy(&graph, &dists);
 
     // dists
@@ -519,6 +1252,113 @@ fn test_conditional_dijkstra_and_extract_policy_on_graph_2() {
     assert_eq!(policy.leaf(1).state, [0.0, 3.0]);
 }
 
+#[test]
+fn test_conditional_weighted_astar_on_graph_2() {
+    let belief_states = vec![vec![0.4, 0.6], vec![1.0, 0.0], vec![0.0, 1.0]];
+
+    let graph = create_graph_2(&belief_states);
+    let edge_cost = |u_id: usize, v_id: usize| norm2(&graph.nodes[u_id].state, &graph.nodes[v_id].state);
+    let heuristic = |id: usize| norm2(&graph.nodes[0].state, &graph.nodes[id].state);
+
+    let exhaustive = conditional_weighted_astar(&graph, &vec![8, 17, 27], edge_cost, 0.0, None, |_| 0.0, 1.0);
+    let dists = exhaustive.costs;
+    assert_eq!(dists, conditional_dijkstra(&graph, &vec![8, 17, 27], ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b))));
+
+    // w = 1, the cost is the one of a valid policy (not necessarily the optimal one)
+    let astar = conditional_weighted_astar(&graph, &vec![8, 17, 27], edge_cost, 0.0, Some(0), heuristic, 1.0);
+    assert!(astar.costs[0] >= dists[0]);
+    assert!(astar.n_expansions < exhaustive.n_expansions);
+    assert!(astar.expanded[0] && astar.expanded.iter().any(|&expanded| !expanded));
+
+    let policy = extract_policy(&graph, &astar.costs);
+    assert_eq!(policy.validate(), Ok(()));
+    assert_eq!(policy.leafs.len(), 2);
+
+    // w > 1, faster, the cost is the one of a valid policy as well
+    let weighted = conditional_weighted_astar(&graph, &vec![8, 17, 27], edge_cost, 0.0, Some(0), heuristic, 3.0);
+    assert!(weighted.n_expansions < astar.n_expansions);
+    assert!(weighted.costs[0] >= dists[0]);
+    assert_eq!(extract_policy(&graph, &weighted.costs).validate(), Ok(()));
+}
+
+#[test]
+fn test_conditional_dijkstra_min_observations() {
+    /*
+      (4,5)
+        |
+        1     3
+        |     |
+        0 --- 2
+    */
+    // the short route needs an observation at 1, the long one doesn't
+    let belief_states = vec![vec![0.5, 0.5], vec![1.0, 0.0], vec![0.0, 1.0]];
+    let mut graph = BeliefGraph{nodes: Vec::new(), reachable_belief_states: belief_states.clone()};
+    graph.add_node([0.0, 0.0], belief_states[0].clone(), 0, BeliefNodeType::Action); // 0
+    graph.add_node([0.0, 1.0], belief_states[0].clone(), 0, BeliefNodeType::Observation); // 1
+    graph.add_node([5.0, 0.0], belief_states[0].clone(), 0, BeliefNodeType::Action); // 2
+    graph.add_node([5.0, 1.0], belief_states[0].clone(), 0, BeliefNodeType::Action); // 3
+    graph.add_node([0.0, 2.0], belief_states[1].clone(), 1, BeliefNodeType::Action); // 4
+    graph.add_node([0.0, 2.0], belief_states[2].clone(), 2, BeliefNodeType::Action); // 5
+    graph.add_edge(0, 1);
+    graph.add_edge(0, 2);
+    graph.add_edge(2, 3);
+    graph.add_edge(1, 4);
+    graph.add_edge(1, 5);
+    let final_node_ids = vec![3, 4, 5];
+
+    // shortest route
+    let dists = conditional_dijkstra(&graph, &final_node_ids, ignore_belief(|a: &[f64; 2], b: &[f64; 2]| norm2(a, b)));
+    assert_eq!(dists[0], 2.0);
+    let policy = extract_policy(&graph, &dists);
+    assert_eq!(policy.leafs.len(), 2);
+
+    // route without observation
+    let observations = conditional_dijkstra_min_observations(&graph, &final_node_ids);
+    assert!(observations[0] < 1.0);
+    assert!((observations[1] - 1.0).abs() < 1e-3);
+    let policy = extract_policy(&graph, &observations);
+    assert_eq!(policy.validate(), Ok(()));
+    assert_eq!(policy.leafs.len(), 1);
+    assert_eq!(policy.path_to_leaf(0), vec![[0.0, 0.0], [5.0, 0.0], [5.0, 1.0]]);
+}
+
+#[test]
+fn test_best_children_dont_depend_on_edge_order() {
+    let create_graph = |children: &[usize]| {
+        let mut graph = BeliefGraph{nodes: Vec::new(), reachable_belief_states: Vec::new()};
+        graph.add_node([0.0, 0.0], vec![1.0], 0, BeliefNodeType::Action); // 0
+        graph.add_node([1.0, 0.0], vec![1.0], 0, BeliefNodeType::Action); // 1
+        graph.add_node([0.0, 1.0], vec![1.0], 0, BeliefNodeType::Action); // 2
+        graph.add_node([-1.0, 0.0], vec![1.0], 0, BeliefNodeType::Action); // 3
+        for &child_id in children {
+            graph.add_edge(0, child_id);
+        }
+        graph
+    };
+
+    let expected_costs_to_goals = vec![1.0, 0.5, 0.5, 0.7]; // tie between 1 and 2
+
+    let best_children = get_best_expected_children(&create_graph(&[1, 2, 3]), 0, &expected_costs_to_goals);
+    assert_eq!(best_children, vec![1]);
+
+    for children in &[[2, 1, 3], [3, 2, 1], [2, 3, 1]] {
+        assert_eq!(get_best_expected_children(&create_graph(children), 0, &expected_costs_to_goals), best_children);
+    }
+}
+
+#[test]
+fn test_belief_id_tolerates_rounding_errors() {
+    let graph: BeliefGraph<2> = BeliefGraph{nodes: Vec::new(), reachable_belief_states: vec![vec![1.0 / 3.0, 2.0 / 3.0], vec![1.0, 0.0], vec![0.0, 1.0]]};
+
+    let rounded = vec![1.0 / 3.0 + 1e-12, 2.0 / 3.0 - 1e-12];
+    assert!(rounded != graph.reachable_belief_states[0]);
+    assert!(beliefs_equal(&rounded, &graph.reachable_belief_states[0], BELIEF_STATE_EQUALITY_TOLERANCE));
+    assert_eq!(graph.belief_id(&rounded), 0);
+    assert_eq!(graph.belief_id(&vec![1e-12, 1.0]), 2);
+
+    assert!(!beliefs_equal(&vec![0.5, 0.5], &vec![0.4, 0.6], BELIEF_STATE_EQUALITY_TOLERANCE));
+    assert!(!beliefs_equal(&vec![0.5, 0.5], &vec![0.5, 0.5, 0.0], BELIEF_STATE_EQUALITY_TOLERANCE));
+}
 
 #[test]
 fn test_transitions() {
@@ -529,4 +1369,12 @@ fn test_transitions() {
     assert_eq!(transition_probability(&vec![0.4, 0.6], &vec![1.0, 0.0]), 0.4);
     assert_eq!(transition_probability(&vec![0.5, 0.0, 0.5, 0.0], &vec![0.0, 0.5, 0.0, 0.5]), 0.0);
 }
+
+#[test]
+fn test_checked_transitions() {
+    assert_eq!(transition_probability_checked(&vec![0.4, 0.6], &vec![1.0, 0.0]), Ok(0.4));
+    assert_eq!(transition_probability_checked(&vec![0.4, 0.6], &vec![0.4, 0.6]), Ok(1.0));
+
+    assert_eq!(transition_probability_checked(&vec![0.0, 1.0], &vec![0.5, 0.5]), Err(TransitionError::InconsistentWorld(0)));
+}
 }
\ No newline at end of file
@@ -2,44 +2,129 @@ use itertools::izip;
 use std::{iter::Zip, slice::Iter, iter::Iterator};
 use bitvec::prelude::*;
 use std::cmp::Ordering;
+use crate::belief_graph::transition_probability;
+use crate::sample_space::DiscreteSampler;
 
 pub type WorldMask = BitVec;
 pub type BeliefState = Vec<f64>;
 pub type NodeId = usize;
 
+/// world queries on the elements holding a validity mask, call sites shouldn't index the raw masks
+/// the masks are packed, one bit per world
+pub trait WorldValidity {
+	fn validity(&self) -> &WorldMask;
+
+	fn valid_in(&self, world: usize) -> bool {
+		self.validity()[world]
+	}
+
+	fn any_valid(&self) -> bool {
+		self.validity().any()
+	}
+
+	fn all_valid(&self) -> bool {
+		self.validity().all()
+	}
+
+	/// worlds where both are valid
+	fn shared_validity(&self, other: &impl WorldValidity) -> WorldMask {
+		let mut validity = self.validity().clone();
+		validity &= other.validity().iter().map(|w| *w);
+		validity
+	}
+
+	/// valid in at least one world of non zero probability
+	fn possible_in(&self, belief_state: &BeliefState) -> bool {
+		self.validity().iter().zip(belief_state).any(|(valid, &p)| *valid && p > 0.0)
+	}
+}
+
+/// bare masks (reachability, finality..) are queried the same way
+impl WorldValidity for WorldMask {
+	fn validity(&self) -> &WorldMask {
+		self
+	}
+}
+
 pub trait GraphNode<const N: usize> {
 	fn state(&self) -> &[f64; N];
 }
 
+/// the neighbors are iterated in place, without allocation
 pub trait Graph<const N: usize> {
+	type Neighbors<'a>: Iterator<Item = usize> where Self: 'a;
+
 	fn node(&self, id:usize) -> &dyn GraphNode<N>;
 	fn n_nodes(&self) -> usize;
-	fn children(&self, id: usize) -> Vec<usize>; // deprecated trait -> do not use
-	fn parents(&self, id: usize) ->Vec<usize>; // deprecated trait -> do not use
+	fn children(&self, id: usize) -> Self::Neighbors<'_>;
+	fn parents(&self, id: usize) -> Self::Neighbors<'_>;
+}
+
+/// ids of the nodes reachable from start_id, in breadth first order
+pub fn bfs<G: Graph<N>, const N: usize>(graph: &G, start_id: usize) -> Vec<usize> {
+	let mut visited = vec![false; graph.n_nodes()];
+	let mut queue = std::collections::VecDeque::new();
+	let mut order = Vec::new();
+
+	visited[start_id] = true;
+	queue.push_back(start_id);
+
+	while let Some(id) = queue.pop_front() {
+		order.push(id);
+
+		for child_id in graph.children(id) {
+			if !visited[child_id] {
+				visited[child_id] = true;
+				queue.push_back(child_id);
+			}
+		}
+	}
+
+	order
 }
 
-pub struct PolicyNode<const N: usize> {
+/// B is the representation of the belief states, dense by default (see SparseBelief)
+#[derive(Clone)]
+pub struct PolicyNode<const N: usize, B = BeliefState> {
 	pub state: [f64; N],
-	pub belief_state: Vec<f64>,
+	pub belief_state: B,
 	pub parent: Option<usize>,
 	pub children: Vec<usize>,
+	pub cost_to_goal: f64, // expected cost to goal of the corresponding belief node
+	pub reaches_goal: bool, // leaf at a goal, a node without children which doesn't is a dead-end
+	pub outcome_probability: Option<f64>, // of the node given its parent, set by the extraction (None: see Policy::outcome_probability)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PolicyError {
+	Empty,
+	InconsistentParent(usize), // the node isn't the child of its parent
+	NotOneParent(usize),
+	LeafWithChildren(usize),
+	Cycle(usize),
+	Unreachable(usize), // from the root
 }
 
-pub struct Policy<const N: usize> {
-	pub nodes: Vec<PolicyNode<N>>,
+#[derive(Clone)]
+pub struct Policy<const N: usize, B = BeliefState> {
+	pub nodes: Vec<PolicyNode<N, B>>,
 	pub leafs: Vec<usize>
 }
 
-impl<const N: usize> Policy<N> {
+impl<const N: usize, B: Clone> Policy<N, B> {
 	#[allow(clippy::style)]
-	pub fn add_node(&mut self, state: &[f64; N], belief_state: &BeliefState, is_leaf: bool) -> usize {
+	/// a leaf which doesn't reach the goal is a dead-end
+	pub fn add_node(&mut self, state: &[f64; N], belief_state: &B, is_leaf: bool, reaches_goal: bool) -> usize {
 		let id = self.nodes.len();
 
 		self.nodes.push(PolicyNode{
 			state: *state,
 			belief_state: belief_state.clone(),
 			parent: None,
-			children: Vec::new()
+			children: Vec::new(),
+			cost_to_goal: 0.0,
+			reaches_goal,
+			outcome_probability: None,
 		});
 
 		if is_leaf {
@@ -54,7 +139,7 @@ impl<const N: usize> Policy<N> {
 		self.nodes[child_id].parent = Some(parent_id);
 	}
 
-	pub fn leaf(&self, id: usize) -> &PolicyNode<N> {
+	pub fn leaf(&self, id: usize) -> &PolicyNode<N, B> {
 		&self.nodes[self.leafs[id]]
 	}
 
@@ -72,6 +157,370 @@ impl<const N: usize> Policy<N> {
 		path.reverse();
 		path
 	}
+
+	/// (time, state) pairs along the path to the leaf at constant velocity, waypoints repeated at observation points are skipped
+	pub fn timed_path_to_leaf(&self, id: usize, velocity: f64) -> Result<Vec<(f64, [f64; N])>, &'static str> {
+		if velocity <= 0.0 {
+			return Err("velocity should be strictly positive");
+		}
+
+		let path = self.path_to_leaf(id);
+		let mut timed_path = vec![(0.0, path[0])];
+
+		for state in path.iter().skip(1) {
+			let &(time, previous) = timed_path.last().unwrap();
+			let length = norm2(&previous, state);
+
+			if length > 0.0 {
+				timed_path.push((time + length / velocity, *state));
+			}
+		}
+
+		Ok(timed_path)
+	}
+
+	/// checks that the policy is a tree rooted at node 0
+	pub fn validate(&self) -> Result<(), PolicyError> {
+		if self.nodes.is_empty() {
+			return Err(PolicyError::Empty);
+		}
+
+		// parents
+		let mut n_parents = vec![0; self.nodes.len()];
+		for (id, node) in self.nodes.iter().enumerate() {
+			for &child_id in &node.children {
+				n_parents[child_id] += 1;
+				if self.nodes[child_id].parent != Some(id) {
+					return Err(PolicyError::InconsistentParent(child_id));
+				}
+			}
+		}
+
+		if n_parents[0] != 0 {
+			return Err(PolicyError::Cycle(0));
+		}
+
+		if let Some(id) = (1..self.nodes.len()).find(|&id| n_parents[id] != 1) {
+			return Err(PolicyError::NotOneParent(id));
+		}
+
+		if let Some(&id) = self.leafs.iter().find(|&&id| !self.nodes[id].children.is_empty()) {
+			return Err(PolicyError::LeafWithChildren(id));
+		}
+
+		// everything reached from the root, only once
+		let mut visited = vec![false; self.nodes.len()];
+		let mut lifo = vec![0];
+		while let Some(id) = lifo.pop() {
+			if visited[id] {
+				return Err(PolicyError::Cycle(id));
+			}
+			visited[id] = true;
+			lifo.extend(&self.nodes[id].children);
+		}
+
+		match visited.iter().position(|v| !v) {
+			Some(id) => Err(PolicyError::Unreachable(id)),
+			None => Ok(())
+		}
+	}
+
+	pub fn cost_to_leaf(&self, id: usize, cost: &impl PathCost<N>) -> f64 {
+		cost.path_cost(&self.path_to_leaf(id))
+	}
+
+	/// timed path to each leaf of the policy
+	pub fn to_timed_trajectory(&self, velocity: f64) -> Result<Vec<Vec<(f64, [f64; N])>>, &'static str> {
+		(0..self.leafs.len())
+			.map(|id| self.timed_path_to_leaf(id, velocity))
+			.collect()
+	}
+
+	/// waypoints shared by all the branches, from the root up to the first observation (or the goal if there is none)
+	pub fn common_prefix(&self) -> Vec<[f64; N]> {
+		if self.nodes.is_empty() {
+			return Vec::new();
+		}
+
+		let mut id = 0;
+		let mut prefix = vec![self.nodes[id].state];
+		while self.nodes[id].children.len() == 1 {
+			id = self.nodes[id].children[0];
+			prefix.push(self.nodes[id].state);
+		}
+
+		prefix
+	}
+}
+
+/// the methods relying on the dense belief states
+impl<const N: usize> Policy<N> {
+	/// probability of child_id given its parent parent_id (1 along the actions), the one of the belief graph when extracted from it,
+	/// otherwise (e.g. policies built by hand) the transition_probability of their beliefs
+	pub fn outcome_probability(&self, parent_id: usize, child_id: usize) -> f64 {
+		self.nodes[child_id].outcome_probability
+			.unwrap_or_else(|| transition_probability(&self.nodes[parent_id].belief_state, &self.nodes[child_id].belief_state))
+	}
+
+	/// standalone policy to follow after an observation resulted in observed_belief_state, None if no observation of the policy leads to it
+	/// the observations closest to the root are checked first
+	pub fn subpolicy_after_observation(&self, observed_belief_state: &BeliefState) -> Option<Policy<N>> {
+		let mut fifo = std::collections::VecDeque::from(vec![0]);
+		while let Some(id) = fifo.pop_front() {
+			let node = self.nodes.get(id)?;
+
+			if node.children.len() > 1 {
+				let outcome = node.children.iter().find(|&&child_id| {
+					let child_belief_state = &self.nodes[child_id].belief_state;
+					self.outcome_probability(id, child_id) > 0.0 && beliefs_equal(child_belief_state, observed_belief_state, BELIEF_STATE_EQUALITY_TOLERANCE)
+				});
+
+				if let Some(&outcome_id) = outcome {
+					return Some(self.subtree(outcome_id));
+				}
+			}
+
+			fifo.extend(&node.children);
+		}
+
+		None
+	}
+
+	/// copy of the subtree rooted at root_id, the leafs keep their order
+	fn subtree(&self, root_id: usize) -> Policy<N> {
+		let mut policy = Policy{nodes: Vec::new(), leafs: Vec::new()};
+		let mut lifo: Vec<(usize, Option<usize>)> = vec![(root_id, None)]; // node, parent in the subtree
+		let mut new_ids = vec![None; self.nodes.len()];
+
+		while let Some((id, parent_id)) = lifo.pop() {
+			let node = &self.nodes[id];
+			let new_id = policy.add_node(&node.state, &node.belief_state, false, node.reaches_goal);
+			policy.nodes[new_id].cost_to_goal = node.cost_to_goal;
+			policy.nodes[new_id].outcome_probability = node.outcome_probability;
+			new_ids[id] = Some(new_id);
+
+			if let Some(parent_id) = parent_id {
+				policy.add_edge(parent_id, new_id);
+			}
+
+			lifo.extend(node.children.iter().rev().map(|&child_id| (child_id, Some(new_id))));
+		}
+
+		policy.leafs = self.leafs.iter().filter_map(|&leaf_id| new_ids[leaf_id]).collect();
+		policy
+	}
+
+	/// probability of ending at a leaf reaching the goal, the rest of the mass ends in dead-ends
+	/// the outcomes of each observation are weighted by their probabilities (see outcome_probability)
+	pub fn success_probability(&self) -> f64 {
+		if self.nodes.is_empty() {
+			return 0.0;
+		}
+
+		let mut success = 0.0;
+		let mut lifo = vec![(0, 1.0)];
+		while let Some((id, p)) = lifo.pop() {
+			let node = &self.nodes[id];
+			if node.children.is_empty() {
+				if node.reaches_goal {
+					success += p;
+				}
+				continue;
+			}
+
+			let probabilities: Vec<f64> = node.children.iter()
+				.map(|&child_id| self.outcome_probability(id, child_id))
+				.collect();
+			let sum: f64 = probabilities.iter().sum();
+			if sum > 0.0 {
+				lifo.extend(node.children.iter().zip(probabilities).map(|(&child_id, q)| (child_id, p * q / sum)));
+			}
+		}
+
+		success
+	}
+
+	/// mean and variance of the length (norm2) of the branch followed in each world, weighted by the root belief state
+	/// at an observation, a world follows the first outcome still possible in it
+	pub fn length_stats(&self) -> (f64, f64) {
+		if self.nodes.is_empty() {
+			return (0.0, 0.0);
+		}
+
+		let mut lengths = Vec::new();
+		for (world, &p) in self.nodes[0].belief_state.iter().enumerate() {
+			if p == 0.0 {
+				continue;
+			}
+
+			let mut id = 0;
+			let mut length = 0.0;
+			while let Some(&child_id) = self.nodes[id].children.iter().find(|&&child_id| self.nodes[child_id].belief_state[world] > 0.0) {
+				length += norm2(&self.nodes[id].state, &self.nodes[child_id].state);
+				id = child_id;
+			}
+
+			lengths.push((p, length));
+		}
+
+		let mass: f64 = lengths.iter().map(|(p, _)| p).sum();
+		let mean = lengths.iter().map(|(p, length)| p * length).sum::<f64>() / mass;
+		let variance = lengths.iter().map(|(p, length)| p * (length - mean).powi(2)).sum::<f64>() / mass;
+
+		(mean, variance)
+	}
+
+	/// waypoints to follow until the next observation (or the goal), keyed by the belief state at the start of the segment
+	/// the segments start at the root and at each outcome of the observations
+	pub fn branch_segments(&self) -> Vec<(BeliefState, Vec<[f64; N]>)> {
+		let mut segments = Vec::new();
+
+		if self.nodes.is_empty() {
+			return segments;
+		}
+
+		let mut lifo = vec![0];
+		while let Some(first_id) = lifo.pop() {
+			let mut waypoints = vec![self.nodes[first_id].state];
+			let mut id = first_id;
+			while self.nodes[id].children.len() == 1 {
+				id = self.nodes[id].children[0];
+				waypoints.push(self.nodes[id].state);
+			}
+
+			segments.push((self.nodes[first_id].belief_state.clone(), waypoints));
+			lifo.extend(&self.nodes[id].children);
+		}
+
+		segments
+	}
+
+	/// policy reduced to its root, observation branchings and leafs
+	pub fn to_decision_tree(&self) -> DecisionTree<N> {
+		let mut tree = DecisionTree{nodes: Vec::new()};
+
+		if self.nodes.is_empty() {
+			return tree;
+		}
+
+		let add_node = |tree: &mut DecisionTree<N>, policy_node_id: usize| {
+			let node = &self.nodes[policy_node_id];
+			tree.nodes.push(DecisionNode{
+				policy_node_id,
+				state: node.state,
+				belief_state: node.belief_state.clone(),
+				branches: Vec::new(),
+				goal: if node.children.is_empty() { Some(node.state) } else { None },
+			});
+			tree.nodes.len() - 1
+		};
+
+		let mut lifo = vec![add_node(&mut tree, 0)];
+
+		while let Some(decision_node_id) = lifo.pop() {
+			let parent_id = tree.nodes[decision_node_id].policy_node_id;
+			let parent = &self.nodes[parent_id];
+
+			for &child_id in &parent.children {
+				let child = &self.nodes[child_id];
+				let probability = self.outcome_probability(parent_id, child_id);
+
+				// skip the action nodes in between
+				let mut next_id = child_id;
+				while self.nodes[next_id].children.len() == 1 {
+					next_id = self.nodes[next_id].children[0];
+				}
+
+				let child_index = add_node(&mut tree, next_id);
+				tree.nodes[decision_node_id].branches.push(DecisionBranch{child_index, belief_state: child.belief_state.clone(), probability});
+				lifo.push(child_index);
+			}
+		}
+
+		tree
+	}
+
+	/// same policy with sparse belief states
+	pub fn to_sparse(&self) -> Policy<N, SparseBelief> {
+		self.map_belief_states(|belief_state| SparseBelief::from(belief_state))
+	}
+}
+
+impl<const N: usize> Policy<N, SparseBelief> {
+	pub fn to_dense(&self) -> Policy<N> {
+		self.map_belief_states(|sparse_belief| BeliefState::from(sparse_belief))
+	}
+}
+
+impl<const N: usize, B> Policy<N, B> {
+	fn map_belief_states<C>(&self, f: impl Fn(&B) -> C) -> Policy<N, C> {
+		let nodes = self.nodes.iter()
+			.map(|node| PolicyNode{
+				state: node.state,
+				belief_state: f(&node.belief_state),
+				parent: node.parent,
+				children: node.children.clone(),
+				cost_to_goal: node.cost_to_goal,
+				reaches_goal: node.reaches_goal,
+				outcome_probability: node.outcome_probability,
+			})
+			.collect();
+
+		Policy{nodes, leafs: self.leafs.clone()}
+	}
+}
+
+/// belief state keeping only the worlds of non-zero probability, for problems with many worlds
+#[derive(Clone, Debug, PartialEq)]
+pub struct SparseBelief {
+	pub entries: Vec<(usize, f64)>, // (world, probability), by increasing world
+	pub n_worlds: usize,
+}
+
+impl SparseBelief {
+	pub fn probability(&self, world: usize) -> f64 {
+		self.entries.binary_search_by_key(&world, |&(w, _)| w)
+			.map_or(0.0, |index| self.entries[index].1)
+	}
+}
+
+impl From<&BeliefState> for SparseBelief {
+	fn from(belief_state: &BeliefState) -> Self {
+		let entries = belief_state.iter().enumerate()
+			.filter(|(_, &p)| p != 0.0)
+			.map(|(world, &p)| (world, p))
+			.collect();
+
+		Self{entries, n_worlds: belief_state.len()}
+	}
+}
+
+impl From<&SparseBelief> for BeliefState {
+	fn from(sparse_belief: &SparseBelief) -> Self {
+		let mut belief_state = vec![0.0; sparse_belief.n_worlds];
+		for &(world, p) in &sparse_belief.entries {
+			belief_state[world] = p;
+		}
+		belief_state
+	}
+}
+
+pub struct DecisionBranch {
+	pub child_index: usize,
+	pub belief_state: BeliefState,
+	pub probability: f64,
+}
+
+pub struct DecisionNode<const N: usize> {
+	pub policy_node_id: usize,
+	pub state: [f64; N],
+	pub belief_state: BeliefState,
+	pub branches: Vec<DecisionBranch>,
+	pub goal: Option<[f64; N]>, // leafs only
+}
+
+pub struct DecisionTree<const N: usize> {
+	pub nodes: Vec<DecisionNode<N>>, // root first
 }
 
 pub fn norm1<const N: usize>(a: &[f64; N], b: &[f64; N]) -> f64 {
@@ -97,8 +546,50 @@ pub fn norm2<const N: usize>(a: &[f64; N], b: &[f64; N]) -> f64 {
 	d2.sqrt()
 }
 
+pub fn norm_inf<const N: usize>(a: &[f64; N], b: &[f64; N]) -> f64 {
+	izip!(a.iter(), b.iter()).fold(0.0, |d, (xa, xb)| f64::max(d, (xb - xa).abs()))
+}
+
+/// metric of the planners: steps, connection radii and default costs all use it (see PRMFuncs::norm and RRTFuncs::norm)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Norm {
+	L1,
+	L2,
+	LInf, // Chebyshev, e.g. for grid-like problems
+}
+
+impl Norm {
+	pub fn distance<const N: usize>(&self, a: &[f64; N], b: &[f64; N]) -> f64 {
+		match self {
+			Norm::L1 => norm1(a, b),
+			Norm::L2 => norm2(a, b),
+			Norm::LInf => norm_inf(a, b),
+		}
+	}
+
+	/// same as steer, the step is measured with this norm
+	pub fn steer<const N: usize>(&self, from: &[f64; N], to: &mut [f64; N], max_step: f64) {
+		let step = self.distance(from, to);
+
+		if step > max_step {
+			let lambda = max_step / step;
+			for i in 0..N {
+				to[i] = from[i] + (to[i] - from[i]) * lambda;
+			}
+		}
+	}
+}
+
+/// the step is measured with norm2, as the neighbor radius and the costs by default (see Norm::steer for the other metrics)
 pub fn steer<const N: usize>(from: &[f64;N], to: &mut [f64;N], max_step: f64) {
-	let step = norm1(from, &to);
+	steer_scaled(from, to, max_step, &[1.0; N]);
+}
+
+/// the max_step constraint is applied (with norm2) in the normalized space where each dimension is multiplied by its scale
+pub fn steer_scaled<const N: usize>(from: &[f64;N], to: &mut [f64;N], max_step: f64, scale: &[f64; N]) {
+	let step = izip!(from.iter(), to.iter(), scale.iter())
+		.fold(0.0, |d, (xa, xb, s)| d + ((xb - xa) * s).powi(2))
+		.sqrt();
 
 	if step > max_step {
 		let lambda = max_step / step;
@@ -108,17 +599,159 @@ pub fn steer<const N: usize>(from: &[f64;N], to: &mut [f64;N], max_step: f64) {
 	}
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DimKind {
+	Linear,
+	Angular, // radians, wraps around
+	Ignored, // e.g. flags, doesn't contribute to the distance
+}
+
+/// norm2 and steer for the states whose dimensions are of different kinds, e.g. [x, y, theta, gripper]
+pub struct GroupedMetric<const N: usize> {
+	pub kinds: [DimKind; N],
+}
+
+impl<const N: usize> GroupedMetric<N> {
+	pub fn new(kinds: [DimKind; N]) -> Self {
+		Self{ kinds }
+	}
+
+	// signed difference from a to b along the dimension, the shortest way around for the angles
+	fn delta(&self, dim: usize, a: f64, b: f64) -> f64 {
+		match self.kinds[dim] {
+			DimKind::Linear => b - a,
+			DimKind::Angular => {
+				let d = (b - a).rem_euclid(2.0 * std::f64::consts::PI);
+				if d > std::f64::consts::PI { d - 2.0 * std::f64::consts::PI } else { d }
+			},
+			DimKind::Ignored => 0.0,
+		}
+	}
+
+	pub fn distance(&self, a: &[f64; N], b: &[f64; N]) -> f64 {
+		(0..N).map(|dim| self.delta(dim, a[dim], b[dim]).powi(2)).sum::<f64>().sqrt()
+	}
+
+	/// same as steer with this distance, the ignored dimensions take the value of to, the angles stay in [-pi, pi]
+	pub fn steer(&self, from: &[f64; N], to: &mut [f64; N], max_step: f64) {
+		let step = self.distance(from, to);
+
+		if step > max_step {
+			let lambda = max_step / step;
+			for dim in 0..N {
+				match self.kinds[dim] {
+					DimKind::Linear => to[dim] = from[dim] + (to[dim] - from[dim]) * lambda,
+					DimKind::Angular => {
+						let angle = from[dim] + self.delta(dim, from[dim], to[dim]) * lambda;
+						to[dim] = (angle + std::f64::consts::PI).rem_euclid(2.0 * std::f64::consts::PI) - std::f64::consts::PI;
+					},
+					DimKind::Ignored => {},
+				}
+			}
+		}
+	}
+}
+
+/// goal valid in all worlds inside the ball
+pub fn ball_goal<const N: usize>(center: [f64; N], radius: f64, n_worlds: usize) -> impl Fn(&[f64; N]) -> WorldMask {
+	move |state: &[f64; N]| bitvec![if norm2(state, &center) < radius { 1 } else { 0 }; n_worlds]
+}
+
+/// goal valid in all worlds inside the box [lo, hi]
+pub fn box_goal<const N: usize>(lo: [f64; N], hi: [f64; N], n_worlds: usize) -> impl Fn(&[f64; N]) -> WorldMask {
+	move |state: &[f64; N]| {
+		let inside = izip!(state.iter(), lo.iter(), hi.iter()).all(|(x, l, h)| l <= x && x <= h);
+		bitvec![if inside { 1 } else { 0 }; n_worlds]
+	}
+}
+
 pub fn pairwise_iter<T>(v: &[T]) -> Zip<Iter<T>, Iter<T>> {
 	v[0..v.len()-1].iter().zip(&v[1..])
 }
 
+/// costs over three consecutive states (e.g. smoothness), the pairwise cost evaluators can't express them
+pub trait PathCost<const N: usize> {
+	fn triple_cost(&self, prev: &[f64; N], cur: &[f64; N], next: &[f64; N]) -> f64;
+
+	/// length plus the triple costs, the repeated waypoints (observations) are ignored
+	fn path_cost(&self, path: &[[f64; N]]) -> f64 {
+		let mut path = path.to_vec();
+		path.dedup();
+
+		let length: f64 = pairwise_iter(&path).map(|(a, b)| norm2(a, b)).sum();
+		let triple_costs: f64 = path.windows(3).map(|w| self.triple_cost(&w[0], &w[1], &w[2])).sum();
+
+		length + triple_costs
+	}
+}
+
+/// penalizes the heading changes, turn_weight per radian
+pub struct SmoothnessCost {
+	pub turn_weight: f64,
+}
+
+impl<const N: usize> PathCost<N> for SmoothnessCost {
+	fn triple_cost(&self, prev: &[f64; N], cur: &[f64; N], next: &[f64; N]) -> f64 {
+		let (l1, l2) = (norm2(prev, cur), norm2(cur, next));
+		if l1 == 0.0 || l2 == 0.0 {
+			return 0.0;
+		}
+
+		let dot: f64 = izip!(prev.iter(), cur.iter(), next.iter())
+			.map(|(p, c, n)| (c - p) * (n - c))
+			.sum();
+
+		self.turn_weight * (dot / (l1 * l2)).max(-1.0).min(1.0).acos()
+	}
+}
+
+/// inserts interpolated waypoints so that consecutive waypoints are no farther than spacing (original vertices are kept)
+pub fn densify_path<const N: usize>(path: &[[f64; N]], spacing: f64) -> Vec<[f64; N]> {
+	assert!(spacing > 0.0, "spacing should be strictly positive");
+
+	if path.is_empty() {
+		return vec![];
+	}
+
+	let mut dense_path = vec![path[0]];
+
+	for (a, b) in pairwise_iter(path) {
+		dense_path.extend(sample_segment(a, b, spacing).skip(1));
+	}
+
+	dense_path
+}
+
+/// evenly spaced points from a to b (both included), consecutive points are no farther than step
+/// e.g. to check the transitions of analytic maps at a given resolution
+pub fn sample_segment<const N: usize>(a: &[f64; N], b: &[f64; N], step: f64) -> impl Iterator<Item = [f64; N]> {
+	assert!(step > 0.0, "step should be strictly positive");
+
+	let (a, b) = (*a, *b);
+	let n_steps = ((norm2(&a, &b) / step).ceil() as usize).max(1); // zero-length segments give 1 step
+
+	(0..=n_steps).map(move |k| {
+		if k == n_steps {
+			return b;
+		}
+
+		let lambda = k as f64 / n_steps as f64;
+		let mut s = a;
+		for i in 0..N {
+			s[i] = a[i] + (b[i] - a[i]) * lambda;
+		}
+		s
+	})
+}
+
 pub struct Priority{
 	pub prio: f64
 }
 
 impl Ord for Priority {
     fn cmp(&self, other: &Self) -> Ordering {
-        if self.prio < other.prio { Ordering::Greater } else { Ordering::Less }
+        // equal priorities compare as equal so that a secondary key can break the ties, see conditional_weighted_astar
+        if self.prio < other.prio { Ordering::Greater } else if self.prio > other.prio { Ordering::Less } else { Ordering::Equal }
     }
 }
 
@@ -147,9 +780,53 @@ pub fn is_compatible(belief_state: &BeliefState, validity: &WorldMask) -> bool {
 	true
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BeliefError {
+	InvalidSum(f64), // sum of the belief state probabilities
+}
+
+pub const BELIEF_STATE_SUM_TOLERANCE: f64 = 0.000001;
+pub const BELIEF_STATE_EQUALITY_TOLERANCE: f64 = 0.000000001;
+
+/// beliefs computed by different floating point operations (e.g. 1/3 summed back) are equal up to eps
+pub fn beliefs_equal(a: &BeliefState, b: &BeliefState, eps: f64) -> bool {
+	a.len() == b.len() && a.iter().zip(b).all(|(p, q)| (p - q).abs() <= eps)
+}
+
 #[allow(clippy::style)]
-pub fn assert_belief_state_validity(belief_state: &BeliefState) {
-	assert!((belief_state.iter().fold(0.0, |s, p| p + s) - 1.0).abs() < 0.000001);
+pub fn assert_belief_state_validity(belief_state: &BeliefState) -> Result<(), BeliefError> {
+	let sum = belief_state.iter().fold(0.0, |s, p| p + s);
+
+	if (sum - 1.0).abs() < BELIEF_STATE_SUM_TOLERANCE {
+		Ok(())
+	} else {
+		Err(BeliefError::InvalidSum(sum))
+	}
+}
+
+#[allow(clippy::style)]
+pub fn normalize(belief_state: &mut BeliefState) {
+	let sum = belief_state.iter().fold(0.0, |s, p| p + s);
+
+	for p in belief_state {
+		*p /= sum;
+	}
+}
+
+/// draws a world with the belief state as categorical distribution, the worlds of probability 0 are never drawn
+pub fn sample_world(belief_state: &BeliefState, sampler: &mut DiscreteSampler) -> usize {
+	let r = sampler.sample_unit() * belief_state.iter().sum::<f64>();
+
+	let mut cumulated = 0.0;
+	for (world, &p) in belief_state.iter().enumerate() {
+		cumulated += p;
+		if r < cumulated {
+			return world;
+		}
+	}
+
+	// rounding errors on the last world
+	belief_state.iter().rposition(|&p| p > 0.0).expect("belief state without possible world")
 }
 
 pub fn contains(wm1: &WorldMask, wm2: &WorldMask) -> bool {
@@ -167,6 +844,264 @@ mod tests {
 
 use super::*;
 
+#[test]
+fn test_belief_state_validity() {
+	assert_eq!(assert_belief_state_validity(&vec![0.25, 0.75]), Ok(()));
+	assert_eq!(assert_belief_state_validity(&vec![0.25, 0.25]), Err(BeliefError::InvalidSum(0.5)));
+}
+
+#[test]
+fn test_normalize_belief_state() {
+	let mut belief_state = vec![0.25, 0.25, 0.5005];
+	assert!(assert_belief_state_validity(&belief_state).is_err());
+
+	normalize(&mut belief_state);
+	assert_eq!(assert_belief_state_validity(&belief_state), Ok(()));
+	assert_eq!(belief_state[0], belief_state[1]);
+}
+
+fn create_policy() -> Policy<2> {
+	let mut policy: Policy<2> = Policy{nodes: Vec::new(), leafs: Vec::new()};
+	policy.add_node(&[0.0, 0.0], &vec![0.5, 0.5], false, false); // 0
+	policy.add_node(&[1.0, 0.0], &vec![0.5, 0.5], false, false); // 1, observation
+	policy.add_node(&[1.0, 0.0], &vec![1.0, 0.0], false, false); // 2
+	policy.add_node(&[1.0, 1.0], &vec![1.0, 0.0], true, true);   // 3
+	policy.add_node(&[1.0, 0.0], &vec![0.0, 1.0], false, false); // 4
+	policy.add_node(&[4.0, 4.0], &vec![0.0, 1.0], true, true);   // 5
+	policy.add_edge(0, 1);
+	policy.add_edge(1, 2);
+	policy.add_edge(2, 3);
+	policy.add_edge(1, 4);
+	policy.add_edge(4, 5);
+	policy
+}
+
+#[test]
+fn test_success_probability() {
+	assert_eq!(create_policy().success_probability(), 1.0);
+
+	// the second world ends in a dead-end
+	let mut policy: Policy<2> = Policy{nodes: Vec::new(), leafs: Vec::new()};
+	policy.add_node(&[0.0, 0.0], &vec![0.3, 0.7], false, false); // 0
+	policy.add_node(&[1.0, 0.0], &vec![0.3, 0.7], false, false); // 1, observation
+	policy.add_node(&[1.0, 0.0], &vec![1.0, 0.0], false, false); // 2
+	policy.add_node(&[1.0, 1.0], &vec![1.0, 0.0], true, true);   // 3
+	policy.add_node(&[1.0, 0.0], &vec![0.0, 1.0], true, false);  // 4, dead-end
+	policy.add_edge(0, 1);
+	policy.add_edge(1, 2);
+	policy.add_edge(2, 3);
+	policy.add_edge(1, 4);
+
+	assert!(policy.nodes[3].reaches_goal && !policy.nodes[4].reaches_goal);
+	assert!((policy.success_probability() - 0.3).abs() < 1e-9);
+}
+
+#[test]
+fn test_sample_world() {
+	let mut sampler = DiscreteSampler::new_with_seed(0);
+	let n = 10000;
+
+	let mut counts = [0; 2];
+	for _ in 0..n {
+		counts[sample_world(&vec![0.4, 0.6], &mut sampler)] += 1;
+	}
+
+	assert!((counts[0] as f64 / n as f64 - 0.4).abs() < 0.02);
+	assert!((counts[1] as f64 / n as f64 - 0.6).abs() < 0.02);
+
+	// impossible worlds are never drawn
+	assert!((0..100).all(|_| sample_world(&vec![0.0, 1.0, 0.0], &mut sampler) == 1));
+}
+
+#[test]
+fn test_validate_policy() {
+	assert_eq!(create_policy().validate(), Ok(()));
+	assert_eq!(Policy::<2>{nodes: Vec::new(), leafs: Vec::new()}.validate(), Err(PolicyError::Empty));
+
+	// back edge to the root
+	let mut cyclic_policy = create_policy();
+	cyclic_policy.add_edge(4, 0);
+	assert_eq!(cyclic_policy.validate(), Err(PolicyError::Cycle(0)));
+
+	// 2 -> 1 without re-parenting 1
+	let mut cyclic_policy = create_policy();
+	cyclic_policy.nodes[2].children.push(1);
+	assert_eq!(cyclic_policy.validate(), Err(PolicyError::InconsistentParent(1)));
+
+	// cycle detached from the root
+	let mut cyclic_policy = create_policy();
+	let a = cyclic_policy.add_node(&[5.0, 5.0], &vec![1.0, 0.0], false, false);
+	let b = cyclic_policy.add_node(&[6.0, 5.0], &vec![1.0, 0.0], false, false);
+	cyclic_policy.add_edge(a, b);
+	cyclic_policy.add_edge(b, a);
+	assert_eq!(cyclic_policy.validate(), Err(PolicyError::Unreachable(a)));
+
+	let mut policy = create_policy();
+	let c = policy.add_node(&[1.0, 2.0], &vec![1.0, 0.0], true, true);
+	policy.add_edge(3, c);
+	assert_eq!(policy.validate(), Err(PolicyError::LeafWithChildren(3)));
+}
+
+#[test]
+fn test_smoothness_cost() {
+	let cost = SmoothnessCost{ turn_weight: 0.5 };
+
+	let straight = [[0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [3.0, 0.0], [4.0, 0.0]];
+	let zig_zag = [[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 2.0], [2.0, 2.0]];
+
+	assert_eq!(cost.path_cost(&straight), 4.0);
+	assert!(cost.path_cost(&straight) < cost.path_cost(&zig_zag));
+	assert!((cost.path_cost(&zig_zag) - (4.0 + 3.0 * 0.5 * std::f64::consts::FRAC_PI_2)).abs() < 0.000001);
+
+	// u-turn, and observation waypoints repeated
+	assert!((cost.triple_cost(&[0.0, 0.0], &[1.0, 0.0], &[0.0, 0.0]) - 0.5 * std::f64::consts::PI).abs() < 0.000001);
+	assert_eq!(cost.path_cost(&[[0.0, 0.0], [1.0, 0.0], [1.0, 0.0], [2.0, 0.0]]), 2.0);
+}
+
+#[test]
+fn test_densify_path() {
+	let path = vec![[0.0, 0.0], [1.0, 0.0], [1.0, 0.35]];
+	let dense_path = densify_path(&path, 0.1);
+
+	assert_eq!(dense_path.first(), path.first());
+	assert_eq!(dense_path.last(), path.last());
+	assert_eq!(dense_path.len(), 15);
+
+	for (a, b) in pairwise_iter(&dense_path) {
+		assert!(norm2(a, b) <= 0.1 + 0.000001);
+	}
+
+	for vertex in &path {
+		assert!(dense_path.contains(vertex));
+	}
+
+	// zero-length segment
+	assert_eq!(densify_path(&[[0.5, 0.5], [0.5, 0.5]], 0.1), vec![[0.5, 0.5], [0.5, 0.5]]);
+}
+
+#[test]
+fn test_sample_segment() {
+	let (a, b) = ([0.0, 0.0, 0.0], [1.0, 0.5, -0.25]);
+	let points: Vec<[f64; 3]> = sample_segment(&a, &b, 0.1).collect();
+
+	assert_eq!(points.first(), Some(&a));
+	assert_eq!(points.last(), Some(&b));
+	assert_eq!(points.len(), 13); // length 1.146
+
+	let spacing = norm2(&points[0], &points[1]);
+	for (p, q) in pairwise_iter(&points) {
+		assert!(norm2(p, q) <= 0.1);
+		assert!((norm2(p, q) - spacing).abs() < 0.000001);
+	}
+
+	// coarser step
+	assert_eq!(sample_segment(&a, &b, 10.0).collect::<Vec<_>>(), vec![a, b]);
+}
+
+#[test]
+fn test_grouped_metric() {
+	use std::f64::consts::PI;
+	let metric = GroupedMetric::new([DimKind::Linear, DimKind::Linear, DimKind::Angular, DimKind::Ignored]);
+
+	// ignored dimension
+	assert_eq!(metric.distance(&[0.0, 0.0, 0.0, 0.0], &[3.0, 4.0, 0.0, 1.0]), 5.0);
+	assert_eq!(metric.distance(&[1.0, 1.0, 0.5, 0.0], &[1.0, 1.0, 0.5, 1.0]), 0.0);
+
+	// angular dimension wraps
+	assert!((metric.distance(&[0.0, 0.0, PI - 0.05, 0.0], &[0.0, 0.0, -PI + 0.05, 0.0]) - 0.1).abs() < 0.000001);
+	assert!((metric.distance(&[0.0, 0.0, 0.0, 0.0], &[0.0, 0.0, 2.0 * PI, 0.0])).abs() < 0.000001);
+
+	// steering goes through pi, the flag is set
+	let from = [0.0, 0.0, PI - 0.05, 0.0];
+	let mut to = [0.0, 0.0, -PI + 0.05, 1.0];
+	metric.steer(&from, &mut to, 0.08);
+	assert!((to[2] - (-PI + 0.03)).abs() < 0.000001);
+	assert_eq!(to[3], 1.0);
+	assert!((metric.distance(&from, &to) - 0.08).abs() < 0.000001);
+
+	let mut to = [10.0, 0.0, 0.0, 0.0];
+	metric.steer(&[0.0, 0.0, 0.0, 0.0], &mut to, 1.0);
+	assert_eq!(to, [1.0, 0.0, 0.0, 0.0]);
+}
+
+#[test]
+fn test_steer_is_euclidean() {
+	let from = [0.0, 0.0];
+	for &target in &[[1.0, 1.0], [-3.0, 0.5], [0.2, -5.0]] {
+		let mut to = target;
+		steer(&from, &mut to, 0.1);
+		assert!(norm2(&from, &to) <= 0.1 + 0.000001);
+		assert!((norm2(&from, &to) - 0.1).abs() < 0.000001); // not shorter along the diagonals
+	}
+
+	// short steps are unchanged
+	let mut to = [0.05, 0.05];
+	steer(&from, &mut to, 0.1);
+	assert_eq!(to, [0.05, 0.05]);
+}
+
+#[test]
+fn test_norm_inf() {
+	assert_eq!(norm_inf(&[0.0, 0.0], &[1.0, -3.0]), 3.0);
+	assert_eq!(norm_inf(&[1.0, 2.0, 3.0], &[1.5, 2.0, 2.0]), 1.0);
+	assert_eq!(norm_inf(&[1.0, 2.0], &[1.0, 2.0]), 0.0);
+
+	assert_eq!(Norm::L1.distance(&[0.0, 0.0], &[1.0, -3.0]), 4.0);
+	assert_eq!(Norm::L2.distance(&[0.0, 0.0], &[3.0, 4.0]), 5.0);
+
+	// the diagonal steps go further than with norm2
+	let mut to = [1.0, 1.0];
+	Norm::LInf.steer(&[0.0, 0.0], &mut to, 0.1);
+	assert!((to[0] - 0.1).abs() < 1e-9 && (to[1] - 0.1).abs() < 1e-9);
+}
+
+#[test]
+fn test_steer_scaled() {
+	let mut to = [0.0, 10.0];
+	steer(&[0.0, 0.0], &mut to, 0.1);
+	assert_eq!(to, [0.0, 0.1]);
+
+	let mut to = [0.0, 10.0];
+	steer_scaled(&[0.0, 0.0], &mut to, 0.1, &[1.0, 0.01]);
+	assert_eq!(to, [0.0, 10.0]); // second dimension moves further per step
+
+	let mut to = [10.0, 0.0];
+	steer_scaled(&[0.0, 0.0], &mut to, 0.1, &[1.0, 0.01]);
+	assert_eq!(to, [0.1, 0.0]);
+}
+
+#[test]
+fn test_timed_trajectory() {
+	let policy = create_policy();
+
+	assert!(policy.to_timed_trajectory(0.0).is_err());
+
+	let trajectories = policy.to_timed_trajectory(0.5).unwrap();
+	assert_eq!(trajectories.len(), 2);
+
+	for (leaf, trajectory) in trajectories.iter().enumerate() {
+		for ((t1, _), (t2, _)) in pairwise_iter(trajectory) {
+			assert!(t1 < t2);
+		}
+
+		let length = pairwise_iter(&policy.path_to_leaf(leaf)).fold(0.0, |l, (a, b)| l + norm2(a, b));
+		assert_eq!(trajectory.last().unwrap().0, length / 0.5);
+	}
+
+	assert_eq!(policy.timed_path_to_leaf(1, 0.5).unwrap(), vec![(0.0, [0.0, 0.0]), (2.0, [1.0, 0.0]), (12.0, [4.0, 4.0])]);
+}
+
+#[test]
+fn test_goal_regions() {
+	let goal = ball_goal([0.5, 0.5], 0.1, 2);
+	assert_eq!(goal(&[0.55, 0.5]), bitvec![1, 1]);
+	assert_eq!(goal(&[0.65, 0.5]), bitvec![0, 0]);
+
+	let goal = box_goal([0.0, 0.0], [1.0, 0.5], 2);
+	assert_eq!(goal(&[0.9, 0.1]), bitvec![1, 1]);
+	assert_eq!(goal(&[0.9, 0.6]), bitvec![0, 0]);
+}
+
 #[test]
 fn test_wm_contains() {
 	assert!(contains(&bitvec![1,1], &bitvec![1,1]));
@@ -178,4 +1113,30 @@ fn test_wm_contains() {
 	assert!(!contains(&bitvec![1,0], &bitvec![0,1]));
 	assert!(!contains(&bitvec![0,0], &bitvec![0,1]));
 }
+
+#[test]
+fn test_sparse_belief() {
+	let belief_state = vec![0.0, 0.25, 0.0, 0.0, 0.75];
+	let sparse_belief = SparseBelief::from(&belief_state);
+	assert_eq!(sparse_belief.entries, vec![(1, 0.25), (4, 0.75)]);
+	assert_eq!(sparse_belief.probability(4), 0.75);
+	assert_eq!(sparse_belief.probability(2), 0.0);
+	assert_eq!(BeliefState::from(&sparse_belief), belief_state);
+
+	// policy built directly with sparse belief states
+	let mut policy: Policy<2, SparseBelief> = Policy{nodes: Vec::new(), leafs: Vec::new()};
+	policy.add_node(&[0.0, 0.0], &SparseBelief::from(&vec![0.5, 0.5]), false, false);
+	policy.add_node(&[1.0, 1.0], &SparseBelief::from(&vec![0.5, 0.5]), true, true);
+	policy.add_edge(0, 1);
+	assert_eq!(policy.validate(), Ok(()));
+	assert_eq!(policy.path_to_leaf(0), vec![[0.0, 0.0], [1.0, 1.0]]);
+	assert_eq!(policy.to_dense().success_probability(), 1.0);
+
+	// round trip of a dense policy
+	let policy = create_policy();
+	let round_trip = policy.to_sparse().to_dense();
+	assert!(policy.nodes.iter().zip(&round_trip.nodes).all(|(a, b)| a.belief_state == b.belief_state && a.children == b.children));
+	assert_eq!(round_trip.leafs, policy.leafs);
+	assert_eq!(round_trip.success_probability(), policy.success_probability());
+}
 }
\ No newline at end of file
@@ -4,6 +4,7 @@
 pub mod common;
 pub mod sample_space;
 pub mod map_io;
+pub mod analytic_map;
 pub mod nearest_neighbor;
 pub mod rrt;
 pub mod prm;
@@ -11,3 +12,6 @@ pub mod prm_graph;
 pub mod prm_reachability;
 pub mod belief_graph;
 pub mod qmdp_policy_extractor;
+
+#[cfg(test)]
+mod test_utils;
@@ -142,7 +142,7 @@ fn main() {
 
 	prm.grow_graph(&[0.55, -0.8], goal, 0.075, 5.0, 2000, 100000).expect("graph not grown up to solution");
 	prm.print_summary();
-	let policy = prm.plan_belief_space(&vec![1.0/16.0; 16] ); //&vec![1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+	let policy = prm.plan_belief_space(&vec![1.0/16.0; 16] ).expect("invalid start belief state"); //&vec![1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
 
 	let mut m2 = m.clone();
 	m2.resize(5);
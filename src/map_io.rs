@@ -9,6 +9,7 @@ use std::collections::HashSet;
 extern crate queues;
 use queues::*;
 use bitvec::prelude::*;
+use priority_queue::PriorityQueue;
 use image::Pixel;
 use image::Rgb;
 
@@ -54,6 +55,55 @@ pub mod colors {
 
 use colors::*;
 
+/// soft zone detection: the zone state is reported right with an accuracy going from 1 on the robot
+/// down to 1/2 (no information) at max_range, the reported beliefs are the bayesian updates
+#[derive(Clone, Copy, Debug)]
+pub struct RangeDetectionModel {
+	pub max_range: f64,
+	pub falloff: f64, // exponent of the decrease of the detection probability with the distance
+}
+
+impl RangeDetectionModel {
+	pub fn detection_probability(&self, distance: f64) -> f64 {
+		if distance >= self.max_range {
+			return 0.0;
+		}
+
+		(1.0 - distance / self.max_range).powf(self.falloff)
+	}
+
+	/// beliefs after the report on the zone open in the worlds of zone_mask, with the probability of the report
+	/// a single belief (of probability 1) if nothing can be learnt
+	/// the beliefs keep the support of belief_state: their probabilities are the ones given here (see PRMFuncs::observe_with_probabilities),
+	/// not the mass of belief_state on their support
+	pub fn observe(&self, state: &[f64; 2], zone_position: &[f64; 2], zone_mask: &WorldMask, belief_state: &BeliefState) -> Vec<(BeliefState, f64)> {
+		let accuracy = 0.5 + 0.5 * self.detection_probability(norm2(state, zone_position));
+
+		let mut outcomes: Vec<(BeliefState, f64)> = Vec::new();
+		for reported_open in [true, false] {
+			let mut belief: BeliefState = belief_state.iter().zip(zone_mask.iter())
+				.map(|(&p, open)| p * if *open == reported_open { accuracy } else { 1.0 - accuracy })
+				.collect();
+			let probability: f64 = belief.iter().sum();
+			if probability == 0.0 {
+				continue;
+			}
+			normalize(&mut belief);
+
+			match outcomes.iter_mut().find(|(b, _)| beliefs_equal(b, &belief, BELIEF_STATE_EQUALITY_TOLERANCE)) {
+				Some((_, p)) => *p += probability,
+				None => outcomes.push((belief, probability)),
+			}
+		}
+
+		if outcomes.iter().all(|(b, _)| beliefs_equal(b, belief_state, BELIEF_STATE_EQUALITY_TOLERANCE)) {
+			return vec![(belief_state.clone(), 1.0)];
+		}
+
+		outcomes
+	}
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Belief {
 	Free,
@@ -61,19 +111,35 @@ pub enum Belief {
 	Zone(usize),
 }
 
+/// zone image of one observation source, its zones are numbered after the ones of the previous layers
+#[derive(Clone)]
+struct ZoneLayer {
+	name: String,
+	img: image::GrayImage,
+	first_zone: usize, // map zone index of the layer zone 0
+	n_zones: usize,
+}
+
 #[derive(Clone)]
 pub struct Map
 {
-	img: image::RgbImage,
+	img: image::RgbImage, // drawings
+	occupancy: image::GrayImage, // source of the validity checks, the drawings don't change it
 	low: [f64; 2],
 	//up: [f64; 2], // low + up are enough and make up redundant
 	ppm: f64,
-	zones: Option<image::GrayImage>,
+	zone_layers: Vec<ZoneLayer>,
 	n_zones: usize,
 	n_worlds: usize,
 	zones_to_worlds: Vec<WorldMask>,
 	zone_positions: Vec<[f64;2]>,
-	visibility_distance: f64
+	zone_priors: Vec<f64>, // probability of each zone to be open
+	visibility_distance: f64,
+	observation_radii: Vec<Option<f64>>, // per zone, visibility_distance if None
+	distance_transform: Vec<f32>, // distance to closest obstacle, in pixels
+	clearance_weight: f64,
+	free_above: u8, // pixels strictly above are free
+	obstacle_below: u8, // pixels strictly below are obstacles, in between they are zones or obstacles
 }
 
 // Given N zones, there are 2^N possible worlds
@@ -86,12 +152,118 @@ impl Map {
 		self.img.save(format!("{}.png", filepath)).expect("Couldn't save image");
 	}
 
+	/// the format is given by the extension of filepath (e.g. png, bmp), the map pixels untouched by the drawings
+	/// are rendered as free (white), obstacle (black) or zone (zone color)
+	pub fn save_color(&self, filepath: &str) {
+		let mut img = self.img.clone();
+		for (j, i, pixel) in img.enumerate_pixels_mut() {
+			let p = self.occupancy.get_pixel(j, i)[0];
+			if *pixel != Rgb([p, p, p]) {
+				continue; // drawing
+			}
+
+			*pixel = match self.get_pixel_belief(i, j) {
+				Belief::Free => WHITE,
+				Belief::Obstacle => BLACK,
+				Belief::Zone(zone_id) => color_map(zone_id),
+			};
+		}
+
+		img.save(filepath).expect("Couldn't save image");
+	}
+
 	fn build(img: image::GrayImage, low: [f64; 2], up: [f64; 2])-> Map {
 		let ppm = (img.width() as f64) / (up[0] - low[0]);
 
-		let img = DynamicImage::ImageLuma8(img).to_rgb8();
+		let occupancy = img;
+		let img = DynamicImage::ImageLuma8(occupancy.clone()).to_rgb8();
+		let (free_above, obstacle_below) = (254, 1);
 
-		Map{img, low, /*up,*/ ppm, zones: None, n_zones: 0, n_worlds: 0, zones_to_worlds: Vec::new(), zone_positions: Vec::new(), visibility_distance: 0.0}
+		let mut map = Map{img, occupancy, low, /*up,*/ ppm, zone_layers: Vec::new(), n_zones: 0, n_worlds: 1, zones_to_worlds: Vec::new(), zone_positions: Vec::new(), zone_priors: Vec::new(), visibility_distance: 0.0, observation_radii: Vec::new(),
+			distance_transform: Vec::new(), clearance_weight: 0.0, free_above, obstacle_below};
+		map.compute_distance_transform();
+		map
+	}
+
+	/// for non-binary images, the defaults (254, 1) only accept pure white as free and pure black as obstacle
+	pub fn with_thresholds(mut self, free_above: u8, obstacle_below: u8) -> Self {
+		assert!(obstacle_below <= free_above, "obstacle threshold should be lower than the free threshold");
+
+		self.free_above = free_above;
+		self.obstacle_below = obstacle_below;
+		self.compute_distance_transform();
+		self
+	}
+
+	/// 2-pass chamfer distance transform of the occupancy image, the obstacles are the dark pixels
+	/// cached for clearance_at, the map calls it again when its occupancy changes (thresholds, resize)
+	pub fn compute_distance_transform(&mut self) {
+		let (w, h) = (self.occupancy.width() as usize, self.occupancy.height() as usize);
+		let diagonal = std::f32::consts::SQRT_2;
+
+		let mut dt: Vec<f32> = self.occupancy.pixels()
+			.map(|p| if p[0] < self.obstacle_below { 0.0 } else { f32::INFINITY })
+			.collect();
+
+		// forward pass
+		for i in 0..h {
+			for j in 0..w {
+				let mut d = dt[i*w + j];
+				if i > 0 {
+					d = d.min(dt[(i-1)*w + j] + 1.0);
+					if j > 0 { d = d.min(dt[(i-1)*w + j-1] + diagonal); }
+					if j + 1 < w { d = d.min(dt[(i-1)*w + j+1] + diagonal); }
+				}
+				if j > 0 { d = d.min(dt[i*w + j-1] + 1.0); }
+				dt[i*w + j] = d;
+			}
+		}
+
+		// backward pass
+		for i in (0..h).rev() {
+			for j in (0..w).rev() {
+				let mut d = dt[i*w + j];
+				if i + 1 < h {
+					d = d.min(dt[(i+1)*w + j] + 1.0);
+					if j > 0 { d = d.min(dt[(i+1)*w + j-1] + diagonal); }
+					if j + 1 < w { d = d.min(dt[(i+1)*w + j+1] + diagonal); }
+				}
+				if j + 1 < w { d = d.min(dt[i*w + j+1] + 1.0); }
+				dt[i*w + j] = d;
+			}
+		}
+
+		self.distance_transform = dt;
+	}
+
+	/// distance to the closest obstacle (in meters), 0 outside of the map
+	/// read from the cached distance transform, see compute_distance_transform
+	pub fn clearance_at(&self, xy: &[f64; 2]) -> f64 {
+		match self.world_to_pixel(xy) {
+			Some(ij) => self.distance_transform[(ij[0] * self.img.width() + ij[1]) as usize] as f64 / self.ppm,
+			None => 0.0,
+		}
+	}
+
+	/// integral of the inverse of the clearance along the edge, the clearance is bounded by one pixel to remain finite
+	pub fn clearance_cost(&self, a: &[f64; 2], b: &[f64; 2]) -> f64 {
+		let length = norm2(a, b);
+		let n_samples = ((length * self.ppm).ceil() as usize).max(1);
+		let ds = length / n_samples as f64;
+
+		(0..n_samples)
+			.map(|k| {
+				let lambda = (k as f64 + 0.5) / n_samples as f64;
+				let xy = [a[0] + (b[0] - a[0]) * lambda, a[1] + (b[1] - a[1]) * lambda];
+				ds / self.clearance_at(&xy).max(1.0 / self.ppm)
+			})
+			.sum()
+	}
+
+	/// the PRM cost becomes length + weight * clearance_cost (the default weight of 0 only accounts for the length)
+	/// to be set before growing the PRM graph, which caches the edge costs
+	pub fn set_clearance_weight(&mut self, weight: f64) {
+		self.clearance_weight = weight;
 	}
 
 
@@ -106,84 +278,147 @@ impl Map {
 
 	// zones specific
 	pub fn add_zones(&mut self, filepath : &str, visibility_distance: f64) {
-		self.zones = Some(Self::open_image(filepath));
-
-		self.init_zone_ids();
-		self.init_zone_positions();
+		self.add_zone_layer(filepath, "zones");
 		self.visibility_distance = visibility_distance;
+	}
 
-		// zone -> worlds
-		for i in 0..self.n_zones {
-			self.zones_to_worlds.push(self.zone_index_to_world_mask(i));
-		}
+	/// each layer adds its zones to the world enumeration, the worlds span the zones of all the layers
+	pub fn add_zone_layer(&mut self, filepath: &str, layer_name: &str) {
+		self.add_zone_layer_image(Self::open_image(filepath), layer_name);
 	}
 
-	fn init_zone_ids(&mut self) {
-		let mut max_id = 0;
-		for i in 0..self.zones.as_ref().unwrap().height() {
-			for j in 0..self.zones.as_ref().unwrap().width() {
-				let z = self.get_zone_index(i, j);
-				if let Some(id) = z {
-					if id > max_id {
-						max_id = id;
-					}
-				} 	
-			}
-		}
-		
-		self.n_zones = max_id + 1;
+	fn add_zone_layer_image(&mut self, img: image::GrayImage, layer_name: &str) {
+		assert!(self.zone_layer(layer_name).is_none(), "zone layer already added");
+
+		let n_layer_zones = img.pixels()
+			.filter(|p| p[0] != 255)
+			.map(|p| p[0] as usize + 1)
+			.max()
+			.unwrap_or(0);
+
+		self.zone_layers.push(ZoneLayer{name: layer_name.to_string(), img, first_zone: self.n_zones, n_zones: n_layer_zones});
+
+		self.n_zones += n_layer_zones;
 		self.n_worlds = (2_u32).pow(self.n_zones as u32) as usize;
+		self.zone_priors.resize(self.n_zones, 0.5);
+		self.observation_radii.resize(self.n_zones, None);
+		self.init_zone_positions();
+
+		// zone -> worlds, the masks of the previous layers grow with the number of worlds
+		self.zones_to_worlds = (0..self.n_zones).map(|i| self.zone_index_to_world_mask(i)).collect();
+	}
+
+	/// probability of the zone to be open, 0.5 by default
+	pub fn set_zone_prior(&mut self, zone_index: usize, p_open: f64) {
+		assert!(zone_index < self.n_zones, "zone index out of range");
+		assert!((0.0..=1.0).contains(&p_open), "prior should be a probability");
+		self.zone_priors[zone_index] = p_open;
+	}
+
+	/// prior belief over the worlds, the zones being independent (e.g. as start belief state)
+	pub fn world_prior(&self) -> BeliefState {
+		(0..self.n_worlds)
+			.map(|world| (0..self.n_zones)
+				.map(|zone| if self.zones_to_worlds[zone][world] { self.zone_priors[zone] } else { 1.0 - self.zone_priors[zone] })
+				.product())
+			.collect()
+	}
+
+	/// distance from which the zone is observed (with a line of sight), instead of the visibility distance given to add_zones
+	pub fn set_observation_radius(&mut self, zone_index: usize, radius: f64) {
+		assert!(zone_index < self.n_zones, "zone index out of range");
+		assert!(radius >= 0.0, "observation radius should be positive");
+		self.observation_radii[zone_index] = Some(radius);
+	}
+
+	pub fn observation_radius(&self, zone_index: usize) -> f64 {
+		self.observation_radii[zone_index].unwrap_or(self.visibility_distance)
+	}
+
+	/// map zone indices of the layer, to key the observations per layer
+	pub fn zone_layer(&self, layer_name: &str) -> Option<std::ops::Range<usize>> {
+		self.zone_layers.iter()
+			.find(|layer| layer.name == layer_name)
+			.map(|layer| layer.first_zone..layer.first_zone + layer.n_zones)
 	}
 
     fn init_zone_positions(&mut self) {
 		let mut zone_to_pixels: Vec<Vec<[u32; 2]>> = vec![Vec::new(); self.n_zones];
-		for i in 0..self.zones.as_ref().unwrap().height() {
-			for j in 0..self.zones.as_ref().unwrap().width() {
-				let z = self.get_zone_index(i, j);
-				if let Some(id) = z {
-					zone_to_pixels[id].push([i, j]);
-				}  	
+		for layer in &self.zone_layers {
+			for (j, i, p) in layer.img.enumerate_pixels() {
+				if p[0] != 255 {
+					zone_to_pixels[layer.first_zone + p[0] as usize].push([i, j]);
+				}
 			}
 		}
-		
+
+		self.zone_positions.clear();
 		for pixel_coords in &zone_to_pixels {
 			let sum = pixel_coords.iter().fold( [0, 0], | [sum_i, sum_j], [i, j] | [sum_i+i, sum_j+j] );
 			let ij = [sum[0] / pixel_coords.len() as u32, sum[1] / pixel_coords.len() as u32];
-			self.zone_positions.push(self.to_coordinates(&ij))
+			self.zone_positions.push(self.pixel_to_world(ij))
 		}
 	}
 
+	/// the states outside of the map are obstacles
 	pub fn is_state_valid(&self, xy: &[f64; 2]) -> Belief {
-		let ij = self.to_pixel_coordinates(&*xy);
-		let p = self.img.get_pixel(ij[1], ij[0]);
+		match self.world_to_pixel(xy) {
+			Some(ij) => self.get_pixel_belief(ij[0], ij[1]),
+			None => Belief::Obstacle,
+		}
+	}
 
-		match p[0] {
-			255 => Belief::Free,
-			0 => Belief::Obstacle,
-			_ => Belief::Zone(self.get_zone_index(ij[0], ij[1]).unwrap())
+	/// the occupancy image has precedence over the zone layers: a zone pixel over a free or an obstacle pixel
+	/// is free or an obstacle in all the worlds, only the gray pixels are looked up in the zones
+	/// (is_state_valid, state_validity and the traversal checks all go through this)
+	fn get_pixel_belief(&self, i: u32, j: u32) -> Belief {
+		let p = self.occupancy.get_pixel(j, i)[0];
+
+		if p > self.free_above {
+			Belief::Free
+		} else if p < self.obstacle_below {
+			Belief::Obstacle
+		} else {
+			// gray pixels outside of the zones are conservatively considered as obstacles
+			match self.get_zone_index(i, j) {
+				Some(zone_index) => Belief::Zone(zone_index),
+				None => Belief::Obstacle,
+			}
 		}
 	}
 
-	fn to_pixel_coordinates(&self, xy: &[f64; 2]) -> [u32; 2] {
-		let i: u32 = (((self.img.height() - 1) as f64) - (xy[1] - self.low[1]) * self.ppm) as u32;
-		let j: u32 = ((xy[0] - self.low[0]) * self.ppm) as u32;
+	/// pixel [row, column] containing the point, None outside of the map
+	pub fn world_to_pixel(&self, xy: &[f64; 2]) -> Option<[u32; 2]> {
+		let [i, j] = self.world_to_pixel_unbounded(xy);
+		let in_bounds = i > -1.0 && i < self.img.height() as f64 && j >= 0.0 && j < self.img.width() as f64; // false for NaN
+
+		if in_bounds { Some([i as u32, j as u32]) } else { None }
+	}
+
+	// continuous pixel coordinates, possibly outside of the image (drawing)
+	fn world_to_pixel_unbounded(&self, xy: &[f64; 2]) -> [f64; 2] {
+		let i = ((self.img.height() - 1) as f64) - (xy[1] - self.low[1]) * self.ppm;
+		let j = (xy[0] - self.low[0]) * self.ppm;
 
 		[i, j]
 	}
 
-	fn to_coordinates(&self, ij: &[u32; 2]) -> [f64; 2] {
-		let x: f64 = ij[1] as f64 / self.ppm + self.low[1];
-		let y: f64 = (self.img.height() - 1 - ij[0]) as f64 / self.ppm + self.low[0];
+	/// center of the pixel [row, column], world_to_pixel(pixel_to_world(ij)) == Some(ij)
+	pub fn pixel_to_world(&self, ij: [u32; 2]) -> [f64; 2] {
+		let x: f64 = (ij[1] as f64 + 0.5) / self.ppm + self.low[0];
+		let y: f64 = ((self.img.height() - 1 - ij[0]) as f64 - 0.5) / self.ppm + self.low[1];
 
 		[x, y]
 	}
 
+	// overlapping layers: the zone of the first layer is returned
 	fn get_zone_index(&self, i: u32, j: u32) -> Option<usize> {
-		let p = self.zones.as_ref().expect("Zones missing").get_pixel(j, i);
-		match p[0] {
-			255 => None,
-			p => Some(p as usize),
-		}
+		self.zone_layers.iter().find_map(|layer| {
+			match layer.img.get_pixel(j, i)[0] {
+				255 => None,
+				p => Some(layer.first_zone + p as usize),
+			}
+		})
 	}
 
 	fn zone_index_to_world_mask(&self, zone_index: usize) -> WorldMask {
@@ -207,19 +442,20 @@ impl Map {
 	fn get_traversed_space(&self, a: &[f64; 2], b: &[f64; 2]) -> Belief {
 		let mut traversed_space = Belief::Free;
 
-		let a_ij = self.to_pixel_coordinates(a);
-		let b_ij = self.to_pixel_coordinates(b);
+		// the map is convex, the segment is inside if its extremities are
+		let (a_ij, b_ij) = match (self.world_to_pixel(a), self.world_to_pixel(b)) {
+			(Some(a_ij), Some(b_ij)) => (a_ij, b_ij),
+			_ => return Belief::Obstacle,
+		};
 
 		let a = (a_ij[0] as i32, a_ij[1] as i32);
 		let b = (b_ij[0] as i32, b_ij[1] as i32);
 
 		for (i, j) in line_drawing::Bresenham::new(a, b) {
-			let pixel = self.img.get_pixel(j as u32, i as u32);
-			match pixel[0] {
-				255 => {},
-				0 => return Belief::Obstacle,
-				_ => {	
-					let zone_index = self.get_zone_index(i as u32, j as u32).unwrap();
+			match self.get_pixel_belief(i as u32, j as u32) {
+				Belief::Free => {},
+				Belief::Obstacle => return Belief::Obstacle,
+				Belief::Zone(zone_index) => {	
 					if let Belief::Zone(previous) = traversed_space {
 						assert!(zone_index == previous, "multiple zone traversal not supported");
 					}				
@@ -231,18 +467,30 @@ impl Map {
 		traversed_space
 	}
 
-	#[allow(clippy::style)]
-	fn get_successor_belief_states(&self, belief_state: &BeliefState, zone_id: usize) -> Vec<Vec<f64>> {
-		let mut output_beliefs: Vec<Vec<f64>> = Vec::new();
+	/// worlds in which the segment is free: intersection of the masks of the traversed zones, None if it hits an obstacle
+	/// unlike get_traversed_space, several zones can be traversed
+	fn get_traversed_validity(&self, a: &[f64; 2], b: &[f64; 2]) -> Option<WorldMask> {
+		let (a_ij, b_ij) = (self.world_to_pixel(a)?, self.world_to_pixel(b)?);
 
-		fn normalize(belief: &mut Vec<f64>) {
-			let sum = belief.iter().fold(0.0, |sum, p| sum + p );
+		let a = (a_ij[0] as i32, a_ij[1] as i32);
+		let b = (b_ij[0] as i32, b_ij[1] as i32);
 
-			for p in belief {
-				*p /= sum;
+		let mut validity = bitvec![1; self.n_worlds];
+		for (i, j) in line_drawing::Bresenham::new(a, b) {
+			match self.get_pixel_belief(i as u32, j as u32) {
+				Belief::Free => {},
+				Belief::Obstacle => return None,
+				Belief::Zone(zone_index) => validity &= self.zones_to_worlds[zone_index].iter().map(|w| *w),
 			}
 		}
 
+		Some(validity)
+	}
+
+	#[allow(clippy::style)]
+	fn get_successor_belief_states(&self, belief_state: &BeliefState, zone_id: usize) -> Vec<Vec<f64>> {
+		let mut output_beliefs: Vec<Vec<f64>> = Vec::new();
+
 		let mask = &self.zones_to_worlds[zone_id];
 
 		// assume closed
@@ -274,7 +522,7 @@ impl Map {
 		output_beliefs.push(belief_state.clone());
 
 		for zone_id in 0..self.n_zones {
-			if  norm2(state, &self.zone_positions[zone_id]) < self.visibility_distance {
+			if  norm2(state, &self.zone_positions[zone_id]) < self.observation_radius(zone_id) {
 				let fov_feasability = self.get_traversed_space(&state, &self.zone_positions[zone_id]) != Belief::Obstacle;
 
 				if fov_feasability {
@@ -290,16 +538,46 @@ impl Map {
 		output_beliefs
 	}
 
+	/// soft version of observe_impl: each zone within the range of model (and in sight) is reported according to it
+	/// the outcomes are the beliefs after the reports of all these zones, with their probabilities
+	/// the reported beliefs depend on the distance to the zones, so reachable_belief_states can't enumerate them:
+	/// this is meant for the PRMFuncs::observe_with_probabilities of functions observing from known states
+	pub fn observe_with_model(&self, model: &RangeDetectionModel, state: &[f64; 2], belief_state: &BeliefState) -> Vec<(BeliefState, f64)> {
+		let mut outcomes = vec![(belief_state.clone(), 1.0)];
+
+		for zone_id in 0..self.n_zones {
+			let zone_position = &self.zone_positions[zone_id];
+			if norm2(state, zone_position) >= model.max_range || self.get_traversed_space(state, zone_position) == Belief::Obstacle {
+				continue;
+			}
+
+			let mut reports: Vec<(BeliefState, f64)> = Vec::new();
+			for (belief, p) in &outcomes {
+				for (reported_belief, q) in model.observe(state, zone_position, &self.zones_to_worlds[zone_id], belief) {
+					match reports.iter_mut().find(|(b, _)| beliefs_equal(b, &reported_belief, BELIEF_STATE_EQUALITY_TOLERANCE)) {
+						Some((_, r)) => *r += p * q,
+						None => reports.push((reported_belief, p * q)),
+					}
+				}
+			}
+			outcomes = reports;
+		}
+
+		outcomes
+	}
+
 	// drawing functions
 	pub fn resize(&mut self, factor: u32) {
 		let w = self.img.width() * factor;
 		let h = self.img.height() * factor;
 
 		self.img = image::imageops::resize(&self.img, w, h, image::imageops::FilterType::Nearest);
+		self.occupancy = image::imageops::resize(&self.occupancy, w, h, image::imageops::FilterType::Nearest);
 		self.ppm *= factor as f64;
+		self.compute_distance_transform();
 
-		if let Some(zone_img) = &self.zones {
-			self.zones = Some(image::imageops::resize(zone_img, w, h, image::imageops::FilterType::Nearest));
+		for layer in &mut self.zone_layers {
+			layer.img = image::imageops::resize(&layer.img, w, h, image::imageops::FilterType::Nearest);
 		}
 	}
 
@@ -375,12 +653,24 @@ impl Map {
 		}
 	}
 
+	/// colors the states from blue (lowest cost) to red (highest cost), the infinite costs are skipped (see PRM::cost_field_for_belief)
+	pub fn draw_cost_field(&mut self, cost_field: &[([f64; 2], f64)]) {
+		let finite_costs = cost_field.iter().map(|(_, cost)| *cost).filter(|cost| cost.is_finite());
+		let max_cost = finite_costs.fold(0.0, f64::max);
+
+		for (state, cost) in cost_field.iter().filter(|(_, cost)| cost.is_finite()) {
+			let t = if max_cost > 0.0 { cost / max_cost } else { 0.0 };
+			let color = Rgb([(255.0 * t) as u8, 0, (255.0 * (1.0 - t)) as u8]);
+			self.draw_circle(state, 0.01, color);
+		}
+	}
+
 	fn draw_line(&mut self, a: [f64; 2], b: [f64; 2], color: Rgb<u8>, alpha: f32) {
-		let a_ij = self.to_pixel_coordinates(&a);
-		let b_ij = self.to_pixel_coordinates(&b);
+		let a_ij = self.world_to_pixel_unbounded(&a);
+		let b_ij = self.world_to_pixel_unbounded(&b);
 
-		let a = (a_ij[0] as f32, a_ij[1] as f32);
-		let b = (b_ij[0] as f32, b_ij[1] as f32);
+		let a = (a_ij[0].trunc() as f32, a_ij[1].trunc() as f32);
+		let b = (b_ij[0].trunc() as f32, b_ij[1].trunc() as f32);
 
 		for ((i, j), line_alpha) in line_drawing::XiaolinWu::<f32, i32>::new(a, b) {
 			if 0 <= i && i < self.img.height() as i32 && 0 <= j && j < self.img.width() as i32 {
@@ -414,6 +704,13 @@ impl Map {
 		}
 	}
 
+	/// e.g. the edges of a policy, see PRM::used_prm_edges
+	pub fn draw_graph_edges(&mut self, graph: &PRMGraph<2>, edges: &[(usize, usize)], color: Rgb<u8>) {
+		for &(from, to) in edges {
+			self.draw_line(graph.nodes[from].state, graph.nodes[to].state, color, 1.0);
+		}
+	}
+
 	pub fn draw_graph_from_root(&mut self, graph: &PRMGraph<2>) {
 		self.draw_graph_from_root_impl(graph, &|_|{true})
 	}
@@ -423,7 +720,7 @@ impl Map {
 			panic!("Invalid world id");
 		}
 
-		self.draw_graph_from_root_impl(graph, &|node|{node.validity[world]})
+		self.draw_graph_from_root_impl(graph, &|node|{node.valid_in(world)})
 	}
 
 	pub fn draw_graph_from_root_impl(&mut self, graph: &PRMGraph<2>, validator: &dyn Fn(&PRMNode<2>) -> bool) {
@@ -452,8 +749,10 @@ impl Map {
 	}
 
 	pub fn draw_world(&mut self, world_id:usize) {
-		for i in 0..self.zones.as_ref().unwrap().height() {
-			for j in 0..self.zones.as_ref().unwrap().width() {
+		assert!(!self.zone_layers.is_empty(), "Zones missing");
+
+		for i in 0..self.img.height() {
+			for j in 0..self.img.width() {
 				let z = self.get_zone_index(i, j);
 
 				if let Some(zone_id) = z {
@@ -464,9 +763,34 @@ impl Map {
 		}
 	}
 
+	/// one tile per world with its zones and its part of the graph, tiled in a square grid
+	pub fn save_world_contact_sheet(&self, filepath: &str, graph: &PRMGraph<2>) {
+		self.world_contact_sheet(graph).save(format!("{}.png", filepath)).expect("Couldn't save image");
+	}
+
+	fn world_contact_sheet(&self, graph: &PRMGraph<2>) -> image::RgbImage {
+		let (w, h) = (self.img.width(), self.img.height());
+		let n_cols = (self.n_worlds as f64).sqrt().ceil() as u32;
+		let n_rows = (self.n_worlds as u32 + n_cols - 1) / n_cols;
+
+		let mut sheet = image::RgbImage::from_pixel(n_cols * w, n_rows * h, WHITE);
+		for world in 0..self.n_worlds {
+			let mut tile = self.clone();
+			if !tile.zone_layers.is_empty() {
+				tile.draw_world(world);
+			}
+			tile.draw_graph_for_world(graph, world);
+
+			let (col, row) = (world as u32 % n_cols, world as u32 / n_cols);
+			image::imageops::replace(&mut sheet, &tile.img, col * w, row * h);
+		}
+
+		sheet
+	}
+
 	pub fn draw_zones_observability(&mut self) {
-		for xy in &self.zone_positions.clone() {
-			self.draw_circle(xy, self.visibility_distance, TEAL);
+		for (zone_id, xy) in self.zone_positions.clone().iter().enumerate() {
+			self.draw_circle(xy, self.observation_radius(zone_id), TEAL);
 		}
 	}
 } 
@@ -510,20 +834,24 @@ impl PRMFuncs<2> for Map {
 
 	fn transition_validator(&self, from: &PRMNode<2>, to: &PRMNode<2>) -> Option<WorldMask> {
 		// needed -> benchmark
-		let symbolic_validity = from.validity.iter().zip(&to.validity)
-		.any(|(a, b)| *a && *b);
+		let symbolic_validity = from.shared_validity(to);
 
-		if !symbolic_validity {
+		if !symbolic_validity.any_valid() {
 			return None
 		}
 		//
 		
-		let geometric_validitiy = self.get_traversed_space(&from.state, &to.state);
+		// worlds shared by the extremities and the traversed zones
+		let validity = self.get_traversed_validity(&from.state, &to.state)?.shared_validity(&symbolic_validity);
 
-		match geometric_validitiy {
-			Belief::Zone(zone_index) => {Some(self.zones_to_worlds[zone_index].clone())}, // TODO: improve readability
-			Belief::Free => {Some(bitvec![1; self.n_worlds])},
-			Belief::Obstacle => None
+		if validity.any_valid() { Some(validity) } else { None }
+	}
+
+	fn cost_evaluator(&self, a: &[f64; 2], b: &[f64; 2]) -> f64 {
+		if self.clearance_weight > 0.0 {
+			norm2(a, b) + self.clearance_weight * self.clearance_cost(a, b)
+		} else {
+			norm2(a, b)
 		}
 	}
 
@@ -561,6 +889,86 @@ impl PRMFuncs<2> for Map {
 	}
 }
 
+/// shortest path on the free pixels (8-connected, octile heuristic), baseline for the sampling based planners
+pub fn grid_astar(map: &Map, start: &[f64; 2], goal: &[f64; 2]) -> Option<Vec<[f64; 2]>> {
+	let (w, h) = (map.img.width() as i64, map.img.height() as i64);
+	let is_free = |i: i64, j: i64| 0 <= i && i < h && 0 <= j && j < w && map.get_pixel_belief(i as u32, j as u32) == Belief::Free;
+	let index = |i: i64, j: i64| (i * w + j) as usize;
+
+	let [si, sj] = map.world_to_pixel(start)?;
+	let [gi, gj] = map.world_to_pixel(goal)?;
+	let (si, sj, gi, gj) = (si as i64, sj as i64, gi as i64, gj as i64);
+
+	if !is_free(si, sj) || !is_free(gi, gj) {
+		return None;
+	}
+
+	let octile = |i: i64, j: i64| {
+		let (di, dj) = ((i - gi).abs() as f64, (j - gj).abs() as f64);
+		di.max(dj) + (std::f64::consts::SQRT_2 - 1.0) * di.min(dj)
+	};
+
+	let mut cost = vec![f64::INFINITY; (w * h) as usize];
+	let mut parent: Vec<Option<(i64, i64)>> = vec![None; (w * h) as usize];
+	let mut q = PriorityQueue::new();
+
+	cost[index(si, sj)] = 0.0;
+	q.push((si, sj), Priority{prio: octile(si, sj)});
+
+	while let Some(((i, j), _)) = q.pop() {
+		if (i, j) == (gi, gj) {
+			break;
+		}
+
+		for (di, dj) in &[(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (-1, 1), (1, -1), (1, 1)] {
+			let (ni, nj) = (i + di, j + dj);
+
+			// diagonal moves don't cut corners
+			if !is_free(ni, nj) || !is_free(i + di, j) || !is_free(i, j + dj) {
+				continue;
+			}
+
+			let step = if *di != 0 && *dj != 0 { std::f64::consts::SQRT_2 } else { 1.0 };
+			let alternative = cost[index(i, j)] + step;
+
+			if alternative < cost[index(ni, nj)] {
+				cost[index(ni, nj)] = alternative;
+				parent[index(ni, nj)] = Some((i, j));
+				q.push((ni, nj), Priority{prio: alternative + octile(ni, nj)});
+			}
+		}
+	}
+
+	if cost[index(gi, gj)] == f64::INFINITY {
+		return None;
+	}
+
+	// pixels in between, the exact start and goal at the extremities
+	let mut path = vec![*goal];
+	let mut current = parent[index(gi, gj)];
+	while let Some((i, j)) = current {
+		if (i, j) != (si, sj) {
+			path.push(map.pixel_to_world([i as u32, j as u32]));
+		}
+		current = parent[index(i, j)];
+	}
+	path.push(*start);
+	path.reverse();
+
+	// shortcut along the lines of sight, 8-connected paths are up to 8% longer than the any-angle ones
+	let mut smoothed_path = vec![path[0]];
+	let mut k = 0;
+	while k + 1 < path.len() {
+		let next = (k+1..path.len()).rev()
+			.find(|&l| map.get_traversed_space(&path[k], &path[l]) == Belief::Free)
+			.unwrap_or(k + 1);
+		smoothed_path.push(path[next]);
+		k = next;
+	}
+
+	Some(smoothed_path)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -580,8 +988,8 @@ fn coordinate_conversion() {
 	let xys = [[0.5, 0.3], [-0.5, 0.1], [-0.1, -0.4]];
 
 	for xy in &xys {
-		let ij = m.to_pixel_coordinates(&xy);
-		let xy_after_roundtrip = m.to_coordinates(&ij);
+		let ij = m.world_to_pixel(&xy).unwrap();
+		let xy_after_roundtrip = m.pixel_to_world(ij);
 		
 		let d = norm2(&xy, &xy_after_roundtrip);
 		let dmax = (2.0 as f64).sqrt() * 2.0 / 200.0;
@@ -590,6 +998,239 @@ fn coordinate_conversion() {
 	}
 }
 
+#[test]
+fn test_world_pixel_round_trip() {
+	let m = Map::build(image::GrayImage::from_pixel(200, 100, Luma([255])), [-2.0, 0.0], [2.0, 2.0]);
+	let resolution = 1.0 / m.ppm;
+
+	for xy in &[[0.5, 0.3], [-0.5, 0.1], [-1.99, 1.5], [1.3, 1.01], [0.0, 0.0]] {
+		let xy_after_roundtrip = m.pixel_to_world(m.world_to_pixel(xy).unwrap());
+
+		assert!((xy[0] - xy_after_roundtrip[0]).abs() <= resolution);
+		assert!((xy[1] - xy_after_roundtrip[1]).abs() <= resolution);
+	}
+
+	for ij in &[[0, 0], [99, 199], [50, 20], [3, 150]] {
+		assert_eq!(m.world_to_pixel(&m.pixel_to_world(*ij)), Some(*ij));
+	}
+}
+
+#[test]
+fn test_transition_validity_through_zones() {
+	// two doors side by side in the middle of the map, zone 0 on the left and zone 1 on the right
+	let mut img = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	let mut zones = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	for i in 40..60 {
+		for j in 40..60 {
+			img.put_pixel(j, i, Luma([128]));
+			zones.put_pixel(j, i, Luma([if j < 50 { 0 } else { 1 }]));
+		}
+	}
+	let mut m = Map::build(img, [-1.0, -1.0], [1.0, 1.0]);
+	m.add_zone_layer_image(zones, "zones");
+
+	let node = |state: [f64; 2]| PRMNode{ state, validity: PRMFuncs::state_validity(&m, &state).unwrap(), parents: Vec::new(), children: Vec::new() };
+	let (left, right, top) = (node([-0.5, 0.0]), node([0.5, 0.0]), node([0.0, 0.5]));
+	let (left_door, right_door) = (node([-0.05, 0.0]), node([0.05, 0.0]));
+
+	// free space
+	assert_eq!(PRMFuncs::transition_validator(&m, &left, &top), Some(bitvec![1, 1, 1, 1]));
+	// one door
+	assert_eq!(PRMFuncs::transition_validator(&m, &left, &left_door), Some(bitvec![0, 1, 0, 1]));
+	assert_eq!(PRMFuncs::transition_validator(&m, &right_door, &right), Some(bitvec![0, 0, 1, 1]));
+	// both doors, open in world 3 only
+	assert_eq!(PRMFuncs::transition_validator(&m, &left, &right), Some(bitvec![0, 0, 0, 1]));
+	assert_eq!(PRMFuncs::transition_validator(&m, &left_door, &right_door), Some(bitvec![0, 0, 0, 1]));
+}
+
+#[test]
+fn test_world_prior() {
+	let mut zones = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	zones.put_pixel(10, 10, Luma([0]));
+	zones.put_pixel(90, 90, Luma([1]));
+	let mut m = Map::build(image::GrayImage::from_pixel(100, 100, Luma([255])), [-1.0, -1.0], [1.0, 1.0]);
+	m.add_zone_layer_image(zones, "zones");
+
+	// uniform by default
+	assert_eq!(m.world_prior(), vec![0.25; 4]);
+
+	m.set_zone_prior(0, 0.8);
+	m.set_zone_prior(1, 0.3);
+	let expected = vec![0.2 * 0.7, 0.8 * 0.7, 0.2 * 0.3, 0.8 * 0.3];
+	for (p, q) in m.world_prior().iter().zip(&expected) {
+		assert!((p - q).abs() < 1e-9);
+	}
+	assert_eq!(assert_belief_state_validity(&m.world_prior()), Ok(()));
+}
+
+#[test]
+fn test_observation_radius_per_zone() {
+	// zone 0 on the left and zone 1 on the right, at the same distance from the center
+	let mut zones = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	for i in 48..52 {
+		for j in 23..27 {
+			zones.put_pixel(j, i, Luma([0]));
+			zones.put_pixel(j + 50, i, Luma([1]));
+		}
+	}
+	let mut m = Map::build(image::GrayImage::from_pixel(100, 100, Luma([255])), [-1.0, -1.0], [1.0, 1.0]);
+	m.add_zone_layer_image(zones, "zones");
+	m.visibility_distance = 0.2;
+
+	let center = [0.0, 0.0];
+	assert_eq!(m.observe_impl(&center, &vec![0.25; 4]), vec![vec![0.25; 4]]);
+
+	// zone 1 is seen from farther, the belief only splits on it
+	m.set_observation_radius(1, 0.8);
+	assert_eq!(m.observation_radius(0), 0.2);
+	assert_eq!(m.observe_impl(&center, &vec![0.25; 4]), vec![vec![0.5, 0.5, 0.0, 0.0], vec![0.0, 0.0, 0.5, 0.5]]);
+
+	// both zones
+	m.set_observation_radius(0, 0.8);
+	assert_eq!(m.observe_impl(&center, &vec![0.25; 4]).len(), 4);
+}
+
+#[test]
+fn test_zone_over_obstacle() {
+	// the zone covers, from left to right, an obstacle, a gray (conditional) area and a free area of the occupancy image
+	let mut occupancy = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	let mut zones = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	for i in 40..60 {
+		for j in 20..80 {
+			zones.put_pixel(j, i, Luma([0]));
+		}
+		for j in 20..40 {
+			occupancy.put_pixel(j, i, Luma([0]));
+		}
+		for j in 40..60 {
+			occupancy.put_pixel(j, i, Luma([128]));
+		}
+	}
+	let mut m = Map::build(occupancy, [-1.0, -1.0], [1.0, 1.0]);
+	m.add_zone_layer_image(zones, "zones");
+
+	let (obstacle, zone, free) = ([-0.5, 0.0], [0.0, 0.0], [0.5, 0.0]);
+	assert_eq!(m.is_state_valid(&obstacle), Belief::Obstacle);
+	assert_eq!(m.is_state_valid(&zone), Belief::Zone(0));
+	assert_eq!(m.is_state_valid(&free), Belief::Free);
+
+	// invalid in all the worlds
+	assert_eq!(m.state_validity(&obstacle), None);
+	assert_eq!(m.state_validity(&zone), Some(bitvec![0, 1]));
+	assert_eq!(m.state_validity(&free), Some(bitvec![1, 1]));
+
+	// same precedence along the segments
+	assert_eq!(m.get_traversed_space(&[-0.5, -0.5], &[-0.5, 0.5]), Belief::Obstacle);
+	assert_eq!(m.get_traversed_validity(&[-0.5, -0.5], &[-0.5, 0.5]), None);
+	assert_eq!(m.get_traversed_space(&[0.0, -0.5], &[0.0, 0.5]), Belief::Zone(0));
+	assert_eq!(m.get_traversed_space(&[0.5, -0.5], &[0.5, 0.5]), Belief::Free);
+}
+
+#[test]
+fn test_draw_cost_field() {
+	let mut m = Map::build(image::GrayImage::from_pixel(100, 100, Luma([255])), [-1.0, -1.0], [1.0, 1.0]);
+	m.draw_cost_field(&[([-0.5, -0.5], 1.0), ([0.5, 0.5], 2.0), ([0.5, -0.5], std::f64::INFINITY)]);
+
+	let pixel = |xy: &[f64; 2]| { let [i, j] = m.world_to_pixel(xy).unwrap(); *m.img.get_pixel(j, i) };
+	assert!(pixel(&[-0.5, -0.5]) != WHITE);
+	assert!(pixel(&[0.5, 0.5]) != WHITE);
+	assert_eq!(pixel(&[0.5, -0.5]), WHITE);
+}
+
+#[test]
+fn test_world_contact_sheet() {
+	let mut m = Map::open("data/map2.pgm", [-1.0, -1.0], [1.0, 1.0]);
+	m.add_zones("data/map2_zone_ids.pgm", 0.1);
+
+	let mut graph = PRMGraph{nodes: Vec::new()};
+	graph.add_node([0.0, 0.0], bitvec![1, 1, 1, 1]);
+	graph.add_node([0.5, 0.0], bitvec![1, 0, 1, 0]);
+	graph.add_bi_edge(0, 1, bitvec![1, 0, 1, 0]);
+
+	m.save_world_contact_sheet("results/tmp_contact_sheet", &graph);
+	let sheet = image::open("results/tmp_contact_sheet.png").unwrap();
+	fs::remove_file("results/tmp_contact_sheet.png").unwrap();
+
+	assert_eq!(sheet.dimensions(), (2 * m.img.width(), 2 * m.img.height()));
+}
+
+#[test]
+fn test_range_detection_from_far_away() {
+	let model = RangeDetectionModel { max_range: 1.0, falloff: 1.0 };
+	let zone_mask = bitvec![0, 1];
+	let belief_state = vec![0.5, 0.5];
+
+	// close to the zone, the belief is almost resolved
+	let near = model.observe(&[0.0, 0.0], &[0.0, 0.05], &zone_mask, &belief_state);
+	assert_eq!(near.len(), 2);
+	assert!(near[0].0[1] > 0.9 && near[1].0[0] > 0.9);
+
+	// far from the zone, both worlds remain possible
+	let far = model.observe(&[0.0, 0.0], &[0.0, 0.8], &zone_mask, &belief_state);
+	assert_eq!(far.len(), 2);
+	assert!(far.iter().all(|(b, _)| b[0] > 0.3 && b[1] > 0.3));
+	assert!(far[0].0[1] > far[0].0[0]); // the zone is reported open
+
+	// with an accuracy of 0.6, the zone is reported open with probability 0.8 * 0.6 + 0.2 * 0.4
+	let prior = vec![0.2, 0.8];
+	let reports = model.observe(&[0.0, 0.0], &[0.0, 0.8], &zone_mask, &prior);
+	assert!((reports[0].1 - 0.56).abs() < 1e-9);
+	assert!((reports[1].1 - 0.44).abs() < 1e-9);
+	assert!((reports[0].0[1] - 0.8 * 0.6 / 0.56).abs() < 1e-9); // bayesian update
+
+	// 3 worlds, the zone is open in 2 of them, accuracy of 0.75
+	let prior = vec![0.5, 0.3, 0.2];
+	let reports = model.observe(&[0.0, 0.0], &[0.0, 0.5], &bitvec![0, 1, 1], &prior);
+	assert!((reports.iter().map(|(_, p)| p).sum::<f64>() - 1.0).abs() < 1e-9);
+	assert!((reports[0].1 - (0.5 * 0.25 + 0.5 * 0.75)).abs() < 1e-9);
+
+	// out of range or already resolved, nothing is learnt
+	assert_eq!(model.observe(&[0.0, 0.0], &[0.0, 2.0], &zone_mask, &belief_state), vec![(belief_state.clone(), 1.0)]);
+	assert_eq!(model.observe(&[0.0, 0.0], &[0.0, 0.05], &zone_mask, &vec![0.0, 1.0]), vec![(vec![0.0, 1.0], 1.0)]);
+}
+
+#[test]
+fn test_save_color() {
+	let mut img = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	for i in 0..100 {
+		for j in 40..60 {
+			img.put_pixel(j, i, Luma([0]));
+		}
+	}
+	let mut m = Map::build(img, [-1.0, -1.0], [1.0, 1.0]);
+
+	// the drawings don't change the validity
+	m.img.put_pixel(50, 50, RED);
+	assert_eq!(m.is_state_valid(&m.pixel_to_world([50, 50])), Belief::Obstacle);
+
+	m.save_color("results/tmp_color.png");
+	let saved = image::open("results/tmp_color.png").unwrap();
+	assert!(fs::metadata("results/tmp_color.png").unwrap().len() > 0);
+	fs::remove_file("results/tmp_color.png").unwrap();
+
+	assert_eq!(saved.color(), image::ColorType::Rgb8);
+	let saved = saved.to_rgb8();
+	assert_eq!(*saved.get_pixel(50, 50), RED);
+	assert_eq!(*saved.get_pixel(50, 20), BLACK);
+	assert_eq!(*saved.get_pixel(20, 20), WHITE);
+}
+
+#[test]
+fn test_out_of_map_states_are_invalid() {
+	let m = Map::build(image::GrayImage::from_pixel(100, 100, Luma([255])), [-1.0, -1.0], [1.0, 1.0]);
+
+	for xy in &[[1.5, 0.0], [0.0, 1.5], [-1.5, 0.0], [0.0, -1.5], [1000.0, -1000.0], [std::f64::NAN, 0.0]] {
+		assert_eq!(m.world_to_pixel(xy), None);
+		assert_eq!(m.is_state_valid(xy), Belief::Obstacle);
+		assert!(RRTFuncs::state_validator(&m, xy) == Reachable::Never);
+		assert_eq!(PRMFuncs::state_validity(&m, xy), None);
+	}
+
+	assert_eq!(m.is_state_valid(&[0.9, 0.9]), Belief::Free);
+	assert_eq!(m.get_traversed_space(&[0.9, 0.0], &[1.1, 0.0]), Belief::Obstacle);
+	assert_eq!(grid_astar(&m, &[0.0, 0.0], &[1.5, 0.0]), None);
+}
+
 #[test]
 fn test_valid_state() {
 	let m = Map::open("data/map0.pgm", [-1.0, -1.0], [1.0, 1.0]);
@@ -689,6 +1330,28 @@ fn test_map_2_observation_model_in_zones() {
 	assert_eq!(posteriors.len(), 2); // zone 0 open
 }
 
+#[test]
+fn test_map_2_observation_with_detection_model() {
+	let mut map = Map::open("data/map2.pgm", [-1.0, -1.0], [1.0, 1.0]);
+	map.add_zones("data/map2_zone_ids.pgm", 0.1);
+	let model = RangeDetectionModel { max_range: 0.5, falloff: 1.0 };
+
+	// zone 0 is reported, the worlds stay possible
+	let prior = vec![0.25; 4];
+	let outcomes = map.observe_with_model(&model, &[0.54, -0.5], &prior);
+	assert_eq!(outcomes.len(), 2);
+	assert!((outcomes.iter().map(|(_, p)| p).sum::<f64>() - 1.0).abs() < 1e-9);
+	assert!(outcomes.iter().all(|(b, _)| b.iter().all(|&p| p > 0.0)));
+
+	// the beliefs average to the prior
+	for w in 0..4 {
+		assert!((outcomes.iter().map(|(b, p)| p * b[w]).sum::<f64>() - prior[w]).abs() < 1e-9);
+	}
+
+	// out of range
+	assert_eq!(map.observe_with_model(&model, &[-0.3, -0.5], &prior), vec![(prior.clone(), 1.0)]);
+}
+
 #[test]
 fn test_map_2_observation_model_outside_zones() {
 	let mut map = Map::open("data/map2.pgm", [-1.0, -1.0], [1.0, 1.0]);
@@ -772,6 +1435,180 @@ fn test_resize_image() {
 	fs::remove_file("results/tmp.png").unwrap();
 }
 
+#[test]
+fn test_clearance_cost() {
+	// 100x100 pixels, obstacle block in the middle
+	let mut img = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	for i in 40..60 {
+		for j in 30..70 {
+			img.put_pixel(j, i, Luma([0]));
+		}
+	}
+	let mut m = Map::build(img, [-1.0, -1.0], [1.0, 1.0]);
+
+	assert_eq!(m.clearance_at(&[0.0, 0.0]), 0.0);
+	assert!(m.clearance_at(&[0.0, -0.5]) > m.clearance_at(&[0.0, -0.3]));
+
+	// same length, on both sides of the obstacle
+	let close_edge = ([-0.5, 0.27], [0.5, 0.27]);
+	let far_edge = ([-0.5, -0.6], [0.5, -0.6]);
+	assert!(m.clearance_cost(&close_edge.0, &close_edge.1) > m.clearance_cost(&far_edge.0, &far_edge.1));
+
+	// the length only is used by default
+	assert_eq!(PRMFuncs::cost_evaluator(&m, &close_edge.0, &close_edge.1), PRMFuncs::cost_evaluator(&m, &far_edge.0, &far_edge.1));
+
+	m.set_clearance_weight(0.1);
+	assert!(PRMFuncs::cost_evaluator(&m, &close_edge.0, &close_edge.1) > PRMFuncs::cost_evaluator(&m, &far_edge.0, &far_edge.1));
+}
+
+#[test]
+fn test_clearance_at() {
+	// obstacle on the left half
+	let mut img = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	for i in 0..100 {
+		for j in 0..50 {
+			img.put_pixel(j, i, Luma([0]));
+		}
+	}
+	let mut m = Map::build(img, [-1.0, -1.0], [1.0, 1.0]);
+
+	assert_eq!(m.clearance_at(&[-0.5, 0.0]), 0.0);
+	assert_eq!(m.clearance_at(&[-0.01, 0.0]), 0.0);
+
+	// grows toward the free space
+	let clearances: Vec<f64> = [0.01, 0.2, 0.5, 0.9].iter().map(|&x| m.clearance_at(&[x, 0.0])).collect();
+	assert!(clearances[0] > 0.0);
+	assert!(clearances.windows(2).all(|pair| pair[0] < pair[1]));
+	assert!((clearances[2] - 0.5).abs() < 0.03);
+
+	// recomputed with the occupancy, the clearances are in meters
+	m.resize(2);
+	assert_eq!(m.distance_transform.len(), 200 * 200);
+	assert_eq!(m.clearance_at(&[-0.01, 0.0]), 0.0);
+	assert!((m.clearance_at(&[0.5, 0.0]) - 0.5).abs() < 0.03);
+}
+
+#[test]
+fn test_grid_astar() {
+	// 100x100 pixels, wall in the middle with an opening at the top
+	let mut img = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	for i in 20..100 {
+		for j in 48..52 {
+			img.put_pixel(j, i, Luma([0]));
+		}
+	}
+	let m = Map::build(img, [-1.0, -1.0], [1.0, 1.0]);
+
+	let start = [-0.5, -0.5];
+	let goal = [0.5, -0.5];
+	let path = grid_astar(&m, &start, &goal).expect("no path found");
+
+	assert_eq!(path[0], start);
+	assert_eq!(path[path.len() - 1], goal);
+
+	// goes through the opening, without touching the wall (same transition check as the planners)
+	assert!(path.iter().any(|state| state[1] > 0.58));
+	for (a, b) in pairwise_iter(&path) {
+		assert_ne!(m.get_traversed_space(a, b), Belief::Obstacle);
+	}
+
+	let cost: f64 = pairwise_iter(&path).map(|(a, b)| norm2(a, b)).sum();
+	assert!(cost > 2.0 * (0.58 + 0.5));
+
+	// no path to an obstacle
+	assert!(grid_astar(&m, &start, &[0.0, -0.5]).is_none());
+}
+
+#[test]
+fn test_grid_astar_cost_is_below_rrt_cost() {
+	use crate::rrt::*;
+	use crate::sample_space::*;
+
+	// on map0, data/map3.pgm isn't part of the data
+	let m = Map::open("data/map0.pgm", [-1.0, -1.0], [1.0, 1.0]);
+
+	fn goal(state: &[f64; 2]) -> bool {
+		(state[0] - 0.9).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05
+	}
+
+	let mut samplers = RRTDefaultSamplers {
+		state_sampler: ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+		belief_state_sampler: DiscreteSampler::new(),
+	};
+
+	let mut rrt = RRT::new(&mut samplers, &m);
+	let (_rrttree, policy, _paths) = rrt.plan([0.0, -0.8], &vec![1.0], goal, 0.1, 5.0, 5000);
+	let rrt_path = policy.path_to_leaf(0);
+	let rrt_cost: f64 = pairwise_iter(&rrt_path).map(|(a, b)| norm2(a, b)).sum();
+
+	let path = grid_astar(&m, &[0.0, -0.8], &rrt_path[rrt_path.len() - 1]).expect("no path found");
+	let cost: f64 = pairwise_iter(&path).map(|(a, b)| norm2(a, b)).sum();
+
+	// up to the discretization of the grid
+	assert!(cost <= rrt_cost + 2.0 / m.ppm);
+}
+
+#[test]
+fn test_zone_layers() {
+	// a door on the left observed by one sensor, a door on the right observed by another
+	let mut img = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	let mut left_layer = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	let mut right_layer = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	for i in 40..60 {
+		for j in 10..20 {
+			img.put_pixel(j, i, Luma([128]));
+			left_layer.put_pixel(j, i, Luma([0]));
+		}
+		for j in 80..90 {
+			img.put_pixel(j, i, Luma([128]));
+			right_layer.put_pixel(j, i, Luma([0]));
+		}
+	}
+	let mut m = Map::build(img, [-1.0, -1.0], [1.0, 1.0]);
+
+	m.add_zone_layer_image(left_layer, "left");
+	assert_eq!(m.n_worlds, 2);
+	assert_eq!(m.zones_to_worlds, vec![bitvec![0, 1]]);
+
+	m.add_zone_layer_image(right_layer, "right");
+	assert_eq!(m.n_zones, 2);
+	assert_eq!(m.n_worlds, 4);
+	assert_eq!(m.zone_layer("left"), Some(0..1));
+	assert_eq!(m.zone_layer("right"), Some(1..2));
+	assert_eq!(m.zone_layer("top"), None);
+
+	// per layer masks
+	assert_eq!(m.zones_to_worlds, vec![bitvec![0, 1, 0, 1], bitvec![0, 0, 1, 1]]);
+	assert_eq!(m.is_state_valid(&[-0.7, 0.0]), Belief::Zone(0));
+	assert_eq!(m.is_state_valid(&[0.7, 0.0]), Belief::Zone(1));
+	assert!(norm2(&m.zone_positions[0], &[-0.7, 0.0]) < 0.05);
+	assert!(norm2(&m.zone_positions[1], &[0.7, 0.0]) < 0.05);
+}
+
+#[test]
+fn test_gray_pixels_are_obstacles() {
+	// white image with a gray (anti-aliased) border around a black obstacle
+	let mut img = image::GrayImage::from_pixel(100, 100, Luma([255]));
+	for i in 40..60 {
+		for j in 40..60 {
+			let border = i < 45 || i >= 55 || j < 45 || j >= 55;
+			img.put_pixel(j, i, Luma([if border { 180 } else { 0 }]));
+		}
+	}
+	let m = Map::build(img, [-1.0, -1.0], [1.0, 1.0]);
+
+	assert_eq!(m.is_state_valid(&[-0.5, -0.5]), Belief::Free);
+	assert_eq!(m.is_state_valid(&[0.0, 0.0]), Belief::Obstacle);
+	assert_eq!(m.is_state_valid(&[-0.15, 0.0]), Belief::Obstacle); // border
+	assert_eq!(m.get_traversed_space(&[-0.5, 0.15], &[0.5, 0.15]), Belief::Obstacle); // crosses the border only
+
+	// the border becomes free with a lower threshold
+	let m = m.with_thresholds(150, 1);
+	assert_eq!(m.is_state_valid(&[-0.15, 0.0]), Belief::Free);
+	assert_eq!(m.get_traversed_space(&[-0.5, 0.15], &[0.5, 0.15]), Belief::Free);
+	assert_eq!(m.is_state_valid(&[0.0, 0.0]), Belief::Obstacle);
+}
+
 #[test]
 fn test_traversed_zone() {
 	let mut m = Map::open("data/map2_thin.pgm", [-1.0, -1.0], [1.0, 1.0]);
@@ -1,4 +1,5 @@
 use crate::common::*;
+use rayon::prelude::*;
 
 pub struct KdNode<const N: usize> {
 	pub id: usize,
@@ -11,13 +12,19 @@ pub struct KdTree<const N: usize> {
 	pub root: KdNode<N>,
 }
 
+fn is_finite<const N: usize>(state: &[f64; N]) -> bool {
+	state.iter().all(|v| v.is_finite())
+}
+
 impl<const N: usize> KdTree<N> {
 	pub fn new(state: [f64; N]) -> Self {
+		debug_assert!(is_finite(&state), "kdtree states should be finite");
 		let root = KdNode { id: 0, state, left: None, right: None };
 		Self { root }
 	}
 
 	pub fn new_with_id(id: usize, state: [f64; N]) -> Self {
+		debug_assert!(is_finite(&state), "kdtree states should be finite");
 		let root = KdNode { id, state, left: None, right: None };
 		Self { root }
 	}
@@ -26,7 +33,18 @@ impl<const N: usize> KdTree<N> {
 		self.root = KdNode { id: 0, state, left: None, right: None };
 	}
 
+	/// same as add, a non finite state is rejected instead of corrupting the tree (comparisons with NaN are always false)
+	pub fn try_add(&mut self, state: [f64; N], id: usize) -> Result<(), &'static str> {
+		if !is_finite(&state) {
+			return Err("kdtree states should be finite");
+		}
+
+		self.add(state, id);
+		Ok(())
+	}
+
 	pub fn add(&mut self, state: [f64; N], id: usize) {
+		debug_assert!(is_finite(&state), "kdtree states should be finite");
 		let mut current = &mut self.root;
 		for axis in (0..N).into_iter().cycle() {
 			let next = if state[axis] < current.state[axis] {
@@ -46,14 +64,27 @@ impl<const N: usize> KdTree<N> {
 	}
 
 	pub fn nearest_neighbor(&self, state: [f64; N]) -> &KdNode<N> {
-		self.nearest_neighbor_filtered(state, |_| { true } )
+		self.nearest_neighbor_filtered(state, |_| { true } ).expect("the tree has at least its root")
 	}
 
-	pub fn nearest_neighbor_filtered(&self, state: [f64; N], validator: impl Fn(usize) -> bool) -> &KdNode<N> {
+	/// None if the validator rejects all the nodes
+	pub fn nearest_neighbor_filtered(&self, state: [f64; N], validator: impl Fn(usize) -> bool) -> Option<&KdNode<N>> {
+		self.nearest_neighbor_impl(state, 0.0, validator)
+	}
+
+	/// approximate nearest neighbor, at most (1 + epsilon) times farther than the exact one
+	/// the branches that can't be closer than best / (1 + epsilon) are pruned
+	pub fn nearest_neighbor_approx(&self, state: [f64; N], epsilon: f64) -> &KdNode<N> {
+		assert!(epsilon >= 0.0, "epsilon should be positive");
+		self.nearest_neighbor_impl(state, epsilon, |_| { true }).expect("the tree has at least its root")
+	}
+
+	fn nearest_neighbor_impl(&self, state: [f64; N], epsilon: f64, validator: impl Fn(usize) -> bool) -> Option<&KdNode<N>> {
 		struct Args<'a, const N: usize> {
 			state: [f64; N],
 			dmin: f64,
-			nearest: &'a KdNode<N>,
+			epsilon: f64,
+			nearest: Option<&'a KdNode<N>>,
 		}
 
 		fn inner<'a, const N: usize>(a: &mut Args<'a, N>, from: &'a KdNode<N>, axis: usize, validator : &impl Fn(usize) -> bool) {
@@ -61,46 +92,53 @@ impl<const N: usize> KdTree<N> {
 				let d = norm2(&from.state, &a.state);
 				if d < a.dmin && validator(from.id) {
 					a.dmin = d;
-					a.nearest = from;
+					a.nearest = Some(from);
 				}
 			}
 
-			// go down
+			// go down (dmin is exact when epsilon is 0)
 			let next_axis = (axis+1) % N;
 			if a.state[axis] < from.state[axis] {
 				// left first
-				if a.state[axis] - a.dmin < from.state[axis] && from.left.is_some() {
+				if a.state[axis] - a.dmin / (1.0 + a.epsilon) < from.state[axis] && from.left.is_some() {
 					inner(a, from.left.as_ref().unwrap(), next_axis, validator);
 				}
-				if a.state[axis] + a.dmin >= from.state[axis] && from.right.is_some() {
+				if a.state[axis] + a.dmin / (1.0 + a.epsilon) >= from.state[axis] && from.right.is_some() {
 					inner(a, from.right.as_ref().unwrap(), next_axis, validator);
 				}
 			} else {
 				// right first
-				if a.state[axis] + a.dmin >= from.state[axis] && from.right.is_some() {
+				if a.state[axis] + a.dmin / (1.0 + a.epsilon) >= from.state[axis] && from.right.is_some() {
 					inner(a, from.right.as_ref().unwrap(), next_axis, validator);
 				}
-				if a.state[axis] - a.dmin < from.state[axis] && from.left.is_some() {
+				if a.state[axis] - a.dmin / (1.0 + a.epsilon) < from.state[axis] && from.left.is_some() {
 					inner(a, from.left.as_ref().unwrap(), next_axis, validator);
 				}
 			}
 		}
 
-		let mut a = Args { state, dmin: f64::INFINITY, nearest: &self.root };
+		debug_assert!(is_finite(&state), "kdtree queries should be finite");
+		let mut a = Args { state, dmin: f64::INFINITY, epsilon, nearest: None };
 		inner(&mut a, &self.root, 0, &validator);
 		a.nearest
 	}
 
 	pub fn nearest_neighbors_filtered(&self, state: [f64; N], radius: f64, validator: impl Fn(usize) -> bool) -> Vec<&KdNode<N>> {
+		self.nearest_neighbors_filtered_with_norm(state, radius, Norm::L2, validator)
+	}
+
+	/// the radius is measured with norm, the pruning along the axes holds for any of them
+	pub fn nearest_neighbors_filtered_with_norm(&self, state: [f64; N], radius: f64, norm: Norm, validator: impl Fn(usize) -> bool) -> Vec<&KdNode<N>> {
 		struct Args<'a, const N: usize> {
 			state: [f64; N],
 			radius: f64,
+			norm: Norm,
 			nearest: Vec<&'a KdNode<N>>,
 		}
 
 		fn inner<'a, const N: usize>(a: &mut Args<'a, N>, from: &'a KdNode<N>, axis: usize, validator : &impl Fn(usize) -> bool) {
 			{
-				let d = norm2(&from.state, &a.state);
+				let d = a.norm.distance(&from.state, &a.state);
 				if d <= a.radius && validator(from.id) {
 					a.nearest.push(from);
 				}
@@ -116,7 +154,8 @@ impl<const N: usize> KdTree<N> {
 			}
 		}
 
-		let mut a = Args { state, radius, nearest: vec![] };
+		debug_assert!(is_finite(&state), "kdtree queries should be finite");
+		let mut a = Args { state, radius, norm, nearest: vec![] };
 		inner(&mut a, &self.root, 0, &validator);
 		a.nearest
 	}
@@ -124,6 +163,17 @@ impl<const N: usize> KdTree<N> {
 	pub fn nearest_neighbors(&self, state: [f64; N], radius: f64) -> Vec<&KdNode<N>> {
 		self.nearest_neighbors_filtered(state, radius, |_| { true } )
 	}
+
+	pub fn nearest_neighbors_with_norm(&self, state: [f64; N], radius: f64, norm: Norm) -> Vec<&KdNode<N>> {
+		self.nearest_neighbors_filtered_with_norm(state, radius, norm, |_| { true } )
+	}
+
+	/// nearest neighbor of each state, queries are independent and run in parallel
+	pub fn nearest_neighbors_batch(&self, states: &[[f64; N]]) -> Vec<&KdNode<N>> {
+		states.par_iter()
+			.map(|state| self.nearest_neighbor(*state))
+			.collect()
+	}
 }
 
 
@@ -167,6 +217,17 @@ fn create_tree() -> (KdTree<2>, Vec<[f64;2]>, Vec<[f64;2]>) {
 	(tree, nodes.to_vec(), centers.to_vec())
 }
 
+#[test]
+fn test_non_finite_states_are_rejected() {
+	let (mut tree, nodes, _) = create_tree();
+
+	assert!(tree.try_add([f64::NAN, 1.0], 7).is_err());
+	assert!(tree.try_add([1.0, f64::INFINITY], 7).is_err());
+	assert_eq!(tree.try_add([1.0, 1.0], 7), Ok(()));
+
+	assert_eq!(tree.nearest_neighbors(nodes[0], 100.0).len(), nodes.len() + 1);
+}
+
 #[test]
 fn test_kdtree_creation() {
 	let tree = KdTree::new([3.0, 6.0]);
@@ -233,6 +294,54 @@ fn test_full_tree() {
 	}
 }
 
+#[test]
+fn test_nearest_neighbors_batch() {
+	let (tree, _, centers) = create_tree();
+
+	let mut states = centers.clone();
+	for i in 0..1000 {
+		states.push([(i % 23) as f64, (i % 19) as f64 + 0.5]);
+	}
+
+	let batch_ids = tree.nearest_neighbors_batch(&states)
+		.iter().map(|node| node.id).collect::<Vec<_>>();
+	let sequential_ids = states.iter()
+		.map(|state| tree.nearest_neighbor(*state).id).collect::<Vec<_>>();
+
+	assert_eq!(batch_ids, sequential_ids);
+}
+
+#[test]
+fn test_kdtree_is_sync() {
+	fn assert_sync<T: Sync>() {}
+	assert_sync::<KdTree<2>>();
+}
+
+#[test]
+fn test_nearest_neighbor_approx() {
+	let mut sampler = crate::sample_space::ContinuousSampler::new_with_seed([-1.0; 3], [1.0; 3], 3);
+
+	let states: Vec<[f64; 3]> = (0..2000).map(|_| sampler.sample()).collect();
+	let mut tree = KdTree::new(states[0]);
+	for (id, state) in states.iter().enumerate().skip(1) {
+		tree.add(*state, id);
+	}
+
+	for epsilon in [0.0, 0.1, 0.5, 2.0] {
+		for _ in 0..200 {
+			let query = sampler.sample();
+			let exact = norm2(&tree.nearest_neighbor(query).state, &query);
+			let approx = norm2(&tree.nearest_neighbor_approx(query, epsilon).state, &query);
+
+			assert!(exact <= approx);
+			assert!(approx <= (1.0 + epsilon) * exact + 1e-12);
+			if epsilon == 0.0 {
+				assert_eq!(approx, exact);
+			}
+		}
+	}
+}
+
 // query nearest neighbors
 #[test]
 fn test_nearest_neighbor() {
@@ -268,45 +377,47 @@ fn test_nearest_neighbor() {
 fn test_nearest_neighbor_with_filter_from_0(){
 	let (tree, _, __) = create_tree();
 
-	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |_| {true});
+	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |_| {true}).unwrap();
 	check_node(node, 0, [3.0, 6.0]);
 
-	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |id| { if vec![0].contains(&id) { false } else { true } });
+	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |id| { if vec![0].contains(&id) { false } else { true } }).unwrap();
 	check_node(node, 5, [2.0, 7.0]);
 
-	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |id| { if vec![0, 5].contains(&id) { false } else { true } });
+	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |id| { if vec![0, 5].contains(&id) { false } else { true } }).unwrap();
 	check_node(node, 3, [6.0, 12.0]);
 
-	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |id| { if vec![0, 5, 3].contains(&id) { false } else { true } });
+	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |id| { if vec![0, 5, 3].contains(&id) { false } else { true } }).unwrap();
 	check_node(node, 4, [9.0, 1.0]);
 
-	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |id| { if vec![0, 5, 3, 4].contains(&id) { false } else { true } });
+	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |id| { if vec![0, 5, 3, 4].contains(&id) { false } else { true } }).unwrap();
 	check_node(node, 2, [13.0, 15.0]);
 
-	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |id| { if vec![0, 5, 3, 4, 2].contains(&id) { false } else { true } });
+	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |id| { if vec![0, 5, 3, 4, 2].contains(&id) { false } else { true } }).unwrap();
 	check_node(node, 6, [10.0, 19.0]);
 
-	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |id| { if vec![0, 5, 3, 4, 2, 6].contains(&id) { false } else { true } });
+	let node = tree.nearest_neighbor_filtered([3.1, 6.0], |id| { if vec![0, 5, 3, 4, 2, 6].contains(&id) { false } else { true } }).unwrap();
 	check_node(node, 1, [17.0, 15.0]);
+
+	assert!(tree.nearest_neighbor_filtered([3.1, 6.0], |_| {false}).is_none());
 }
 
 #[test]
 fn test_nearest_neighbor_with_filter_from_2(){
 	let (tree, _, __) = create_tree();
 
-	let node = tree.nearest_neighbor_filtered([13.0, 15.1], &|_| {true});
+	let node = tree.nearest_neighbor_filtered([13.0, 15.1], &|_| {true}).unwrap();
 	check_node(node, 2, [13.0, 15.0]);
 
-	let node = tree.nearest_neighbor_filtered([13.0, 15.1], &|id| { if vec![2].contains(&id) { false } else { true } });
+	let node = tree.nearest_neighbor_filtered([13.0, 15.1], &|id| { if vec![2].contains(&id) { false } else { true } }).unwrap();
 	check_node(node, 1, [17.0, 15.0]);
 
-	let node = tree.nearest_neighbor_filtered([13.0, 15.1], &|id| { if vec![2, 1].contains(&id) { false } else { true } });
+	let node = tree.nearest_neighbor_filtered([13.0, 15.1], &|id| { if vec![2, 1].contains(&id) { false } else { true } }).unwrap();
 	check_node(node, 6, [10.0, 19.0]);
 
-	let node = tree.nearest_neighbor_filtered([13.0, 15.1], &|id| { if vec![2, 1, 6].contains(&id) { false } else { true } });
+	let node = tree.nearest_neighbor_filtered([13.0, 15.1], &|id| { if vec![2, 1, 6].contains(&id) { false } else { true } }).unwrap();
 	check_node(node, 3, [6.0, 12.0]);
 
-	let node = tree.nearest_neighbor_filtered([13.0, 15.1], &|id| { if vec![2, 1, 6].contains(&id) { false } else { true } });
+	let node = tree.nearest_neighbor_filtered([13.0, 15.1], &|id| { if vec![2, 1, 6].contains(&id) { false } else { true } }).unwrap();
 	check_node(node, 3, [6.0, 12.0]);
 }
 
@@ -1,4 +1,4 @@
-use itertools::{all, enumerate, izip, merge, zip};
+use itertools::{all, enumerate, izip, merge, zip, Itertools};
 
 use crate::common::*;
 use crate::nearest_neighbor::*;
@@ -8,7 +8,247 @@ use crate::prm_graph::*;
 use crate::prm_reachability::*;
 use crate::belief_graph::*;
 use bitvec::prelude::*;
-use std::{collections::HashMap, ops::Index};
+use priority_queue::PriorityQueue;
+use rayon::prelude::*;
+use serde::{Serialize, Deserialize};
+use std::{fs::File, io::BufWriter, ops::Index, sync::{Arc, atomic::{AtomicBool, Ordering}}, time::{Duration, Instant}};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RadiusSchedule {
+	Shrinking, // search_radius * (ln(n)/n)^(1/N), as in RRG
+	Constant,  // search_radius
+}
+
+/// whether the robot can move on from a state where it observes, without waiting for the outcome
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ObservationPolicy {
+	ObserveThenStop, // observation nodes only have observation edges
+	ObserveOrAct,    // observation nodes also keep their action edges, the cheapest option is planned (observing on ties)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlanError {
+	InvalidBelief(BeliefError),
+	BeliefGraphTooLarge{ estimated: usize }, // number of belief nodes
+	Cancelled,
+	GraphNotGrown,
+	FinalNodesNotReached, // for at least one world
+	BeliefGraphNotBuilt,
+}
+
+impl From<BeliefError> for PlanError {
+	fn from(error: BeliefError) -> Self {
+		PlanError::InvalidBelief(error)
+	}
+}
+
+/// size of the belief space planning problem, known before building the belief graph
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlanComplexity {
+	pub n_belief_states: usize,   // reachable from the start belief
+	pub max_belief_nodes: usize,  // n_nodes * n_belief_states, bound checked against set_max_belief_nodes (nodes of the possible worlds only)
+	pub n_belief_nodes: usize,    // compatible (node, belief) pairs, before compaction
+	pub n_belief_edges: usize,    // compatible (edge, belief) pairs, observation edges aren't counted
+}
+
+/// wall-clock time of the last run of each phase, the growth time adds up over grow and extend
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PhaseTimings {
+	pub growth: Duration,
+	pub belief_graph: Duration,
+	pub dp: Duration,         // conditional DP, or shortest path when the belief can't change
+	pub extraction: Duration,
+}
+
+/// parameters, results and timings of a planning run, see PRM::export_run_summary
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunSummary {
+	// parameters of the last growth, None if the graph wasn't grown
+	pub max_step: Option<f64>,
+	pub search_radius: Option<f64>,
+	pub n_iter_min: Option<usize>,
+	pub n_iter_max: Option<usize>,
+	pub seed: Option<u64>, // of the continuous sampler
+	// stats
+	pub n_iterations: usize,
+	pub n_nodes: usize,
+	pub n_edges: usize,
+	pub n_belief_nodes: usize,
+	pub n_worlds: usize,
+	pub final_set_complete: bool,
+	pub root_expected_cost: Option<f64>, // None before planning
+	// timings, in seconds
+	pub growth_time: f64,
+	pub belief_graph_time: f64,
+	pub dp_time: f64,
+	pub extraction_time: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GrowthParameters {
+	pub max_step: f64,
+	pub search_radius: f64,
+	pub n_iter_min: usize,
+	pub n_iter_max: usize,
+	pub radius_schedule: RadiusSchedule,
+	pub timeout: Option<Duration>,
+	pub snap_distance: Option<f64>, // goal samples closer than this are reached in one step, beyond max_step
+	pub max_degree: Option<usize>,  // out-edges per node, the closest neighbors are connected first
+	pub propagate_reachability: bool, // the reachability gained through a new edge is forwarded to the descendants of its end
+	pub clamp_to_bounds: bool, // the steered states are clipped into the bounds of the continuous sampler
+	pub focus_unsolved_worlds: bool, // the worlds are sampled among the ones whose goal isn't reached yet
+}
+
+impl Default for GrowthParameters {
+	fn default() -> Self {
+		Self {
+			max_step: 0.05,
+			search_radius: 5.0,
+			n_iter_min: 1000,
+			n_iter_max: 100000,
+			radius_schedule: RadiusSchedule::Shrinking,
+			timeout: None,
+			snap_distance: None,
+			max_degree: None,
+			propagate_reachability: true,
+			clamp_to_bounds: false,
+			focus_unsolved_worlds: false,
+		}
+	}
+}
+
+/// item of PRM::grow_iter
+#[derive(Clone, Debug, PartialEq)]
+pub enum GrowthStep {
+	Node{ id: usize, n_it: usize, completion_fraction: f64 }, // accepted node, iterations and reachability so far
+	Done(Result<(), PlanError>), // last item, what grow_graph would return
+}
+
+/// growth stepped one accepted node at a time, see PRM::grow_iter
+pub struct GrowIter<'p, 'a, F: PRMFuncs<N>, G: Fn(&[f64; N]) -> WorldMask, const N: usize> {
+	prm: &'p mut PRM<'a, F, N>,
+	goal: G,
+	parameters: GrowthParameters,
+	start_time: Instant,
+	i: usize,
+	done: bool,
+}
+
+impl<'p, 'a, F: PRMFuncs<N>, G: Fn(&[f64; N]) -> WorldMask, const N: usize> GrowIter<'p, 'a, F, G, N> {
+	/// the PRM grown so far, to render it between two steps
+	pub fn prm(&self) -> &PRM<'a, F, N> {
+		self.prm
+	}
+}
+
+impl<'p, 'a, F: PRMFuncs<N>, G: Fn(&[f64; N]) -> WorldMask, const N: usize> Iterator for GrowIter<'p, 'a, F, G, N> {
+	type Item = GrowthStep;
+
+	fn next(&mut self) -> Option<GrowthStep> {
+		if self.done {
+			return None;
+		}
+
+		loop {
+			if let Some(result) = self.prm.growth_stop(self.i, &self.parameters, self.start_time) {
+				self.done = true;
+				self.prm.finish_growth(self.i, self.start_time);
+				return Some(GrowthStep::Done(result.and_then(|_| self.prm.update_final_node_ids())));
+			}
+
+			self.i += 1;
+
+			if let Some(id) = self.prm.grow_iteration(&self.goal, &self.parameters, &mut |continuous_sampler| continuous_sampler.sample()) {
+				return Some(GrowthStep::Node{ id, n_it: self.i, completion_fraction: self.prm.conservative_reachability.completion_fraction() });
+			}
+		}
+	}
+}
+
+/// builds and grows a PRM with named parameters, defaults match the positional grow_graph api
+pub struct PRMBuilder<'a, F: PRMFuncs<N>, const N: usize> {
+	fns: &'a F,
+	low: [f64; N],
+	up: [f64; N],
+	seed: u64,
+	parameters: GrowthParameters,
+}
+
+impl<'a, F: PRMFuncs<N>, const N: usize> PRMBuilder<'a, F, N> {
+	pub fn new(fns: &'a F, low: [f64; N], up: [f64; N]) -> Self {
+		Self { fns, low, up, seed: 0, parameters: GrowthParameters::default() }
+	}
+
+	pub fn max_step(mut self, max_step: f64) -> Self {
+		self.parameters.max_step = max_step;
+		self
+	}
+
+	pub fn search_radius(mut self, search_radius: f64) -> Self {
+		self.parameters.search_radius = search_radius;
+		self
+	}
+
+	pub fn iter_bounds(mut self, n_iter_min: usize, n_iter_max: usize) -> Self {
+		assert!(n_iter_min <= n_iter_max, "n_iter_min should be lower than n_iter_max");
+		self.parameters.n_iter_min = n_iter_min;
+		self.parameters.n_iter_max = n_iter_max;
+		self
+	}
+
+	pub fn seed(mut self, seed: u64) -> Self {
+		self.seed = seed;
+		self
+	}
+
+	pub fn radius_schedule(mut self, radius_schedule: RadiusSchedule) -> Self {
+		self.parameters.radius_schedule = radius_schedule;
+		self
+	}
+
+	pub fn timeout(mut self, timeout: Duration) -> Self {
+		self.parameters.timeout = Some(timeout);
+		self
+	}
+
+	pub fn snap_distance(mut self, snap_distance: f64) -> Self {
+		self.parameters.snap_distance = Some(snap_distance);
+		self
+	}
+
+	pub fn max_degree(mut self, max_degree: usize) -> Self {
+		assert!(max_degree > 0, "nodes should have at least one edge");
+		self.parameters.max_degree = Some(max_degree);
+		self
+	}
+
+	pub fn propagate_reachability(mut self, propagate_reachability: bool) -> Self {
+		self.parameters.propagate_reachability = propagate_reachability;
+		self
+	}
+
+	pub fn clamp_to_bounds(mut self, clamp_to_bounds: bool) -> Self {
+		self.parameters.clamp_to_bounds = clamp_to_bounds;
+		self
+	}
+
+	pub fn focus_unsolved_worlds(mut self, focus_unsolved_worlds: bool) -> Self {
+		self.parameters.focus_unsolved_worlds = focus_unsolved_worlds;
+		self
+	}
+
+	pub fn build(&self) -> PRM<'a, F, N> {
+		PRM::new(ContinuousSampler::new_with_seed(self.low, self.up, self.seed),
+				 DiscreteSampler::new_with_seed(self.seed),
+				 self.fns)
+	}
+
+	pub fn grow(&self, start: &[f64; N], goal: impl Fn(&[f64; N]) -> WorldMask) -> Result<PRM<'a, F, N>, PlanError> {
+		let mut prm = self.build();
+		prm.grow_graph_with(start, goal, &self.parameters)?;
+		Ok(prm)
+	}
+}
 
 pub struct PRM<'a, F: PRMFuncs<N>, const N: usize> {
 	continuous_sampler: ContinuousSampler<N>,
@@ -19,10 +259,22 @@ pub struct PRM<'a, F: PRMFuncs<N>, const N: usize> {
 	pub n_worlds: usize,
 	n_it: usize,
 	pub graph: PRMGraph<N>,
-	final_node_ids: Vec<usize>,
+	pub final_node_ids: Vec<usize>,
 	// grow graph rrg
 	pub conservative_reachability: Reachability,
+	// multi-query roadmap
+	roadmap_parameters: Option<GrowthParameters>,
+	cancel_flag: Option<Arc<AtomicBool>>,
 	// pomdp
+	observation_fan_out_limit: Option<usize>,
+	observation_cost: f64,
+	observation_policy: ObservationPolicy,
+	heuristic_weight: Option<f64>,
+	max_belief_nodes: Option<usize>,
+	// run summary
+	growth_parameters: Option<GrowthParameters>,
+	timings: PhaseTimings,
+	root_cost: Option<f64>,
 	node_to_belief_nodes: Vec<Vec<Option<usize>>>,
 	belief_graph: BeliefGraph<N>,
 	expected_costs_to_goals: Vec<f64>
@@ -39,377 +291,2846 @@ impl<'a, F: PRMFuncs<N>, const N: usize> PRM<'a, F, N> {
 			   graph: PRMGraph{nodes: vec![]},
 			   final_node_ids: Vec::new(),
 			   conservative_reachability: Reachability::new(), 
+			   roadmap_parameters: None,
+			   cancel_flag: None,
+			   observation_fan_out_limit: None,
+			   observation_cost: 0.0,
+			   observation_policy: ObservationPolicy::ObserveThenStop,
+			   heuristic_weight: None,
+			   max_belief_nodes: None,
+			   growth_parameters: None,
+			   timings: PhaseTimings::default(),
+			   root_cost: None,
 			   node_to_belief_nodes: Vec::new(),
 		       belief_graph: BeliefGraph{nodes: Vec::new(), reachable_belief_states: Vec::new()},
 			   expected_costs_to_goals: Vec::new() }
 	}
 
-	pub fn grow_graph(&mut self, &start: &[f64; N], goal: fn(&[f64; N]) -> WorldMask,
-				max_step: f64, search_radius: f64, n_iter_min: usize, n_iter_max: usize) -> Result<(), &'static str> {
+	pub fn grow_graph(&mut self, start: &[f64; N], goal: impl Fn(&[f64; N]) -> WorldMask,
+				max_step: f64, search_radius: f64, n_iter_min: usize, n_iter_max: usize) -> Result<(), PlanError> {
+		let parameters = GrowthParameters{ max_step, search_radius, n_iter_min, n_iter_max, ..Default::default() };
+
+		self.grow_graph_with(start, goal, &parameters)
+	}
+
+	pub fn grow_graph_with(&mut self, start: &[f64; N], goal: impl Fn(&[f64; N]) -> WorldMask, parameters: &GrowthParameters) -> Result<(), PlanError> {
+		self.grow_graph_with_progress(start, goal, parameters, |_, _| {})
+	}
+
+	/// progress is called after each iteration, with the iteration and the reachability (see completion_fraction and reached_worlds)
+	pub fn grow_graph_with_progress(&mut self, start: &[f64; N], goal: impl Fn(&[f64; N]) -> WorldMask, parameters: &GrowthParameters,
+				progress: impl FnMut(usize, &Reachability)) -> Result<(), PlanError> {
+		self.grow_graph_impl(start, goal, parameters, &mut |continuous_sampler| continuous_sampler.sample(), progress)
+	}
+
+	/// custom sampling distribution (goal-biased, obstacle-biased..), sampler draws the states from the uniform sampler of the PRM
+	pub fn grow_graph_with_sampler(&mut self, start: &[f64; N], goal: impl Fn(&[f64; N]) -> WorldMask, parameters: &GrowthParameters,
+				sampler: &mut dyn FnMut(&mut ContinuousSampler<N>) -> [f64; N]) -> Result<(), PlanError> {
+		self.grow_graph_impl(start, goal, parameters, sampler, |_, _| {})
+	}
+
+	fn grow_graph_impl(&mut self, &start: &[f64; N], goal: impl Fn(&[f64; N]) -> WorldMask, parameters: &GrowthParameters,
+				sampler: &mut dyn FnMut(&mut ContinuousSampler<N>) -> [f64; N], mut progress: impl FnMut(usize, &Reachability)) -> Result<(), PlanError> {
+		println!("grow graph..");
+
+		let start_time = Instant::now();
+		self.start_growth(start, parameters);
+
+		let mut i = 0;
+		loop {
+			if let Some(result) = self.growth_stop(i, parameters, start_time) {
+				self.finish_growth(i, start_time);
+				return result.and_then(|_| self.update_final_node_ids());
+			}
+
+			i+=1;
+
+			self.grow_iteration(&goal, parameters, sampler);
+
+			progress(i, &self.conservative_reachability);
+		}
+	}
+
+	/// same growth as grow_graph_with, stepped one accepted node at a time (e.g. to render the intermediate graphs)
+	/// the last item is GrowthStep::Done
+	pub fn grow_iter<'p, G: Fn(&[f64; N]) -> WorldMask>(&'p mut self, start: &[f64; N], goal: G, parameters: &GrowthParameters) -> GrowIter<'p, 'a, F, G, N> {
+		let start_time = Instant::now();
+		self.start_growth(*start, parameters);
+
+		GrowIter{ prm: self, goal, parameters: *parameters, start_time, i: 0, done: false }
+	}
+
+	fn start_growth(&mut self, start: [f64; N], parameters: &GrowthParameters) {
+		self.growth_parameters = Some(*parameters);
+
+		let root_validity = self.fns.state_validity(&start).expect("Start from a valid state!");
+		self.n_worlds = root_validity.len();
+		self.graph.add_node(start, root_validity.clone());
+		self.conservative_reachability.set_root(root_validity);
+		self.kdtree.reset(start);
+	}
+
+	fn finish_growth(&mut self, n_iterations: usize, start_time: Instant) {
+		self.n_it += n_iterations;
+		self.timings.growth += start_time.elapsed();
+	}
+
+	/// None while the growth should go on after i iterations, otherwise Ok (iteration bounds or timeout) or Err if cancelled
+	fn growth_stop(&self, i: usize, parameters: &GrowthParameters, start_time: Instant) -> Option<Result<(), PlanError>> {
+		let &GrowthParameters{n_iter_min, n_iter_max, timeout, ..} = parameters;
+
+		if i >= n_iter_min && (self.conservative_reachability.is_final_set_complete() || i >= n_iter_max) {
+			return Some(Ok(()));
+		}
+
+		if self.is_cancelled() {
+			println!("grow graph cancelled after {} iterations", i);
+			return Some(Err(PlanError::Cancelled));
+		}
+
+		if timeout.map_or(false, |timeout| start_time.elapsed() > timeout) {
+			println!("grow graph timeout after {} iterations", i);
+			return Some(Ok(()));
+		}
+
+		None
+	}
+
+	/// continues the growth of an already grown graph for n_iter iterations (the iteration bounds and timeout of the parameters are ignored)
+	pub fn extend_graph(&mut self, goal: impl Fn(&[f64; N]) -> WorldMask, parameters: &GrowthParameters, n_iter: usize) -> Result<(), PlanError> {
+		if self.graph.nodes.is_empty() {
+			return Err(PlanError::GraphNotGrown);
+		}
+
+		let start_time = Instant::now();
+
+		for i in 0..n_iter {
+			if self.is_cancelled() {
+				self.finish_growth(i, start_time);
+				return Err(PlanError::Cancelled);
+			}
+
+			self.grow_iteration(&goal, parameters, &mut |continuous_sampler| continuous_sampler.sample());
+		}
+
+		self.finish_growth(n_iter, start_time);
+
+		self.update_final_node_ids()
+	}
+
+	/// returns the id of the new node, None if the sample is rejected
+	fn grow_iteration(&mut self, goal: &impl Fn(&[f64; N]) -> WorldMask, parameters: &GrowthParameters, sampler: &mut dyn FnMut(&mut ContinuousSampler<N>) -> [f64; N]) -> Option<usize> {
+		let &GrowthParameters{max_step, snap_distance, clamp_to_bounds, focus_unsolved_worlds, ..} = parameters;
+
+		// First sample state and world
+		let mut new_state = sampler(&mut self.continuous_sampler);
+		let world = self.sample_world(focus_unsolved_worlds);
+
+		// Second, retrieve closest node for sampled world and steer from there (unless the goal is close enough to be reached directly)
+		// the iteration is skipped if no node is reachable in the sampled world yet
+		let kd_from = self.kdtree.nearest_neighbor_filtered(new_state, |id|{self.conservative_reachability.reachability(id).valid_in(world)})?; // log n
+		let norm = self.fns.norm();
+		let snap = snap_distance.map_or(false, |snap_distance| norm.distance(&kd_from.state, &new_state) <= snap_distance && goal(&new_state).any());
+		if !snap {
+			norm.steer(&kd_from.state, &mut new_state, max_step);
+		}
+		if clamp_to_bounds {
+			self.continuous_sampler.clamp(&mut new_state);
+		}
+
+		let kd_from_id = kd_from.id;
+		let state_validity = self.fns.state_validity(&new_state)?;
+
+		Some(self.add_connected_node(new_state, state_validity, goal(&new_state), kd_from_id, parameters))
+	}
+
+	/// adds the node, connects it to its neighbors (to fallback_id if it has none) and registers it in the reachability
+	fn add_connected_node(&mut self, new_state: [f64; N], state_validity: WorldMask, finality: WorldMask, fallback_id: usize, parameters: &GrowthParameters) -> usize {
+		let &GrowthParameters{max_step, search_radius, radius_schedule, max_degree, propagate_reachability, ..} = parameters;
+		let norm = self.fns.norm();
+
+		// Third, add node
+		let new_node_id = self.graph.add_node(new_state, state_validity.clone());
+		let new_node = &self.graph.nodes[new_node_id];
+		self.conservative_reachability.add_node(state_validity.clone());
+
+		// Fourth, we find the neighbors in a specific radius of new_state.
+		let radius = self.connection_radius(max_step, search_radius, radius_schedule);
+
+		// Fifth we connect to neighbors 
+		let mut neighbour_ids: Vec<usize> = self.kdtree.nearest_neighbors_with_norm(new_state, radius, norm).iter()
+		.map(|&kd_node| kd_node.id)
+		.collect();
+
+		if neighbour_ids.is_empty() { neighbour_ids.push(fallback_id); }
+
+		// with a degree cap, the saturated neighbors are skipped and the closest ones come first
+		if let Some(max_degree) = max_degree {
+			neighbour_ids.retain(|&id| self.graph.nodes[id].children.len() < max_degree);
+			neighbour_ids.sort_by(|&a, &b| norm.distance(&self.graph.nodes[a].state, &new_state).partial_cmp(&norm.distance(&self.graph.nodes[b].state, &new_state)).unwrap());
+		}
+		let max_degree = max_degree.unwrap_or(usize::MAX);
+
+		//let b = Vec<(usize, Option<WorldMask>)>::new();
+		// Idea: sample which ones we rewire to?
+		let fwd_edges: Vec<(usize, Option<WorldMask>)> = neighbour_ids.iter()
+			.map(|&id| (id, &self.graph.nodes[id]))
+			.map(|(id, node)| (id, self.fns.transition_validator(node, new_node)))
+			.filter(|(_, validity)| validity.is_some())
+			.take(max_degree)
+			.collect();
+
+		let bwd_edges: Vec<(usize, Option<WorldMask>)> = neighbour_ids.iter()
+			.map(|&id| (id, &self.graph.nodes[id]))
+			.map(|(id, node)| (id, self.fns.transition_validator(node, new_node)))
+			.filter(|(_, validity)| validity.is_some())
+			.take(max_degree)
+			.collect();
+					
+		// connect neighbors to new node
+		for (id, validity) in fwd_edges {
+			let cost = self.fns.cost_evaluator(&self.graph.nodes[id].state, &new_state);
+			self.graph.add_edge_with_cost(id, new_node_id, validity.expect("None validity should be filtered at this stage"), cost);
+			self.conservative_reachability.add_edge(id, new_node_id);
+		}
+
+		// connect new node to neighbor
+		for (id, validity) in bwd_edges {
+			let cost = self.fns.cost_evaluator(&new_state, &self.graph.nodes[id].state);
+			self.graph.add_edge_with_cost(new_node_id, id, validity.expect("None validity should be filtered at this stage"), cost);
+			if propagate_reachability {
+				self.propagate_reachability(new_node_id, id);
+			}
+			else {
+				self.conservative_reachability.add_edge(new_node_id, id);
+			}
+		}
+
+		let is_final = finality.iter().any(|w|{*w});
+		if is_final {
+			self.conservative_reachability.add_final_node(new_node_id, finality);
+		}
+
+		self.kdtree.add(new_state, new_node_id);
+
+		new_node_id
+	}
+
+	/// inserts the exact goal state in a grown graph as a final node of the worlds of finality, connected to its neighbors
+	/// (within the connection radius of the last growth, or to the nearest node), returns its id
+	/// the final nodes are updated once the goals of all the worlds are reached
+	pub fn add_goal_state(&mut self, state: [f64; N], finality: WorldMask) -> Result<usize, &'static str> {
+		if self.graph.nodes.is_empty() {
+			return Err(&"graph should be grown before adding a goal");
+		}
+
+		let state_validity = self.fns.state_validity(&state).ok_or("goal state isn't valid")?;
+		let nearest_id = self.kdtree.nearest_neighbor(state).id;
+		let parameters = self.growth_parameters.unwrap_or_default();
+		let goal_id = self.add_connected_node(state, state_validity, finality, nearest_id, &parameters);
+
+		if self.conservative_reachability.is_final_set_complete() {
+			self.final_node_ids = self.conservative_reachability.final_node_ids();
+		}
+
+		Ok(goal_id)
+	}
+
+	/// with focus_unsolved_worlds, uniform among the worlds whose goal isn't reached yet (all of them once they are all reached)
+	fn sample_world(&mut self, focus_unsolved_worlds: bool) -> usize {
+		if focus_unsolved_worlds {
+			let unsolved_worlds: Vec<usize> = self.conservative_reachability.reached_worlds().iter_zeros().collect();
+			if !unsolved_worlds.is_empty() {
+				return unsolved_worlds[self.discrete_sampler.sample(unsolved_worlds.len())];
+			}
+		}
+
+		self.discrete_sampler.sample(self.n_worlds)
+	}
+
+	/// adds the edge to the reachability, the nodes whose reachability improves forward it to their children (bfs)
+	/// terminates since the reachability only grows
+	fn propagate_reachability(&mut self, from_id: usize, to_id: usize) {
+		let mut queue = std::collections::VecDeque::from(vec![(from_id, to_id)]);
+
+		while let Some((from_id, to_id)) = queue.pop_front() {
+			if self.conservative_reachability.add_edge(from_id, to_id) {
+				queue.extend(self.graph.nodes[to_id].children.iter().map(|edge| (to_id, edge.id)));
+			}
+		}
+	}
+
+	fn connection_radius(&self, max_step: f64, search_radius: f64, radius_schedule: RadiusSchedule) -> f64 {
+		let s = match radius_schedule {
+			RadiusSchedule::Shrinking => {
+				let n = self.graph.nodes.len() as f64;
+				search_radius * (n.ln()/n).powf(1.0/(N as f64))
+			},
+			RadiusSchedule::Constant => search_radius,
+		};
+		if s < max_step { s } else { max_step }
+	}
+
+	/// query-independent roadmap: parameters.n_iter_min states are sampled and the valid ones connected to their neighbors
+	/// the roadmap isn't rooted, it is meant to be used with query (not with plan_belief_space)
+	pub fn grow_roadmap(&mut self, parameters: &GrowthParameters) {
+		let &GrowthParameters{max_step, search_radius, n_iter_min, radius_schedule, ..} = parameters;
+
+		for _ in 0..n_iter_min {
+			let new_state = self.continuous_sampler.sample();
+
+			if let Some(state_validity) = self.fns.state_validity(&new_state) {
+				let radius = self.connection_radius(max_step, search_radius, radius_schedule);
+				let neighbour_ids: Vec<usize> = if self.graph.nodes.is_empty() { Vec::new() } else {
+					self.kdtree.nearest_neighbors_with_norm(new_state, radius, self.fns.norm()).iter().map(|&kd_node| kd_node.id).collect()
+				};
+
+				self.n_worlds = state_validity.len();
+				let new_node_id = self.graph.add_node(new_state, state_validity);
+
+				let edges: Vec<(usize, usize, WorldMask)> = neighbour_ids.iter()
+					.flat_map(|&id| vec![(id, new_node_id), (new_node_id, id)])
+					.filter_map(|(from, to)| self.fns.transition_validator(&self.graph.nodes[from], &self.graph.nodes[to]).map(|validity| (from, to, validity)))
+					.collect();
+
+				for (from, to, validity) in edges {
+					let cost = self.fns.cost_evaluator(&self.graph.nodes[from].state, &self.graph.nodes[to].state);
+					self.graph.add_edge_with_cost(from, to, validity, cost);
+				}
+
+				if new_node_id == 0 { self.kdtree.reset(new_state); } else { self.kdtree.add(new_state, new_node_id); }
+			}
+		}
+
+		self.n_it += n_iter_min;
+		self.roadmap_parameters = Some(*parameters);
+	}
+
+	/// shortest path on the roadmap from start to goal, which are connected to their neighbors without modifying the roadmap
+	/// the path is valid in all the worlds, None if there is none (or if no roadmap node was valid)
+	pub fn query(&self, start: &[f64; N], goal: &[f64; N]) -> Option<Vec<[f64; N]>> {
+		let parameters = self.roadmap_parameters.as_ref().expect("grow the roadmap before querying it");
+		if self.graph.nodes.is_empty() {
+			return None; // the kdtree only holds its placeholder root
+		}
+		let radius = self.connection_radius(parameters.max_step, parameters.search_radius, parameters.radius_schedule);
+
+		let query_node = |state: &[f64; N]| -> Option<PRMNode<N>> {
+			let validity = self.fns.state_validity(state).filter(|validity| validity.all())?;
+			Some(PRMNode{state: *state, validity, parents: Vec::new(), children: Vec::new()})
+		};
+		let (start_node, goal_node) = (query_node(start)?, query_node(goal)?);
+
+		let connections = |from: &PRMNode<N>, to: &PRMNode<N>| {
+			self.fns.transition_validator(from, to).map_or(false, |validity| validity.all())
+		};
+
+		// roadmap ids, then start and goal
+		let (start_id, goal_id) = (self.graph.nodes.len(), self.graph.nodes.len() + 1);
+		let neighbour_ids = |state: &[f64; N]| -> Vec<usize> {
+			self.kdtree.nearest_neighbors_with_norm(*state, radius, self.fns.norm()).iter().map(|&kd_node| kd_node.id).collect()
+		};
+
+		let start_edges: Vec<usize> = neighbour_ids(start).into_iter().filter(|&id| connections(&start_node, &self.graph.nodes[id])).collect();
+		let goal_edges: Vec<usize> = neighbour_ids(goal).into_iter().filter(|&id| connections(&self.graph.nodes[id], &goal_node)).collect();
+
+		// dijkstra from the start
+		let mut dist = vec![std::f64::INFINITY; self.graph.nodes.len() + 2];
+		let mut previous: Vec<Option<usize>> = vec![None; self.graph.nodes.len() + 2];
+		let mut q = PriorityQueue::new();
+
+		dist[start_id] = 0.0;
+		q.push(start_id, Priority{prio: 0.0});
+
+		while let Some((u_id, _)) = q.pop() {
+			if u_id == goal_id {
+				break;
+			}
+
+			let successors: Vec<(usize, f64)> = if u_id == start_id {
+				let mut successors: Vec<(usize, f64)> = start_edges.iter().map(|&id| (id, self.fns.cost_evaluator(start, &self.graph.nodes[id].state))).collect();
+				if self.fns.norm().distance(start, goal) <= radius && connections(&start_node, &goal_node) {
+					successors.push((goal_id, self.fns.cost_evaluator(start, goal)));
+				}
+				successors
+			} else {
+				let u = &self.graph.nodes[u_id];
+				let mut successors: Vec<(usize, f64)> = u.children.iter()
+					.filter(|edge| edge.all_valid() && self.graph.nodes[edge.id].all_valid())
+					.map(|edge| (edge.id, edge.cost))
+					.collect();
+				if goal_edges.contains(&u_id) {
+					successors.push((goal_id, self.fns.cost_evaluator(&u.state, goal)));
+				}
+				successors
+			};
+
+			for (v_id, cost) in successors {
+				let alternative = dist[u_id] + cost;
+				if alternative < dist[v_id] {
+					dist[v_id] = alternative;
+					previous[v_id] = Some(u_id);
+					q.push(v_id, Priority{prio: alternative});
+				}
+			}
+		}
+
+		previous[goal_id]?;
+
+		let mut path = vec![*goal];
+		let mut current = previous[goal_id];
+		while let Some(id) = current {
+			path.push(if id == start_id { *start } else { self.graph.nodes[id].state });
+			current = previous[id];
+		}
+
+		path.reverse();
+		Some(path)
+	}
+
+	fn update_final_node_ids(&mut self) -> Result<(), PlanError> {
+		match self.conservative_reachability.is_final_set_complete() {
+			true => {
+				self.final_node_ids = self.conservative_reachability.final_node_ids();
+				Ok(())
+			},
+			_ => Err(PlanError::FinalNodesNotReached)
+		}
+	}
+
+	/// beliefs that observing at state can lead to, with their probabilities (see PRMFuncs::observe_with_probabilities)
+	/// a single outcome of probability 1 where nothing is observed
+	pub fn observation_outcomes(&self, state: &[f64; N], belief_state: &BeliefState) -> Vec<(BeliefState, f64)> {
+		self.fns.observe_with_probabilities(state, belief_state)
+	}
+
+	/// updates the graph in place once the status of a zone is known, returns the number of removed edges
+	/// the worlds follow the Map convention: the zone z is open in the worlds w such that w & (1 << z) != 0
+	pub fn apply_observation(&mut self, resolved_zone: usize, is_open: bool) -> usize {
+		let possible_worlds: WorldMask = (0..self.n_worlds)
+			.map(|world| (world & (1 << resolved_zone) != 0) == is_open)
+			.collect();
+
+		let n_removed_edges = self.graph.restrict_worlds(&possible_worlds);
+		self.conservative_reachability.restrict_worlds(&possible_worlds);
+		self.final_node_ids = self.conservative_reachability.final_node_ids();
+
+		n_removed_edges
+	}
+
+	/// removes the nodes that can't be reached from the start in any world, returns the number of removed nodes
+	pub fn prune_unreachable(&mut self) -> usize {
+		if self.graph.nodes.is_empty() {
+			return 0;
+		}
+
+		let new_ids = self.graph.prune_unreachable_impl(0);
+
+		self.conservative_reachability.remap(&new_ids);
+		self.final_node_ids = self.final_node_ids.iter().filter_map(|&id| new_ids[id]).collect();
+
+		self.kdtree.reset(self.graph.nodes[0].state);
+		for (id, node) in self.graph.nodes.iter().enumerate().skip(1) {
+			self.kdtree.add(node.state, id);
+		}
+
+		new_ids.iter().filter(|id| id.is_none()).count()
+	}
+
+	#[allow(clippy::style)]
+	pub fn plan_belief_space(&mut self, start_belief_state: &BeliefState) -> Result<Policy<N>, PlanError> {
+		let mut start_belief_state = start_belief_state.clone();
+
+		// beliefs slightly off because of upstream floating point errors are fixed, wrong ones are rejected
+		if let Err(BeliefError::InvalidSum(sum)) = assert_belief_state_validity(&start_belief_state) {
+			if !(0.5..=2.0).contains(&sum) {
+				return Err(BeliefError::InvalidSum(sum).into());
+			}
+
+			normalize(&mut start_belief_state);
+		}
+
+		if self.is_cancelled() {
+			return Err(PlanError::Cancelled);
+		}
+
+		// no observation can change the belief, the pomdp collapses to a shortest path
+		if self.fns.reachable_belief_states(&start_belief_state).len() == 1 {
+			println!("single belief state, plan shortest path..");
+
+			return Ok(self.plan_single_belief(&start_belief_state));
+		}
+
+		println!("build belief graph..");
+
+		self.build_belief_graph(&start_belief_state)?;
+
+		if self.is_cancelled() {
+			return Err(PlanError::Cancelled);
+		}
+
+		// no observation anywhere, the belief never changes and the pomdp collapses to a shortest path as well
+		// it isn't planned per world: without observation, the robot can't tell the worlds apart and follows a single path,
+		// valid in all the possible worlds, which is what the DP on the belief graph would give (see plan_single_belief)
+		if !self.has_observations() {
+			println!("no observation in the belief graph, plan shortest path..");
+
+			return Ok(self.plan_single_belief(&start_belief_state));
+		}
+
+		println!("compute expected costs to goal..");
+
+		self.compute_expected_costs_to_goals();
+
+		if self.is_cancelled() {
+			return Err(PlanError::Cancelled);
+		}
+		
+		println!("extract policy..");
+
+		let start_time = Instant::now();
+		let policy = self.extract_policy();
+		self.timings.extraction = start_time.elapsed();
+
+		println!("success!");
+
+		Ok(policy)
+	}
+
+	/// anytime refinement of a grown graph: the graph is extended by parameters.n_iter_min iterations between plannings
+	/// until the expected cost of the root improves by less than tol, returns the last policy and the root cost of each round
+	pub fn plan_until_converged(&mut self, start_belief_state: &BeliefState, goal: impl Fn(&[f64; N]) -> WorldMask,
+				parameters: &GrowthParameters, tol: f64, max_rounds: usize) -> Result<(Policy<N>, Vec<f64>), PlanError> {
+		assert!(max_rounds > 0, "at least one round is needed");
+
+		let mut cost_history = Vec::new();
+		let mut policy = self.plan_belief_space(start_belief_state)?;
+		cost_history.push(policy.nodes[0].cost_to_goal);
+
+		for _ in 1..max_rounds {
+			self.extend_graph(&goal, parameters, parameters.n_iter_min)?;
+
+			policy = self.plan_belief_space(start_belief_state)?;
+			cost_history.push(policy.nodes[0].cost_to_goal);
+
+			let previous_cost = cost_history[cost_history.len() - 2];
+			if previous_cost - policy.nodes[0].cost_to_goal <= tol {
+				break;
+			}
+		}
+
+		Ok((policy, cost_history))
+	}
+
+	/// one policy per start belief, e.g. for a prior varying along the probability of one zone
+	/// the belief graph is built once: when the reachable belief states of a start belief have the same supports as the ones of the graph,
+	/// only the beliefs of its nodes (hence the transition probabilities) are updated before computing the costs and extracting the policy
+	/// otherwise (or with an observation fan-out limit, that depends on the probabilities, or with soft observations) the belief graph is rebuilt
+	pub fn plan_belief_sweep(&mut self, beliefs: &[BeliefState]) -> Result<Vec<Policy<N>>, PlanError> {
+		let same_supports = |a: &[BeliefState], b: &[BeliefState]| a.len() == b.len() && a.iter().zip(b)
+			.all(|(a, b)| a.len() == b.len() && a.iter().zip(b).all(|(p, q)| (*p > 0.0) == (*q > 0.0)));
+
+		let mut built = false;
+		let mut policies = Vec::new();
+
+		for belief in beliefs {
+			assert_belief_state_validity(belief)?;
+
+			let reachable_belief_states = self.fns.reachable_belief_states(belief);
+			if built && self.observation_fan_out_limit.is_none() && !self.belief_graph.has_soft_observations()
+				&& same_supports(&reachable_belief_states, &self.belief_graph.reachable_belief_states) {
+				for node in &mut self.belief_graph.nodes {
+					node.belief_state = reachable_belief_states[node.belief_id].clone();
+					node.outcome_probabilities.clear(); // given by the new beliefs
+				}
+				self.belief_graph.reachable_belief_states = reachable_belief_states;
+			}
+			else {
+				self.build_belief_graph(belief)?;
+				built = true;
+			}
+
+			self.compute_expected_costs_to_goals();
+			policies.push(self.extract_policy());
+		}
+
+		Ok(policies)
+	}
+
+	#[allow(clippy::style)]
+	pub fn plan_single_belief(&mut self, belief_state: &BeliefState) -> Policy<N> {
+		let start_time = Instant::now();
+
+		// dijkstra on the nodes and edges compatible with the belief, keeping the next node towards the goal
+		let mut dist = vec![std::f64::INFINITY; self.graph.n_nodes()];
+		let mut next: Vec<Option<usize>> = vec![None; self.graph.n_nodes()];
+		let mut q = PriorityQueue::new();
+
+		for &id in &self.final_node_ids {
+			if is_compatible(belief_state, &self.graph.nodes[id].validity) && self.is_goal_for_belief(id, belief_state) {
+				dist[id] = 0.0;
+				q.push(id, Priority{prio: 0.0});
+			}
+		}
+
+		while let Some((v_id, _)) = q.pop() {
+			let v = &self.graph.nodes[v_id];
+
+			for parent_edge in &v.parents {
+				let u_id = parent_edge.id;
+				let u = &self.graph.nodes[u_id];
+
+				if !is_compatible(belief_state, &u.validity) || !is_compatible(belief_state, &parent_edge.validity) {
+					continue;
+				}
+
+				let cost = match self.fns.belief_cost_evaluator(&u.state, &v.state, parent_edge.cost, belief_state) {
+					Some(cost) => cost,
+					None => continue,
+				};
+
+				let alternative = dist[v_id] + cost;
+
+				if alternative < dist[u_id] {
+					dist[u_id] = alternative;
+					next[u_id] = Some(v_id);
+					q.push(u_id, Priority{prio: alternative});
+				}
+			}
+		}
+
+		// the belief graph isn't needed
+		self.node_to_belief_nodes = Vec::new();
+		self.belief_graph = BeliefGraph{nodes: Vec::new(), reachable_belief_states: vec![belief_state.clone()]};
+		self.expected_costs_to_goals = Vec::new();
+		self.root_cost = Some(dist[0]);
+		self.timings.dp = start_time.elapsed();
+		self.timings.extraction = Duration::default();
+
+		// single branch policy
+		let mut policy: Policy<N> = Policy{nodes: Vec::new(), leafs: Vec::new()};
+		let mut id = 0;
+		let mut policy_id = policy.add_node(&self.graph.nodes[id].state, belief_state, next[id].is_none(), dist[id] == 0.0);
+		policy.nodes[policy_id].cost_to_goal = dist[id];
+
+		while let Some(next_id) = next[id] {
+			let child_policy_id = policy.add_node(&self.graph.nodes[next_id].state, belief_state, next[next_id].is_none(), dist[next_id] == 0.0);
+			policy.nodes[child_policy_id].cost_to_goal = dist[next_id];
+			policy.add_edge(policy_id, child_policy_id);
+
+			id = next_id;
+			policy_id = child_policy_id;
+		}
+
+		policy
+	}
+
+	/// free-space-only planning (e.g. map without zones): the graph is grown and searched in the single world, without belief states
+	pub fn plan_deterministic(&mut self, start: &[f64; N], goal: impl Fn(&[f64; N]) -> bool, parameters: &GrowthParameters) -> Result<Vec<[f64; N]>, &'static str> {
+		match self.fns.state_validity(start) {
+			Some(validity) if validity.len() == 1 => {},
+			Some(_) => return Err("deterministic planning needs a single world"),
+			None => return Err("start state isn't valid"),
+		}
+
+		self.grow_graph_with(start, |state| bitvec![if goal(state) { 1 } else { 0 }; 1], parameters).map_err(|error| match error {
+			PlanError::Cancelled => "growth cancelled",
+			_ => "goal not reached",
+		})?;
+
+		let policy = self.plan_single_belief(&vec![1.0]);
+		if policy.leafs.is_empty() {
+			return Err("goal not reached");
+		}
+
+		Ok(policy.path_to_leaf(0))
+	}
+
+	/// lossy: only the most probable outcomes of each observation are kept in the belief graph
+	pub fn set_observation_fan_out_limit(&mut self, limit: usize) {
+		assert!(limit > 0, "observations should keep at least one outcome");
+		self.observation_fan_out_limit = Some(limit);
+	}
+
+	/// flag polled between the iterations of the growth and the steps of the belief space planning, once set they stop with an error
+	/// the graph is left consistent: a cancelled growth can be resumed with extend_graph (after clearing the flag)
+	pub fn set_cancel_flag(&mut self, cancel_flag: Arc<AtomicBool>) {
+		self.cancel_flag = Some(cancel_flag);
+	}
+
+	fn is_cancelled(&self) -> bool {
+		self.cancel_flag.as_ref().map_or(false, |cancel_flag| cancel_flag.load(Ordering::Relaxed))
+	}
+
+	/// budget of the belief graph, planning fails early with BeliefGraphTooLarge beyond it
+	pub fn set_max_belief_nodes(&mut self, max_belief_nodes: usize) {
+		self.max_belief_nodes = Some(max_belief_nodes);
+	}
+
+	/// fixed cost of each observation (sensing), 0 by default
+	pub fn set_observation_cost(&mut self, cost: f64) {
+		assert!(cost >= 0.0, "observation cost should be positive");
+		self.observation_cost = cost;
+	}
+
+	/// ObserveThenStop by default
+	pub fn set_observation_policy(&mut self, observation_policy: ObservationPolicy) {
+		self.observation_policy = observation_policy;
+	}
+
+	/// pairs of possible worlds (i < j) that no observation of the belief graph tells apart, the belief between them is never resolved
+	pub fn unresolvable_world_pairs(&mut self, start_belief_state: &BeliefState) -> Result<Vec<(usize, usize)>, PlanError> {
+		self.build_belief_graph(start_belief_state)?;
+
+		let possible_worlds: Vec<usize> = (0..start_belief_state.len()).filter(|&w| start_belief_state[w] > 0.0).collect();
+		let mut resolved = vec![vec![false; start_belief_state.len()]; start_belief_state.len()];
+
+		// an observation edge splits the worlds possible before it that are not both possible after it
+		for node in self.belief_graph.nodes.iter().filter(|node| node.node_type == BeliefNodeType::Observation) {
+			for &child_id in &node.children {
+				let child_belief_state = &self.belief_graph.nodes[child_id].belief_state;
+
+				for (&i, &j) in possible_worlds.iter().tuple_combinations() {
+					if node.belief_state[i] > 0.0 && node.belief_state[j] > 0.0 && (child_belief_state[i] > 0.0) != (child_belief_state[j] > 0.0) {
+						resolved[i][j] = true;
+					}
+				}
+			}
+		}
+
+		Ok(possible_worlds.iter().tuple_combinations()
+			.filter(|(&i, &j)| !resolved[i][j])
+			.map(|(&i, &j)| (i, j))
+			.collect())
+	}
+
+	/// nodes valid in at least one world of non zero start probability, the others are pruned from the belief graph
+	/// (the belief states keep the dimension of the graph so that the policies are indexed by the worlds of the map)
+	fn n_possible_nodes(&self, start_belief_state: &BeliefState) -> usize {
+		self.graph.nodes.iter()
+			.filter(|node| node.possible_in(start_belief_state))
+			.count()
+	}
+
+	/// dry run of build_belief_graph, nothing is allocated per belief node
+	pub fn estimate_plan_complexity(&self, start_belief_state: &BeliefState) -> PlanComplexity {
+		let reachable_belief_states = self.fns.reachable_belief_states(start_belief_state);
+
+		let mut n_belief_nodes = 0;
+		let mut n_belief_edges = 0;
+		for node in &self.graph.nodes {
+			for belief_state in reachable_belief_states.iter().filter(|belief_state| is_compatible(belief_state, &node.validity)) {
+				n_belief_nodes += 1;
+				n_belief_edges += node.children.iter()
+					.filter(|child_edge| is_compatible(belief_state, &child_edge.validity) && is_compatible(belief_state, &self.graph.nodes[child_edge.id].validity))
+					.count();
+			}
+		}
+
+		PlanComplexity {
+			n_belief_states: reachable_belief_states.len(),
+			max_belief_nodes: self.n_possible_nodes(start_belief_state) * reachable_belief_states.len(),
+			n_belief_nodes,
+			n_belief_edges,
+		}
+	}
+
+	/// trades optimality for speed: the expected costs are computed by weighted A* from the root instead of an exhaustive dijkstra,
+	/// with PRMFuncs::cost_lower_bound as heuristic. The policy stays valid, but no suboptimality bound is guaranteed (see conditional_weighted_astar)
+	pub fn set_heuristic_weight(&mut self, weight: f64) {
+		assert!(weight >= 1.0, "the heuristic weight should be greater than 1");
+		self.heuristic_weight = Some(weight);
+	}
+
+	/// fails before allocating anything if the belief graph could exceed the budget, see set_max_belief_nodes
+	/// the construction only iterates over vectors, the belief nodes are ordered by graph node then by reachable belief state
+	#[allow(clippy::style)]
+	pub fn build_belief_graph(&mut self, start_belief_state: &BeliefState) -> Result<(), PlanError> {
+		let start_time = Instant::now();
+		self.build_full_belief_graph(start_belief_state)?;
+		self.compact_belief_graph();
+		self.timings.belief_graph = start_time.elapsed();
+		Ok(())
+	}
+
+	/// one belief node per compatible (node, belief), including the ones left Unknown
+	fn build_full_belief_graph(&mut self, start_belief_state: &BeliefState) -> Result<(), PlanError> {
+		// build belief state graph
+		let reachable_belief_states = self.fns.reachable_belief_states(start_belief_state);
+
+		// upper bound, the incompatible (node, belief) pairs are skipped
+		let estimated = self.n_possible_nodes(start_belief_state) * reachable_belief_states.len();
+		if self.max_belief_nodes.map_or(false, |max_belief_nodes| estimated > max_belief_nodes) {
+			return Err(PlanError::BeliefGraphTooLarge{ estimated });
+		}
+		let mut belief_space_graph: BeliefGraph<N> = BeliefGraph{nodes: Vec::new(), reachable_belief_states: reachable_belief_states.clone()};
+		let mut node_to_belief_nodes: Vec<Vec<Option<usize>>> = vec![vec![None; reachable_belief_states.len()]; self.graph.n_nodes()];
+		
+		// build nodes (only where the node is compatible with the belief, others would never be typed)
+		// the compatibilities are computed in parallel, the nodes are then added in (node, belief) order
+		let compatible_belief_ids: Vec<Vec<usize>> = self.graph.nodes.par_iter()
+			.map(|node| (0..reachable_belief_states.len()).filter(|&belief_id| is_compatible(&reachable_belief_states[belief_id], &node.validity)).collect())
+			.collect();
+
+		for (id, belief_ids) in compatible_belief_ids.iter().enumerate() {
+			for &belief_id in belief_ids {
+				let belief_node_id = belief_space_graph.add_node(self.graph.nodes[id].state, reachable_belief_states[belief_id].clone(), belief_id, BeliefNodeType::Unknown);
+				node_to_belief_nodes[id][belief_id] = Some(belief_node_id);
+			}
+		}
+
+		// build transitions due to observations (observation edges)
+		for (id, node) in self.graph.nodes.iter().enumerate() {
+			if self.is_cancelled() {
+				return Err(PlanError::Cancelled);
+			}
+
+			for (belief_id, belief_state) in reachable_belief_states.iter().enumerate() {
+				let parent_belief_node_id = node_to_belief_nodes[id][belief_id];
+				if parent_belief_node_id.is_none() {
+					continue;
+				}
+
+				for (child_belief_state_id, p) in self.observed_belief_ids(&belief_space_graph, &node.state, belief_state) {
+					let child_belief_node_id = node_to_belief_nodes[id][child_belief_state_id];
+
+					if let (Some(parent_id), Some(child_id)) = (parent_belief_node_id, child_belief_node_id) {
+						belief_space_graph.nodes[parent_id].node_type = BeliefNodeType::Observation;
+						belief_space_graph.add_observation_edge(parent_id, child_id, p);
+					}
+				}
+			}
+		}
+
+		// build possible geometric edges (action edges)
+		for (id, node) in self.graph.nodes.iter().enumerate() {
+			for (belief_id, _) in reachable_belief_states.iter().enumerate() {
+				let parent_belief_node_id = node_to_belief_nodes[id][belief_id];
+
+				if self.observation_policy == ObservationPolicy::ObserveThenStop && parent_belief_node_id.is_some() && belief_space_graph.nodes[parent_belief_node_id.unwrap()].node_type == BeliefNodeType::Observation {
+					continue;
+				}
+
+				for child_edge in &node.children {
+					let child_belief_node_id = node_to_belief_nodes[child_edge.id][belief_id];
+
+					if let (Some(parent_id), Some(child_id)) = (parent_belief_node_id, child_belief_node_id) {
+						if is_compatible(&belief_space_graph.nodes[parent_id].belief_state, &child_edge.validity) {
+							if belief_space_graph.nodes[parent_id].node_type == BeliefNodeType::Unknown {
+								belief_space_graph.nodes[parent_id].node_type = BeliefNodeType::Action;
+							}
+							belief_space_graph.add_edge(parent_id, child_id);
+						}
+					}
+				}
+			}
+		}
+
+		self.node_to_belief_nodes = node_to_belief_nodes;
+		self.belief_graph = belief_space_graph;
+
+		Ok(())
+	}
+
+	/// ids of the belief states observed from state, other than belief_state (see set_observation_fan_out_limit), with their probabilities
+	fn observed_belief_ids(&self, belief_space_graph: &BeliefGraph<N>, state: &[f64; N], belief_state: &BeliefState) -> Vec<(usize, f64)> {
+		let mut outcomes = self.fns.observe_with_probabilities(state, belief_state);
+		// an outcome leaving the belief unchanged isn't an observation, it doesn't count in the fan-out limit
+		outcomes.retain(|(child_belief_state, _)| !beliefs_equal(belief_state, child_belief_state, BELIEF_STATE_EQUALITY_TOLERANCE));
+
+		if let Some(limit) = self.observation_fan_out_limit {
+			// the probabilities of the remaining outcomes are renormalized during the DP
+			outcomes.sort_by(|(_, p), (_, q)| q.total_cmp(p));
+			outcomes.truncate(limit);
+		}
+
+		outcomes.iter()
+			.map(|(child_belief_state, p)| (belief_space_graph.belief_id(child_belief_state), *p))
+			.collect()
+	}
+
+	/// updates the belief graph after the validities of changed_nodes (or of their edges) changed, instead of building it again
+	/// only the belief nodes of the changed nodes and of their neighbors are retyped and reconnected, the nodes added to the graph since the last build count as changed
+	/// the result is the one of build_belief_graph up to the order of the belief nodes: the root stays first and the new belief nodes are appended
+	pub fn rebuild_belief_graph_incremental(&mut self, changed_nodes: &[usize]) -> Result<(), PlanError> {
+		if self.belief_graph.nodes.is_empty() {
+			return Err(PlanError::BeliefGraphNotBuilt);
+		}
+
+		let start_time = Instant::now();
+		let reachable_belief_states = self.belief_graph.reachable_belief_states.clone();
+		let n_known_nodes = self.node_to_belief_nodes.len();
+		self.node_to_belief_nodes.resize(self.graph.n_nodes(), vec![None; reachable_belief_states.len()]);
+
+		let mut touched = vec![false; self.graph.n_nodes()];
+		for id in changed_nodes.iter().copied().chain(n_known_nodes..self.graph.n_nodes()) {
+			touched[id] = true;
+			for edge in self.graph.nodes[id].parents.iter().chain(&self.graph.nodes[id].children) {
+				touched[edge.id] = true;
+			}
+		}
+		let touched_ids: Vec<usize> = (0..touched.len()).filter(|&id| touched[id]).collect();
+
+		// types as build_full_belief_graph would give them, before compaction (a belief node exists wherever the node is compatible)
+		let compatible = |id: usize, belief_id: usize| is_compatible(&reachable_belief_states[belief_id], &self.graph.nodes[id].validity);
+		let mut observed_belief_ids = vec![Vec::new(); self.graph.n_nodes() * reachable_belief_states.len()];
+		let mut new_types = vec![None; self.graph.n_nodes() * reachable_belief_states.len()];
+		for &id in &touched_ids {
+			let node = &self.graph.nodes[id];
+			for (belief_id, belief_state) in reachable_belief_states.iter().enumerate() {
+				if !compatible(id, belief_id) {
+					continue;
+				}
+
+				let index = id * reachable_belief_states.len() + belief_id;
+				observed_belief_ids[index] = self.observed_belief_ids(&self.belief_graph, &node.state, belief_state);
+				observed_belief_ids[index].retain(|&(child_belief_id, _)| compatible(id, child_belief_id));
+
+				let node_type = if !observed_belief_ids[index].is_empty() {
+					BeliefNodeType::Observation
+				} else if node.children.iter().any(|edge| compatible(edge.id, belief_id) && is_compatible(belief_state, &edge.validity)) {
+					BeliefNodeType::Action
+				} else {
+					BeliefNodeType::Unknown
+				};
+
+				// same belief nodes as compact_belief_graph keeps
+				let is_root = self.node_to_belief_nodes[id][belief_id] == Some(0);
+				if node_type != BeliefNodeType::Unknown || is_root || self.final_node_ids.contains(&id) {
+					new_types[index] = Some(node_type);
+				}
+			}
+		}
+
+		// the edges of the touched belief nodes are all rebuilt
+		for &id in &touched_ids {
+			for &belief_node_id in self.node_to_belief_nodes[id].iter().flatten() {
+				let node = &mut self.belief_graph.nodes[belief_node_id];
+				let (parents, children) = (std::mem::take(&mut node.parents), std::mem::take(&mut node.children));
+				node.outcome_probabilities.clear();
+				for parent_id in parents {
+					self.belief_graph.nodes[parent_id].children.retain(|&child_id| child_id != belief_node_id);
+				}
+				for child_id in children {
+					self.belief_graph.nodes[child_id].parents.retain(|&parent_id| parent_id != belief_node_id);
+				}
+			}
+		}
+
+		let mut keep = vec![true; self.belief_graph.nodes.len()];
+		for &id in &touched_ids {
+			for (belief_id, belief_state) in reachable_belief_states.iter().enumerate() {
+				match (self.node_to_belief_nodes[id][belief_id], new_types[id * reachable_belief_states.len() + belief_id]) {
+					(Some(belief_node_id), Some(node_type)) => self.belief_graph.nodes[belief_node_id].node_type = node_type,
+					(None, Some(node_type)) => {
+						let belief_node_id = self.belief_graph.add_node(self.graph.nodes[id].state, belief_state.clone(), belief_id, node_type);
+						self.node_to_belief_nodes[id][belief_id] = Some(belief_node_id);
+						keep.push(true);
+					},
+					(Some(belief_node_id), None) => {
+						self.node_to_belief_nodes[id][belief_id] = None;
+						keep[belief_node_id] = false;
+					},
+					(None, None) => {},
+				}
+			}
+		}
+
+		let observe_then_stop = self.observation_policy == ObservationPolicy::ObserveThenStop;
+		let has_action_edges = |belief_node_id: usize, belief_graph: &BeliefGraph<N>|
+			!(observe_then_stop && belief_graph.nodes[belief_node_id].node_type == BeliefNodeType::Observation);
+
+		for &id in &touched_ids {
+			let node = &self.graph.nodes[id];
+			for (belief_id, belief_state) in reachable_belief_states.iter().enumerate() {
+				let belief_node_id = match self.node_to_belief_nodes[id][belief_id] {
+					Some(belief_node_id) => belief_node_id,
+					None => continue,
+				};
+
+				for &(child_belief_id, p) in &observed_belief_ids[id * reachable_belief_states.len() + belief_id] {
+					if let Some(child_id) = self.node_to_belief_nodes[id][child_belief_id] {
+						self.belief_graph.add_observation_edge(belief_node_id, child_id, p);
+					}
+				}
+
+				if has_action_edges(belief_node_id, &self.belief_graph) {
+					for edge in &node.children {
+						if let Some(child_id) = self.node_to_belief_nodes[edge.id][belief_id] {
+							if is_compatible(belief_state, &edge.validity) {
+								self.belief_graph.add_edge(belief_node_id, child_id);
+							}
+						}
+					}
+				}
+
+				// the edges from the untouched parents, the touched ones add their own
+				for edge in node.parents.iter().filter(|edge| !touched[edge.id]) {
+					if let Some(parent_id) = self.node_to_belief_nodes[edge.id][belief_id] {
+						if has_action_edges(parent_id, &self.belief_graph) && is_compatible(belief_state, &edge.validity) {
+							self.belief_graph.add_edge(parent_id, belief_node_id);
+						}
+					}
+				}
+			}
+		}
+
+		self.retain_belief_nodes(&keep);
+		self.timings.belief_graph = start_time.elapsed();
+
+		Ok(())
+	}
+
+	/// drops the Unknown belief nodes (no transition, they can't be part of a policy), the root and the final nodes are kept
+	/// returns the number of removed belief nodes
+	pub fn compact_belief_graph(&mut self) -> usize {
+		let mut keep: Vec<bool> = self.belief_graph.nodes.iter().map(|node| node.node_type != BeliefNodeType::Unknown).collect();
+
+		if let Some(root) = keep.first_mut() {
+			*root = true;
+		}
+
+		for &final_id in &self.final_node_ids {
+			for &belief_node_id in self.node_to_belief_nodes[final_id].iter().flatten() {
+				keep[belief_node_id] = true;
+			}
+		}
+
+		self.retain_belief_nodes(&keep)
+	}
+
+	/// returns the number of removed belief nodes
+	fn retain_belief_nodes(&mut self, keep: &[bool]) -> usize {
+		let new_ids = self.belief_graph.retain_nodes(keep);
+
+		for belief_node_ids in &mut self.node_to_belief_nodes {
+			for belief_node_id in belief_node_ids.iter_mut() {
+				*belief_node_id = belief_node_id.and_then(|id| new_ids[id]);
+			}
+		}
+
+		new_ids.iter().filter(|id| id.is_none()).count()
+	}
+
+	/// a final node is a goal for the beliefs whose possible worlds all have it as goal (see the goal mask given to grow_graph)
+	/// the final nodes set by hand, without goal mask, are goals in every world
+	fn is_goal_for_belief(&self, id: usize, belief_state: &BeliefState) -> bool {
+		self.conservative_reachability.finality(id).map_or(true, |finality| is_compatible(belief_state, finality))
+	}
+
+	/// returns the number of belief nodes expanded by the DP
+	pub fn compute_expected_costs_to_goals(&mut self) -> usize {
+		//let mut final_belief_state_node_ids = final_node_ids.iter().fold(Vec::new(), |finals, final_id| { finals.extend(node_to_belief_nodes[final_id]); finals } );
+		let mut final_belief_state_node_ids: Vec<usize> = Vec::new();
+		for &final_id in &self.final_node_ids {
+			for belief_node_id in self.node_to_belief_nodes[final_id].iter().flatten() {
+				if self.is_goal_for_belief(final_id, &self.belief_graph.nodes[*belief_node_id].belief_state) {
+					final_belief_state_node_ids.push(*belief_node_id);
+				}
+			}
+		}
+
+		self.compute_expected_costs_to_terminals(&final_belief_state_node_ids)
+	}
+
+	/// same as compute_expected_costs_to_goals, the costs are measured to the given belief nodes instead of the final nodes (see belief_nodes)
+	pub fn compute_expected_costs_to_terminals(&mut self, terminal_belief_node_ids: &[usize]) -> usize {
+		let start_time = Instant::now();
+		let belief_node_to_node = self.belief_node_to_node();
+
+		// DP in belief state, the forbidden edges cost infinity and are never relaxed
+		let edge_cost = |u_id: usize, v_id: usize| self.belief_edge_cost(&belief_node_to_node, u_id, v_id).unwrap_or(std::f64::INFINITY);
+
+		let result = match self.heuristic_weight {
+			Some(weight) => {
+				let heuristic = |id: usize| self.fns.cost_lower_bound(&self.belief_graph.nodes[0].state, &self.belief_graph.nodes[id].state);
+				conditional_weighted_astar(&self.belief_graph, terminal_belief_node_ids, edge_cost, self.observation_cost, Some(0), heuristic, weight)
+			},
+			None => conditional_weighted_astar(&self.belief_graph, terminal_belief_node_ids, edge_cost, self.observation_cost, None, |_| 0.0, 1.0),
+		};
+		self.expected_costs_to_goals = result.costs;
+
+		self.root_cost = self.expected_costs_to_goals.first().copied();
+		self.timings.dp = start_time.elapsed();
+		result.n_expansions
+	}
+
+	/// cost of a belief edge, on the costs cached in the roadmap (observation edges aren't in the roadmap)
+	/// None if belief_cost_evaluator forbids it
+	fn belief_edge_cost(&self, belief_node_to_node: &[usize], u_id: usize, v_id: usize) -> Option<f64> {
+		let (u, v) = (&self.belief_graph.nodes[u_id], &self.belief_graph.nodes[v_id]);
+		let cost = self.graph.edge_cost(belief_node_to_node[u_id], belief_node_to_node[v_id])
+			.unwrap_or_else(|| self.fns.cost_evaluator(&u.state, &v.state));
+
+		self.fns.belief_cost_evaluator(&u.state, &v.state, cost, &u.belief_state)
+	}
+
+	pub fn extract_policy(&self) -> Policy<N> {
+		let belief_node_to_node = self.belief_node_to_node();
+		extract_policy_with_edge_filter(&self.belief_graph, &self.expected_costs_to_goals, self.observation_cost, |u_id, v_id| self.belief_edge_cost(&belief_node_to_node, u_id, v_id).is_some())
+	}
+
+	/// belief node of the graph node for each reachable belief state, None if incompatible or dropped
+	pub fn belief_nodes(&self, node_id: usize) -> &[Option<usize>] {
+		&self.node_to_belief_nodes[node_id]
+	}
+
+	/// graph node of each belief node of the current belief graph
+	pub fn belief_node_to_node(&self) -> Vec<usize> {
+		let mut node_ids = vec![0; self.belief_graph.nodes.len()];
+		for (id, belief_node_ids) in self.node_to_belief_nodes.iter().enumerate() {
+			for &belief_node_id in belief_node_ids.iter().flatten() {
+				node_ids[belief_node_id] = id;
+			}
+		}
+		node_ids
+	}
+
+	/// edges (from, to) of the graph taken by a policy extracted from the current belief graph, sorted without duplicates
+	/// the policy is matched to the belief graph from the root, the observations don't move and aren't edges
+	pub fn used_prm_edges(&self, policy: &Policy<N>) -> Vec<(usize, usize)> {
+		let node_ids = self.belief_node_to_node();
+		let mut edges = Vec::new();
+
+		let mut lifo = vec![(0, 0)]; // policy_node, belief_graph_node
+		while let Some((policy_node_id, belief_node_id)) = lifo.pop() {
+			for &child_policy_id in &policy.nodes[policy_node_id].children {
+				let child = &policy.nodes[child_policy_id];
+				let child_belief_node_id = *self.belief_graph.nodes[belief_node_id].children.iter()
+					.find(|&&id| self.belief_graph.nodes[id].state == child.state && self.belief_graph.nodes[id].belief_state == child.belief_state)
+					.expect("the policy should be extracted from the belief graph");
+
+				let (from, to) = (node_ids[belief_node_id], node_ids[child_belief_node_id]);
+				if from != to {
+					edges.push((from, to));
+				}
+
+				lifo.push((child_policy_id, child_belief_node_id));
+			}
+		}
+
+		edges.sort_unstable();
+		edges.dedup();
+		edges
+	}
+
+	/// state and expected cost of each graph node in the given reachable belief state (INFINITY if it has no belief node), e.g. for heatmaps
+	pub fn cost_field_for_belief(&self, belief_id: usize) -> Vec<([f64; N], f64)> {
+		self.graph.nodes.iter().zip(&self.node_to_belief_nodes)
+			.map(|(node, belief_node_ids)| {
+				let cost = belief_node_ids[belief_id].map_or(std::f64::INFINITY, |belief_node_id| self.expected_costs_to_goals[belief_node_id]);
+				(node.state, cost)
+			})
+			.collect()
+	}
+
+	pub fn belief_graph(&self) -> &BeliefGraph<N> {
+		&self.belief_graph
+	}
+
+	/// whether the belief graph has an observation edge, false after a shortest path planning (the belief graph is then empty)
+	pub fn has_observations(&self) -> bool {
+		self.belief_graph.nodes.iter().any(|node| node.node_type == BeliefNodeType::Observation)
+	}
+
+	pub fn expected_costs(&self) -> &[f64] {
+		&self.expected_costs_to_goals
+	}
+
+	/// worlds in which the node is known to be reachable from the start (conservative: without propagate_reachability, the edges added before the node was reached aren't replayed)
+	pub fn reachability_of(&self, node_id: usize) -> &WorldMask {
+		self.conservative_reachability.reachability(node_id)
+	}
+
+	/// whether a final node is reached in each world, the condition for grow_graph to stop after n_iter_min iterations
+	pub fn is_complete(&self) -> bool {
+		self.conservative_reachability.is_final_set_complete()
+	}
+
+	pub fn print_summary(&self) {
+		println!("number of iterations:{}", self.n_it);
+		self.graph.print_summary();
+	}
+
+	pub fn timings(&self) -> &PhaseTimings {
+		&self.timings
+	}
+
+	pub fn run_summary(&self) -> RunSummary {
+		RunSummary {
+			max_step: self.growth_parameters.map(|parameters| parameters.max_step),
+			search_radius: self.growth_parameters.map(|parameters| parameters.search_radius),
+			n_iter_min: self.growth_parameters.map(|parameters| parameters.n_iter_min),
+			n_iter_max: self.growth_parameters.map(|parameters| parameters.n_iter_max),
+			seed: self.continuous_sampler.seed(),
+			n_iterations: self.n_it,
+			n_nodes: self.graph.nodes.len(),
+			n_edges: self.graph.nodes.iter().map(|node| node.children.len()).sum(),
+			n_belief_nodes: self.belief_graph.nodes.len(),
+			n_worlds: self.n_worlds,
+			final_set_complete: self.conservative_reachability.is_final_set_complete(),
+			root_expected_cost: self.root_cost,
+			growth_time: self.timings.growth.as_secs_f64(),
+			belief_graph_time: self.timings.belief_graph.as_secs_f64(),
+			dp_time: self.timings.dp.as_secs_f64(),
+			extraction_time: self.timings.extraction.as_secs_f64(),
+		}
+	}
+
+	/// run_summary, as json
+	pub fn export_run_summary(&self, filepath: &str) -> Result<(), &'static str> {
+		let writer = BufWriter::new(File::create(filepath).map_err(|_| "can't create file")?);
+		serde_json::to_writer_pretty(writer, &self.run_summary()).map_err(|_| "can't write the run summary")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+use crate::belief_graph;
+use crate::test_utils::*;
+
+    use super::*;
+
+#[test]
+fn test_grow_graph_to_captured_goal() {
+	let center = [0.8, 0.7];
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &FreeSpaceFuncs{n_worlds: 1});
+
+	prm.grow_graph(&[-0.5, -0.5], ball_goal(center, 0.05, 1), 0.1, 5.0, 100, 10000).expect("graph not grown up to solution");
+
+	assert!(!prm.final_node_ids.is_empty());
+	for &id in &prm.final_node_ids {
+		assert!(norm2(&prm.graph.nodes[id].state, &center) < 0.05);
+	}
+}
+
+#[test]
+fn test_observation_outcomes() {
+	// the status of the zone (open in world 1 only) is observed from the origin
+	let prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+					   DiscreteSampler::new(),
+					   &OriginObservationFuncs{});
+
+	let outcomes = prm.observation_outcomes(&[0.0, 0.0], &vec![0.3, 0.7]);
+	assert_eq!(outcomes.len(), 2);
+	assert_eq!(outcomes[0].0, vec![1.0, 0.0]);
+	assert!((outcomes[0].1 - 0.3).abs() < 0.000001);
+	assert!((outcomes.iter().map(|(_, p)| p).sum::<f64>() - 1.0).abs() < 0.000001);
+
+	// nothing to observe
+	assert_eq!(prm.observation_outcomes(&[0.5, 0.0], &vec![0.3, 0.7]), vec![(vec![0.3, 0.7], 1.0)]);
+}
+
+#[test]
+fn test_plan_with_soft_observation() {
+	// the zone (open in world 1 only) is reported from the origin with an accuracy of 0.75, only once (from the prior)
+	// the cost to the goal grows with the probability of world 0
+	struct Funcs { prior: BeliefState }
+	impl Funcs {
+		fn reports(&self, belief_state: &BeliefState) -> Vec<(BeliefState, f64)> {
+			let model = RangeDetectionModel { max_range: 1.0, falloff: 1.0 };
+			model.observe(&[0.0, 0.0], &[0.0, 0.5], &bitvec![0, 1], belief_state)
+		}
+	}
+	impl PRMFuncs<2> for Funcs {
+		fn belief_cost_evaluator(&self, _a: &[f64; 2], _b: &[f64; 2], cost: f64, belief_state: &BeliefState) -> Option<f64> {
+			Some(cost * (1.0 + 10.0 * belief_state[0]))
+		}
+
+		fn reachable_belief_states(&self, belief_state: &BeliefState) -> Vec<BeliefState> {
+			std::iter::once(belief_state.clone()).chain(self.reports(belief_state).into_iter().map(|(b, _)| b)).collect()
+		}
+
+		fn observe(&self, state: &[f64; 2], belief_state: &BeliefState) -> Vec<BeliefState> {
+			self.observe_with_probabilities(state, belief_state).into_iter().map(|(b, _)| b).collect()
+		}
+
+		fn observe_with_probabilities(&self, state: &[f64; 2], belief_state: &BeliefState) -> Vec<(BeliefState, f64)> {
+			match *state == [0.0, 0.0] && beliefs_equal(belief_state, &self.prior, BELIEF_STATE_EQUALITY_TOLERANCE) {
+				true => self.reports(belief_state),
+				false => vec![(belief_state.clone(), 1.0)],
+			}
+		}
+	}
+
+	let funcs = Funcs{ prior: vec![0.2, 0.8] };
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &funcs);
+	prm.n_worlds = 2;
+	prm.graph.add_node([0.0, 0.0], bitvec![1, 1]);
+	prm.graph.add_node([1.0, 0.0], bitvec![1, 1]);
+	prm.graph.add_bi_edge(0, 1, bitvec![1, 1]);
+	prm.final_node_ids.push(1);
+
+	let policy = prm.plan_belief_space(&funcs.prior).expect("invalid start belief state");
+	assert_eq!(policy.validate(), Ok(()));
+	assert_eq!(prm.belief_graph().nodes[0].node_type, BeliefNodeType::Observation);
+
+	// the zone is reported open with probability 0.2 * 0.25 + 0.8 * 0.75, both worlds remain possible
+	let probabilities = observation_probabilities(prm.belief_graph(), 0);
+	assert_eq!(probabilities.len(), 2);
+	let open_probability = probabilities.iter().find(|(id, _)| prm.belief_graph().nodes[*id].belief_state[1] > 0.8).unwrap().1;
+	assert!((open_probability - 0.65).abs() < 1e-9);
+
+	// the cost is linear in the belief, which averages to the prior over the outcomes: observing doesn't change the expected cost
+	assert!((prm.expected_costs()[0] - 3.0).abs() < 1e-9);
+	assert!((policy.nodes[0].cost_to_goal - 3.0).abs() < 1e-9);
+
+	let tree = policy.to_decision_tree();
+	let mut branch_probabilities: Vec<f64> = tree.nodes[0].branches.iter().map(|branch| branch.probability).collect();
+	branch_probabilities.sort_by(f64::total_cmp);
+	assert!((branch_probabilities[0] - 0.35).abs() < 1e-9 && (branch_probabilities[1] - 0.65).abs() < 1e-9);
+	assert!((policy.success_probability() - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_apply_observation() {
+	// one zone (vertical band in the middle), open in world 1 only
+	struct Funcs {}
+	impl PRMFuncs<2> for Funcs {
+		fn state_validity(&self, state: &[f64; 2]) -> Option<WorldMask> {
+			if state[0].abs() < 0.1 && state[1].abs() < 0.5 { Some(bitvec![0, 1]) } else { Some(bitvec![1, 1]) }
+		}
+
+		fn transition_validator(&self, from: &PRMNode<2>, to: &PRMNode<2>) -> Option<WorldMask> {
+			let crosses_zone = densify_path(&[from.state, to.state], 0.01).iter().any(|s| s[0].abs() < 0.1 && s[1].abs() < 0.5);
+			if crosses_zone { Some(bitvec![0, 1]) } else { Some(bitvec![1, 1]) }
+		}
+	}
+
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &Funcs{});
+	prm.grow_graph(&[-0.5, 0.0], ball_goal([0.5, 0.0], 0.05, 2), 0.1, 5.0, 1000, 100000).expect("graph not grown up to solution");
+
+	let zone_edges = |graph: &PRMGraph<2>| graph.nodes.iter().map(|node| node.children.iter().filter(|edge| edge.validity == bitvec![0, 1]).count()).sum::<usize>();
+	let n_edges = |graph: &PRMGraph<2>| graph.nodes.iter().map(|node| node.children.len()).sum::<usize>();
+
+	let n_zone_edges = zone_edges(&prm.graph);
+	let n_edges_before = n_edges(&prm.graph);
+	assert!(n_zone_edges > 0);
+	assert!(prm.graph.nodes.iter().enumerate().any(|(id, _)| prm.conservative_reachability.reachability(id)[1]));
+
+	// closed: the edges through the zone only are removed
+	assert_eq!(prm.apply_observation(0, false), n_zone_edges);
+	assert_eq!(n_edges(&prm.graph), n_edges_before - n_zone_edges);
+	assert_eq!(zone_edges(&prm.graph), 0);
+	for node in &prm.graph.nodes {
+		assert!(!node.valid_in(1));
+		for edge in node.children.iter().chain(node.parents.iter()) {
+			assert_eq!(edge.validity, bitvec![1, 0]);
+		}
+	}
+
+	for id in 0..prm.graph.nodes.len() {
+		assert!(!prm.conservative_reachability.reachability(id)[1]);
+	}
+
+	// world 0 is still solved
+	assert!(!prm.final_node_ids.is_empty());
+	let policy = prm.plan_belief_space(&vec![1.0, 0.0]).expect("invalid start belief state");
+	for state in densify_path(&policy.path_to_leaf(0), 0.01) {
+		assert!(!(state[0].abs() < 0.1 && state[1].abs() < 0.5));
+	}
+}
+
+#[test]
+fn test_grow_graph_progress() {
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &FreeSpaceFuncs{n_worlds: 1});
+
+	let mut fractions = Vec::new();
+	prm.grow_graph_with_progress(&[-0.5, -0.5], ball_goal([0.8, 0.7], 0.05, 1), &GrowthParameters::default(),
+		|_, reachability| fractions.push(reachability.completion_fraction())).expect("graph not grown up to solution");
+
+	assert_eq!(fractions.len(), prm.n_it);
+	assert_eq!(fractions[0], 0.0);
+	assert_eq!(*fractions.last().unwrap(), 1.0);
+	assert!(fractions.windows(2).all(|w| w[0] <= w[1]));
+}
+
+#[test]
+fn test_grow_graph_with_sampler() {
+	let funcs = FreeSpaceFuncs{n_worlds: 1};
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &funcs);
+
+	// upper right quadrant only
+	let mut sampler = |continuous_sampler: &mut ContinuousSampler<2>| {
+		let state = continuous_sampler.sample();
+		[state[0].abs(), state[1].abs()]
+	};
+
+	let parameters = GrowthParameters{ max_step: 0.1, n_iter_min: 500, ..Default::default() };
+	prm.grow_graph_with_sampler(&[0.5, 0.5], ball_goal([0.9, 0.9], 0.1, 1), &parameters, &mut sampler).unwrap();
+
+	assert!(prm.graph.nodes.len() > 100);
+	assert!(prm.graph.nodes.iter().all(|node| node.state[0] >= 0.0 && node.state[1] >= 0.0));
+}
+
+#[test]
+fn test_cached_edge_costs() {
+	struct Funcs {}
+	impl PRMFuncs<2> for Funcs {
+		fn state_validity(&self, _state: &[f64; 2]) -> Option<WorldMask> {
+			Some(bitvec![1])
+		}
+
+		fn cost_evaluator(&self, a: &[f64; 2], b: &[f64; 2]) -> f64 {
+			norm1(a, b)
+		}
+	}
+
+	let fns = Funcs{};
+	let mut prm = PRM::new(ContinuousSampler::new_with_seed([-1.0, -1.0], [1.0, 1.0], 0),
+						   DiscreteSampler::new_with_seed(0),
+						   &fns);
+
+	prm.grow_graph(&[-0.5, -0.5], ball_goal([0.8, 0.7], 0.05, 1), 0.1, 5.0, 500, 10000).expect("graph not grown up to solution");
+
+	for (id, node) in prm.graph.nodes.iter().enumerate() {
+		for edge in &node.children {
+			assert_eq!(edge.cost, fns.cost_evaluator(&node.state, &prm.graph.nodes[edge.id].state));
+			assert_eq!(prm.graph.edge_cost(id, edge.id), Some(edge.cost));
+		}
+		for edge in &node.parents {
+			assert_eq!(edge.cost, fns.cost_evaluator(&prm.graph.nodes[edge.id].state, &node.state));
+		}
+	}
+
+	// the belief space DP gives the same costs as with the evaluator
+	prm.build_belief_graph(&vec![1.0]).unwrap();
+	prm.compute_expected_costs_to_goals();
+
+	let final_belief_node_ids: Vec<usize> = prm.final_node_ids.iter().filter_map(|&id| prm.node_to_belief_nodes[id][0]).collect();
+	let expected_costs = conditional_dijkstra(prm.belief_graph(), &final_belief_node_ids, ignore_belief(|a: &[f64; 2], b: &[f64; 2]| fns.cost_evaluator(a, b)));
+	assert_eq!(prm.expected_costs(), &expected_costs[..]);
+}
+
+#[test]
+fn test_roadmap_queries() {
+	let m = crate::analytic_map::AnalyticMap::new(|state| norm2(state, &[0.0, 0.0]) > 0.3, 0.01);
+
+	let mut prm = PRM::new(ContinuousSampler::new_with_seed([-1.0, -1.0], [1.0, 1.0], 0),
+						   DiscreteSampler::new_with_seed(0),
+						   &m);
+
+	let parameters = GrowthParameters{ max_step: 0.2, search_radius: 5.0, n_iter_min: 2000, ..Default::default() };
+	prm.grow_roadmap(&parameters);
+	let n_nodes = prm.graph.nodes.len();
+
+	for (start, goal) in &[([-0.6, 0.0], [0.6, 0.0]), ([0.0, -0.8], [0.0, 0.8]), ([-0.7, -0.7], [0.5, 0.6])] {
+		let path = prm.query(start, goal).expect("no path found");
+
+		assert_eq!(path.first(), Some(start));
+		assert_eq!(path.last(), Some(goal));
+		for (a, b) in pairwise_iter(&path) {
+			assert!(m.is_transition_valid(a, b));
+		}
+
+		let length: f64 = pairwise_iter(&path).map(|(a, b)| norm2(a, b)).sum();
+		assert!(length >= norm2(start, goal));
+		assert!(length < 1.5 * norm2(start, goal) + 0.5);
+	}
+
+	// the roadmap is reused as is
+	assert_eq!(prm.graph.nodes.len(), n_nodes);
+
+	// invalid query
+	assert_eq!(prm.query(&[0.0, 0.0], &[0.6, 0.0]), None);
+
+	// empty roadmap
+	let mut prm = PRM::new(ContinuousSampler::new_with_seed([-1.0, -1.0], [1.0, 1.0], 0),
+						   DiscreteSampler::new_with_seed(0),
+						   &m);
+	prm.grow_roadmap(&GrowthParameters{ n_iter_min: 0, ..parameters });
+	assert!(prm.graph.nodes.is_empty());
+	assert_eq!(prm.query(&[-0.6, 0.0], &[0.6, 0.0]), None);
+}
+
+#[test]
+fn test_builder_matches_positional_api() {
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &FreeSpaceFuncs{n_worlds: 1});
+	prm.grow_graph(&[-0.5, -0.5], ball_goal([0.8, 0.7], 0.05, 1), 0.1, 5.0, 200, 10000).expect("graph not grown up to solution");
+
+	let built_prm = PRMBuilder::new(&FreeSpaceFuncs{n_worlds: 1}, [-1.0, -1.0], [1.0, 1.0])
+		.max_step(0.1)
+		.search_radius(5.0)
+		.iter_bounds(200, 10000)
+		.seed(0)
+		.grow(&[-0.5, -0.5], ball_goal([0.8, 0.7], 0.05, 1))
+		.expect("graph not grown up to solution");
+
+	assert_eq!(built_prm.n_it, prm.n_it);
+	assert_eq!(built_prm.final_node_ids, prm.final_node_ids);
+	assert_eq!(built_prm.graph.nodes.len(), prm.graph.nodes.len());
+	for (a, b) in built_prm.graph.nodes.iter().zip(&prm.graph.nodes) {
+		assert_eq!(a.state, b.state);
+		assert_eq!(a.children.len(), b.children.len());
+	}
+
+	// timeout
+	let result = PRMBuilder::new(&FreeSpaceFuncs{n_worlds: 1}, [-1.0, -1.0], [1.0, 1.0])
+		.timeout(Duration::from_secs(0))
+		.grow(&[-0.5, -0.5], ball_goal([0.8, 0.7], 0.05, 1));
+	assert!(result.is_err());
+}
+
+#[test]
+fn test_export_run_summary() {
+	let mut prm = PRMBuilder::new(&FreeSpaceFuncs{n_worlds: 1}, [-1.0, -1.0], [1.0, 1.0])
+		.max_step(0.1)
+		.iter_bounds(200, 10000)
+		.seed(3)
+		.grow(&[-0.5, -0.5], ball_goal([0.8, 0.7], 0.05, 1))
+		.expect("graph not grown up to solution");
+	assert_eq!(prm.run_summary().root_expected_cost, None);
+
+	let policy = prm.plan_belief_space(&vec![1.0]).unwrap();
+
+	let filepath = std::env::temp_dir().join("po_rrt_test_run_summary.json");
+	prm.export_run_summary(filepath.to_str().unwrap()).unwrap();
+	let reader = std::io::BufReader::new(File::open(&filepath).unwrap());
+	let summary: RunSummary = serde_json::from_reader(reader).unwrap();
+	std::fs::remove_file(&filepath).unwrap();
+
+	assert_eq!(summary, prm.run_summary());
+	assert_eq!((summary.max_step, summary.search_radius), (Some(0.1), Some(5.0)));
+	assert_eq!((summary.n_iter_min, summary.n_iter_max, summary.seed), (Some(200), Some(10000), Some(3)));
+	assert_eq!(summary.n_iterations, prm.n_it);
+	assert_eq!(summary.n_nodes, prm.graph.nodes.len());
+	assert!(summary.n_edges >= 2 * (summary.n_nodes - 1));
+	assert_eq!((summary.n_belief_nodes, summary.n_worlds), (0, 1)); // single belief, no belief graph
+	assert!(summary.final_set_complete);
+	assert_eq!(summary.root_expected_cost, Some(policy.nodes[0].cost_to_goal));
+	assert!(summary.growth_time > 0.0 && summary.dp_time > 0.0);
+}
+
+#[test]
+fn test_growth_with_unreachable_world() {
+	// the start is only valid in world 0, no node is ever reachable in world 1
+	struct Funcs {}
+	impl PRMFuncs<2> for Funcs {
+		fn state_validity(&self, state: &[f64; 2]) -> Option<WorldMask> {
+			if state[0] < -0.4 { Some(bitvec![1, 0]) } else { Some(bitvec![1, 1]) }
+		}
+	}
+
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &Funcs{});
+
+	let result = prm.grow_graph(&[-0.5, -0.5], ball_goal([0.8, 0.7], 0.05, 2), 0.1, 5.0, 500, 500);
+	assert!(result.is_err());
+	assert_eq!(prm.n_it, 500);
+	assert!(prm.graph.nodes.len() > 1);
+	assert!((0..prm.graph.nodes.len()).all(|id| !prm.reachability_of(id)[1]));
+}
+
+#[test]
+fn test_grow_graph_with_norm_inf() {
+	struct Funcs {}
+	impl PRMFuncs<2> for Funcs {
+		fn state_validity(&self, _state: &[f64; 2]) -> Option<WorldMask> {
+			Some(bitvec![1])
+		}
+
+		fn norm(&self) -> Norm {
+			Norm::LInf
+		}
+	}
+
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &Funcs{});
+	prm.grow_graph(&[-0.5, -0.5], ball_goal([0.8, 0.7], 0.05, 1), 0.1, 5.0, 1000, 100000).expect("graph not grown up to solution");
+
+	// the edges are clamped in norm_inf, the diagonal ones are longer than max_step in norm2
+	let edges: Vec<(&[f64; 2], &[f64; 2], f64)> = prm.graph.nodes.iter()
+		.flat_map(|node| node.children.iter().map(move |edge| (&node.state, edge.id, edge.cost)))
+		.map(|(from, to, cost)| (from, &prm.graph.nodes[to].state, cost))
+		.collect();
+	assert!(edges.iter().all(|(a, b, _)| norm_inf(a, b) <= 0.1 + 1e-9));
+	assert!(edges.iter().any(|(a, b, _)| norm2(a, b) > 0.1));
+	assert!(edges.iter().all(|(a, b, cost)| *cost == norm_inf(a, b)));
+}
+
+#[test]
+fn test_clamp_to_bounds() {
+	// every sample is far out of the bounds, the growth drifts towards it
+	let grow = |clamp_to_bounds| {
+		let parameters = GrowthParameters{ max_step: 0.1, n_iter_min: 50, n_iter_max: 50, clamp_to_bounds, ..Default::default() };
+		let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+							   DiscreteSampler::new(),
+							   &FreeSpaceFuncs{n_worlds: 1});
+		let _ = prm.grow_graph_with_sampler(&[0.5, 0.0], ball_goal([0.0, 0.0], 0.05, 1), &parameters, &mut |_| [5.0, 0.0]);
+		prm.graph.nodes.iter().map(|node| node.state).collect::<Vec<_>>()
+	};
+
+	assert!(grow(false).iter().any(|state| state[0] > 1.0));
+
+	let states = grow(true);
+	assert!(states.len() > 1);
+	assert!(states.iter().all(|state| (-1.0..=1.0).contains(&state[0]) && (-1.0..=1.0).contains(&state[1])));
+	assert!(states.iter().any(|state| state[0] == 1.0));
+}
+
+#[test]
+fn test_focus_unsolved_worlds() {
+	// the goal only exists in world 0, world 1 is never solved
+	let goal = |state: &[f64; 2]| {
+		let mut finality = bitvec![0; 2];
+		finality.set(0, norm2(state, &[0.8, 0.7]) < 0.1);
+		finality
+	};
+
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &FreeSpaceFuncs{n_worlds: 2});
+	let parameters = GrowthParameters{ max_step: 0.1, n_iter_min: 500, n_iter_max: 500, ..Default::default() };
+	assert!(prm.grow_graph_with(&[-0.5, -0.5], goal, &parameters).is_err());
+	assert_eq!(prm.conservative_reachability.reached_worlds(), bitvec![1, 0]);
+
+	// over a window of iterations, the solved world isn't sampled anymore
+	let count_world_0 = |prm: &mut PRM<FreeSpaceFuncs, 2>, focus_unsolved_worlds| (0..1000).filter(|_| prm.sample_world(focus_unsolved_worlds) == 0).count();
+	assert!(count_world_0(&mut prm, false) > 400);
+	assert_eq!(count_world_0(&mut prm, true), 0);
+}
+
+#[test]
+fn test_add_goal_state() {
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &FreeSpaceFuncs{n_worlds: 1});
+	assert!(prm.add_goal_state([0.8, 0.7], bitvec![1]).is_err());
+
+	// no sampled state is a goal
+	let parameters = GrowthParameters{ max_step: 0.1, n_iter_min: 300, n_iter_max: 300, ..Default::default() };
+	assert!(prm.grow_graph_with(&[-0.5, -0.5], |_| bitvec![0], &parameters).is_err());
+
+	let n_nodes = prm.graph.nodes.len();
+	let goal_id = prm.add_goal_state([0.8, 0.7], bitvec![1]).unwrap();
+	assert_eq!(goal_id, n_nodes);
+	assert!(!prm.graph.nodes[goal_id].parents.is_empty());
+	assert_eq!(prm.final_node_ids, vec![goal_id]);
+
+	let policy = prm.plan_belief_space(&vec![1.0]).unwrap();
+	assert_eq!(policy.leaf(0).state, [0.8, 0.7]);
+	assert_eq!(*policy.path_to_leaf(0).last().unwrap(), [0.8, 0.7]);
+}
+
+#[test]
+fn test_grow_iter() {
+	// free space around a disc
+	struct Funcs {}
+	impl PRMFuncs<2> for Funcs {
+		fn state_validity(&self, state: &[f64; 2]) -> Option<WorldMask> {
+			if norm2(state, &[0.0, 0.0]) > 0.3 { Some(bitvec![1]) } else { None }
+		}
+	}
+
+	let parameters = GrowthParameters{ max_step: 0.1, n_iter_min: 200, ..Default::default() };
+	let goal = ball_goal([0.8, 0.8], 0.1, 1);
+
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &Funcs{});
+	let mut steps = prm.grow_iter(&[-0.8, -0.8], &goal, &parameters);
+
+	// one node per step
+	for _ in 0..50 {
+		let n_nodes = steps.prm().graph.nodes.len();
+		match steps.next() {
+			Some(GrowthStep::Node{id, ..}) => {
+				assert_eq!(id, n_nodes);
+				assert_eq!(steps.prm().graph.nodes.len(), n_nodes + 1);
+			},
+			step => panic!("unexpected step {:?}", step),
+		}
+	}
+
+	let last = steps.by_ref().last();
+	assert_eq!(last, Some(GrowthStep::Done(Ok(()))));
+	assert_eq!(steps.next(), None);
+
+	// same graph as the growth in one go
+	let mut reference = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &Funcs{});
+	reference.grow_graph_with(&[-0.8, -0.8], &goal, &parameters).unwrap();
+
+	assert_eq!(prm.n_it, reference.n_it);
+	assert_eq!(prm.graph.nodes.len(), reference.graph.nodes.len());
+	assert_eq!(prm.final_node_ids, reference.final_node_ids);
+}
+
+#[test]
+fn test_cancel_growth_and_planning() {
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &FreeSpaceFuncs{n_worlds: 1});
+
+	let cancel_flag = Arc::new(AtomicBool::new(false));
+	prm.set_cancel_flag(cancel_flag.clone());
+
+	let canceller = {
+		let cancel_flag = cancel_flag.clone();
+		std::thread::spawn(move || {
+			std::thread::sleep(Duration::from_millis(100));
+			cancel_flag.store(true, Ordering::Relaxed);
+		})
+	};
+
+	// the goal is out of bounds, the growth only stops when cancelled
+	let start_time = Instant::now();
+	let result = prm.grow_graph(&[-0.5, -0.5], ball_goal([5.0, 5.0], 0.05, 1), 0.1, 5.0, 100, usize::MAX);
+	canceller.join().unwrap();
+
+	assert_eq!(result, Err(PlanError::Cancelled));
+	assert!(start_time.elapsed() < Duration::from_secs(10));
+
+	// the partial graph is consistent
+	let n_nodes = prm.graph.nodes.len();
+	assert!(n_nodes > 1);
+	assert!(prm.n_it > 0);
+	for node in &prm.graph.nodes {
+		assert!(node.children.iter().chain(&node.parents).all(|edge| edge.id < n_nodes));
+	}
+
+	// planning is cancelled as well
+	assert_eq!(prm.plan_belief_space(&vec![1.0]).err(), Some(PlanError::Cancelled));
+
+	assert_eq!(prm.extend_graph(ball_goal([5.0, 5.0], 0.05, 1), &GrowthParameters::default(), 10), Err(PlanError::Cancelled));
+
+	// the growth resumes once the flag is cleared
+	cancel_flag.store(false, Ordering::Relaxed);
+	let n_it = prm.n_it;
+	assert_eq!(prm.extend_graph(ball_goal([5.0, 5.0], 0.05, 1), &GrowthParameters::default(), 10), Err(PlanError::FinalNodesNotReached));
+	assert_eq!(prm.n_it, n_it + 10);
+	assert!(prm.graph.nodes.len() >= n_nodes);
+}
+
+#[test]
+fn test_plan_on_map2_pomdp() {
+	let mut m = Map::open("data/map2.pgm", [-1.0, -1.0], [1.0, 1.0]);
+	m.add_zones("data/map2_zone_ids.pgm", 0.2);
+
+	fn goal(state: &[f64; 2]) -> WorldMask {
+		bitvec![if (state[0] - 0.55).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05 { 1 } else { 0 }; 4]
+	}
+
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &m);
+
+	prm.grow_graph(&[0.55, -0.8], goal, 0.1, 5.0, 2000, 100000).expect("graph not grown up to solution");
+	prm.print_summary();
+	let policy = prm.plan_belief_space(&vec![0.1, 0.1, 0.1, 0.7]).expect("invalid start belief state");
+
+	let mut m2 = m.clone();
+	m2.resize(5);
+	m2.draw_full_graph(&prm.graph);
+	m2.draw_zones_observability();
+	m2.draw_policy(&policy);
+	m2.save("results/test_prm_on_map2_pomdp");
+}
+
+#[test]
+fn test_plan_deterministic_on_map0() {
+	let m = Map::open("data/map0.pgm", [-1.0, -1.0], [1.0, 1.0]);
+
+	fn goal(state: &[f64; 2]) -> bool {
+		(state[0] - 0.55).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05
+	}
+
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &m);
+
+	let parameters = GrowthParameters{ max_step: 0.1, n_iter_min: 2000, ..Default::default() };
+	let path = prm.plan_deterministic(&[0.55, -0.8], goal, &parameters).expect("no path found");
+
+	assert_eq!(path[0], [0.55, -0.8]);
+	assert!(goal(path.last().unwrap()));
+	for (a, b) in pairwise_iter(&path) {
+		assert!(crate::rrt::RRTFuncs::transition_validator(&m, a, b) == crate::rrt::Reachable::Always);
+	}
+}
+
+#[test]
+fn test_plan_deterministic() {
+	let m = crate::analytic_map::AnalyticMap::new(|state| norm2(state, &[0.0, 0.0]) > 0.3, 0.01);
+
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &m);
+
+	let goal = |state: &[f64; 2]| norm2(state, &[0.0, 0.8]) < 0.1;
+	let parameters = GrowthParameters{ max_step: 0.1, n_iter_min: 2000, ..Default::default() };
+	let path = prm.plan_deterministic(&[0.0, -0.8], goal, &parameters).expect("no path found");
+
+	assert_eq!(path[0], [0.0, -0.8]);
+	assert!(goal(path.last().unwrap()));
+	for (a, b) in pairwise_iter(&path) {
+		assert!(m.is_transition_valid(a, b));
+	}
+
+	// several worlds
+	let funcs = FreeSpaceFuncs{n_worlds: 2};
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &funcs);
+	assert_eq!(prm.plan_deterministic(&[0.0, -0.8], goal, &parameters), Err("deterministic planning needs a single world"));
+}
+
+#[test]
+fn test_plan_until_converged_on_map2() {
+	let mut m = Map::open("data/map2.pgm", [-1.0, -1.0], [1.0, 1.0]);
+	m.add_zones("data/map2_zone_ids.pgm", 0.2);
+
+	fn goal(state: &[f64; 2]) -> WorldMask {
+		bitvec![if (state[0] - 0.55).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05 { 1 } else { 0 }; 4]
+	}
+
+	let mut prm = PRM::new(ContinuousSampler::new_with_seed([-1.0, -1.0], [1.0, 1.0], 0),
+						   DiscreteSampler::new_with_seed(0),
+						   &m);
+
+	prm.grow_graph(&[0.55, -0.8], goal, 0.1, 5.0, 2000, 100000).expect("graph not grown up to solution");
+
+	let parameters = GrowthParameters{ max_step: 0.1, search_radius: 5.0, n_iter_min: 1000, ..Default::default() };
+	let tol = 0.01;
+	let (policy, cost_history) = prm.plan_until_converged(&vec![0.1, 0.1, 0.1, 0.7], goal, &parameters, tol, 20).expect("invalid start belief state");
+
+	assert!(cost_history.len() >= 2);
+	for (previous, cost) in pairwise_iter(&cost_history) {
+		assert!(cost <= &(previous + 0.000001));
+	}
+
+	let n = cost_history.len();
+	assert!(cost_history[n - 2] - cost_history[n - 1] <= tol);
+	assert_eq!(policy.nodes[0].cost_to_goal, cost_history[n - 1]);
+}
+
+#[test]
+fn test_plan_on_map4_pomdp() {
+	let mut m = Map::open("data/map4.pgm", [-1.0, -1.0], [1.0, 1.0]);
+	m.add_zones("data/map4_zone_ids.pgm", 0.15);
+
+	fn goal(state: &[f64; 2]) -> WorldMask {
+		bitvec![if (state[0] + 0.55).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05 { 1 } else { 0 }; 16]
+	}
+
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &m);
+
+	prm.grow_graph(&[0.55, -0.8], goal, 0.05, 5.0, 1000, 100000).expect("graph not grown up to solution");
+	prm.print_summary();
+	let policy = prm.plan_belief_space( &vec![1.0/16.0; 16]).expect("invalid start belief state");
+
+	let mut m2 = m.clone();
+	m2.resize(5);
+	m2.draw_full_graph(&prm.graph);
+	m2.draw_zones_observability();
+	m2.draw_policy(&policy);
+	m2.save("results/test_prm_on_map4_pomdp");
+}
+
+#[test]
+fn test_plan_on_map1_fov_pomdp() {
+	let mut m = Map::open("data/map1_fov.pgm", [-1.0, -1.0], [1.0, 1.0]);
+	m.add_zones("data/map1_fov_zone_ids.pgm", 1.5);
+
+	fn goal(state: &[f64; 2]) -> WorldMask {
+		bitvec![if (state[0] - 0.85).abs() < 0.05 && (state[1] - 0.37).abs() < 0.05 { 1 } else { 0 }; 2]
+	}
+
+	let mut prm = deterministic_prm(&m, 0);
+
+	prm.grow_graph_with(&[-0.37, 0.37], goal, &map_growth_parameters()).expect("graph not grown up to solution");
+	prm.print_summary();
+	let policy = prm.plan_belief_space(&vec![0.5, 0.5]).expect("invalid start belief state");
+	assert_reaches_goal_in_all_worlds(&policy, goal);
+	assert_finite_root_cost(&policy);
+
+	let mut m2 = m.clone();
+	m2.resize(5);
+	m2.draw_full_graph(&prm.graph);
+	m2.draw_zones_observability();
+	m2.draw_policy(&policy);
+	m2.save("results/test_prm_on_map1_fov_pomdp");
+}
+
+#[test]
+fn test_plan_on_map2_fov_pomdp() {
+	let mut m = Map::open("data/map2_fov.pgm", [-1.0, -1.0], [1.0, 1.0]);
+	m.add_zones("data/map2_fov_zone_ids.pgm", 1.1);
+
+	fn goal(state: &[f64; 2]) -> WorldMask {
+		bitvec![if (state[0] - 0.775).abs() < 0.05 && (state[1] - 0.3).abs() < 0.05 { 1 } else { 0 }; 4]
+	}
+
+	let mut prm = deterministic_prm(&m, 0);
+
+	prm.grow_graph_with(&[0.35, -0.125], goal, &map_growth_parameters()).expect("graph not grown up to solution");
+	prm.print_summary();
+	let policy = prm.plan_belief_space(&vec![0.25, 0.25, 0.25, 0.25]).expect("invalid start belief state");
+	assert_reaches_goal_in_all_worlds(&policy, goal);
+	assert_finite_root_cost(&policy);
+
+	let mut m2 = m.clone();
+	m2.resize(5);
+	m2.draw_full_graph(&prm.graph);
+	m2.draw_zones_observability();
+	m2.draw_policy(&policy);
+	m2.save("results/test_prm_on_map2_fov_pomdp");
+}
+
+#[test]
+fn test_build_belief_graph() {
+	let mut m = Map::open("data/map1.pgm", [-1.0, -1.0], [1.0, 1.0]);
+	m.add_zones("data/map1_zone_ids.pgm", 0.1);
+
+	fn goal(state: &[f64; 2]) -> WorldMask {
+		bitvec![if (state[0] - 0.55).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05 { 1 } else { 0 }; 4]
+	}
+
+	let mut prm = mock_prm(&m);
+
+	let _policy = prm.plan_belief_space(&vec![0.5, 0.5]).expect("invalid start belief state");	
+	assert_eq!(prm.belief_graph.nodes[6].children, vec![7, 8]); // observation transitions
+	assert!(!prm.belief_graph.nodes[7].children.contains(&6)); // observation is irreversible
+	assert!(!prm.belief_graph.nodes[8].children.contains(&6)); // observation is irreversible
+
+	for (id, node) in prm.belief_graph.nodes.iter().enumerate() { // belief jump only at observation points
+		if id == 6 {
+			continue;
+		}
+		for &child_id in &node.children {
+			assert_eq!(node.belief_id, prm.belief_graph.nodes[child_id].belief_id);
+		}
+	}
+
+	for component in prm.belief_graph.condensation() { // the cycles stay in one belief
+		let belief_id = prm.belief_graph.nodes[component[0]].belief_id;
+		assert!(component.iter().all(|&id| prm.belief_graph.nodes[id].belief_id == belief_id));
+	}
+
+	// draw
+	//let mut full = m.clone();
+	//full.resize(5);
+	//full.draw_full_graph(&prm.graph);
+	//full.draw_policy(&policy);
+	//full.save("results/test_build_belief_graph.pgm");
+}
+
+#[test]
+fn test_observation_fan_out_limit() {
+	// 3 worlds, all resolved by observing at the start
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0; 3], [1.0; 3]),
+						   DiscreteSampler::new(),
+						   &OriginObservationFuncs{});
+	prm.n_worlds = 3;
+	prm.graph.add_node([0.0; 3], bitvec![1, 1, 1]);
+	prm.graph.add_node([1.0, 0.0, 0.0], bitvec![1, 1, 1]);
+	prm.graph.add_bi_edge(0, 1, bitvec![1, 1, 1]);
+	prm.final_node_ids.push(1);
+
+	prm.set_observation_fan_out_limit(2);
+	let policy = prm.plan_belief_space(&vec![0.2, 0.3, 0.5]).expect("invalid start belief state");
+
+	// the least probable outcome is dropped
+	let root = &prm.belief_graph().nodes[0];
+	assert_eq!(root.node_type, BeliefNodeType::Observation);
+	assert_eq!(root.children.len(), 2);
+	let children_beliefs: Vec<&BeliefState> = root.children.iter().map(|&id| &prm.belief_graph().nodes[id].belief_state).collect();
+	assert!(!children_beliefs.contains(&&vec![1.0, 0.0, 0.0]));
+
+	let probabilities = observation_probabilities(prm.belief_graph(), 0);
+	assert_eq!(probabilities.iter().map(|(_, p)| p).sum::<f64>(), 1.0);
+	assert!((probabilities.iter().find(|(id, _)| prm.belief_graph().nodes[*id].belief_state == vec![0.0, 0.0, 1.0]).unwrap().1 - 0.625).abs() < 0.000001);
+
+	assert_eq!(policy.validate(), Ok(()));
+	assert!((prm.expected_costs()[0] - 1.0).abs() < 0.000001);
+}
+
+#[test]
+fn test_observation_fan_out_limit_ignores_unchanged_belief() {
+	// the observation may also leave the belief unchanged, its outcome is the most probable one
+	struct Funcs {}
+	impl PRMFuncs<2> for Funcs {
+		fn reachable_belief_states(&self, belief_state: &BeliefState) -> Vec<BeliefState> {
+			PRMFuncs::<2>::reachable_belief_states(&OriginObservationFuncs{}, belief_state)
+		}
+
+		fn observe(&self, state: &[f64; 2], belief_state: &BeliefState) -> Vec<BeliefState> {
+			let mut outcomes = OriginObservationFuncs{}.observe(state, belief_state);
+			outcomes.insert(0, belief_state.clone());
+			outcomes
+		}
+	}
+
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0; 2], [1.0; 2]),
+						   DiscreteSampler::new(),
+						   &Funcs{});
+	prm.n_worlds = 2;
+	prm.graph.add_node([0.0; 2], bitvec![1, 1]);
+	prm.graph.add_node([1.0, 0.0], bitvec![1, 1]);
+	prm.graph.add_bi_edge(0, 1, bitvec![1, 1]);
+	prm.final_node_ids.push(1);
+
+	prm.set_observation_fan_out_limit(1);
+	prm.plan_belief_space(&vec![0.3, 0.7]).expect("invalid start belief state");
+
+	// the kept outcome is an actual observation
+	let root = &prm.belief_graph().nodes[0];
+	assert_eq!(root.node_type, BeliefNodeType::Observation);
+	assert_eq!(root.children.len(), 1);
+	assert_eq!(prm.belief_graph().nodes[root.children[0]].belief_state, vec![0.0, 1.0]);
+}
+
+#[test]
+fn test_observation_policy() {
+	// 2 worlds resolved by observing at the start, the goal is reachable in both
+	let plan = |observation_policy| {
+		let mut prm = PRM::new(ContinuousSampler::new([-1.0; 2], [1.0; 2]),
+							   DiscreteSampler::new(),
+							   &OriginObservationFuncs{});
+		prm.n_worlds = 2;
+		prm.graph.add_node([0.0; 2], bitvec![1, 1]);
+		prm.graph.add_node([1.0, 0.0], bitvec![1, 1]);
+		prm.graph.add_bi_edge(0, 1, bitvec![1, 1]);
+		prm.final_node_ids.push(1);
+
+		prm.set_observation_cost(0.5);
+		prm.set_observation_policy(observation_policy);
+		let policy = prm.plan_belief_space(&vec![0.5, 0.5]).expect("invalid start belief state");
+		assert_eq!(policy.validate(), Ok(()));
+
+		let root = &prm.belief_graph().nodes[0];
+		assert_eq!(root.node_type, BeliefNodeType::Observation);
+		let n_action_edges = root.children.iter().filter(|&&id| !prm.belief_graph().is_observation_edge(0, id)).count();
+		(n_action_edges, prm.expected_costs()[0], policy)
+	};
+
+	// the robot has to wait for the outcome of the observation
+	let (n_action_edges, cost, policy) = plan(ObservationPolicy::ObserveThenStop);
+	assert_eq!(n_action_edges, 0);
+	assert!((cost - 1.5).abs() < 0.000001);
+	assert_eq!(policy.nodes[0].children.len(), 2);
+
+	// the robot can skip the observation and go straight to the goal
+	let (n_action_edges, cost, policy) = plan(ObservationPolicy::ObserveOrAct);
+	assert_eq!(n_action_edges, 1);
+	assert!((cost - 1.0).abs() < 0.000001);
+	assert_eq!(policy.nodes[0].children.len(), 1);
+	assert_eq!(policy.nodes[policy.nodes[0].children[0]].belief_state, vec![0.5, 0.5]);
+}
+
+#[test]
+fn test_observe_or_act_picks_the_cheaper_option() {
+	// observing at the start tells whether the shortcut 0 -> 1 (world 1 only) is open, the detour 0 -> 2 -> 3 -> 1 is always open
+	let plan = |observation_cost| {
+		let mut prm = PRM::new(ContinuousSampler::new([-1.0; 2], [1.0; 2]),
+							   DiscreteSampler::new(),
+							   &OriginObservationFuncs{});
+		prm.n_worlds = 2;
+		for state in [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]] {
+			prm.graph.add_node(state, bitvec![1, 1]);
+		}
+		prm.graph.add_bi_edge(0, 1, bitvec![0, 1]);
+		for (from, to) in [(0, 2), (2, 3), (3, 1)] {
+			prm.graph.add_bi_edge(from, to, bitvec![1, 1]);
+		}
+		prm.final_node_ids.push(1);
+
+		prm.set_observation_cost(observation_cost);
+		prm.set_observation_policy(ObservationPolicy::ObserveOrAct);
+		let policy = prm.plan_belief_space(&vec![0.5, 0.5]).expect("invalid start belief state");
+		assert_eq!(policy.validate(), Ok(()));
+		policy
+	};
+
+	// observing costs observation_cost + 0.5 * 3 + 0.5 * 1, acting takes the detour (3)
+	let policy = plan(0.5);
+	assert!((policy.nodes[0].cost_to_goal - 2.5).abs() < 0.000001);
+	assert_eq!(policy.nodes[0].children.len(), 2);
+
+	let policy = plan(2.0);
+	assert!((policy.nodes[0].cost_to_goal - 3.0).abs() < 0.000001);
+	assert_eq!(policy.path_to_leaf(0), vec![[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]]);
+	assert!(policy.nodes.iter().all(|node| node.belief_state == vec![0.5, 0.5]));
+
+	// same cost, the observation is kept
+	let policy = plan(1.0);
+	assert_eq!(policy.nodes[0].children.len(), 2);
+}
+
+#[test]
+fn test_reachability_accessors() {
+	let funcs = MockFuncs{};
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   &funcs);
+
+	// mock growth of create_mock_graph, the edges are added in the order they would be grown
+	prm.graph = create_mock_graph();
+	prm.conservative_reachability.set_root(prm.graph.nodes[0].validity.clone());
+	for node in prm.graph.nodes.iter().skip(1) {
+		prm.conservative_reachability.add_node(node.validity.clone());
+	}
+	prm.conservative_reachability.add_final_node(5, bitvec![1, 1]);
+	assert!(!prm.is_complete());
+
+	for (from, to) in [(0, 1), (1, 2), (2, 3), (3, 5)] {
+		prm.conservative_reachability.add_edge(from, to);
+	}
+
+	// 3 is only valid in world 1, so is the path through it
+	assert_eq!(prm.reachability_of(2), &bitvec![1, 1]);
+	assert_eq!(prm.reachability_of(3), &bitvec![0, 1]);
+	assert_eq!(prm.reachability_of(5), &bitvec![0, 1]);
+	assert_eq!(prm.reachability_of(4), &bitvec![0, 0]);
+	assert!(!prm.is_complete());
+
+	// the other branch solves world 0
+	prm.conservative_reachability.add_edge(1, 4);
+	prm.conservative_reachability.add_edge(4, 5);
+	assert_eq!(prm.reachability_of(4), &bitvec![1, 1]);
+	assert_eq!(prm.reachability_of(5), &bitvec![1, 1]);
+	assert!(prm.is_complete());
+}
+
+#[test]
+fn test_reachability_propagation() {
+	// the center of the corridor is a door, only open in world 1
+	struct Funcs {}
+	impl PRMFuncs<2> for Funcs {
+		fn state_validity(&self, state: &[f64; 2]) -> Option<WorldMask> {
+			if (state[0] - 0.5).abs() < 0.05 && state[1].abs() < 0.1 { Some(bitvec![0, 1]) } else { Some(bitvec![1, 1]) }
+		}
+	}
+
+	// the branch behind the door is grown first, the detour around it (0.5, 0.2) then unlocks it for world 0
+	let n_iterations = |propagate_reachability| {
+		let mut samples = vec![[0.3, 0.0], [0.5, 0.0], [0.7, 0.0], [0.9, 0.0], [0.5, 0.2], [0.9, 0.1]].into_iter();
+		let parameters = GrowthParameters{ max_step: 1.0, search_radius: 0.3, n_iter_min: 1, n_iter_max: 6,
+			radius_schedule: RadiusSchedule::Constant, propagate_reachability, ..Default::default() };
+
+		let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+							   DiscreteSampler::new(),
+							   &Funcs{});
+		prm.grow_graph_with_sampler(&[0.0, 0.0], ball_goal([0.9, 0.0], 0.05, 2), &parameters, &mut |_| samples.next().unwrap())
+			.expect("graph not grown up to solution");
+		assert_eq!(prm.reachability_of(3), &bitvec![1, 1]);
+		prm.n_it
+	};
+
+	assert_eq!(n_iterations(true), 5);
+	assert_eq!(n_iterations(false), 6);
+}
+
+#[test]
+fn test_plan_belief_sweep() {
+	// 2 worlds resolved by observing at the start, the observations are counted to track the graph builds
+	struct Funcs {
+		n_observations: std::cell::Cell<usize>,
+	}
+	impl PRMFuncs<2> for Funcs {
+		fn reachable_belief_states(&self, belief_state: &BeliefState) -> Vec<BeliefState> {
+			vec![belief_state.clone(), vec![1.0, 0.0], vec![0.0, 1.0]]
+		}
+
+		fn observe(&self, state: &[f64; 2], belief_state: &BeliefState) -> Vec<BeliefState> {
+			self.n_observations.set(self.n_observations.get() + 1);
+			if *state == [0.0; 2] && belief_state.iter().filter(|&&p| p > 0.0).count() > 1 {
+				vec![vec![1.0, 0.0], vec![0.0, 1.0]]
+			}
+			else {
+				vec![belief_state.clone()]
+			}
+		}
+	}
+
+	let create_prm = |funcs| {
+		let mut prm = PRM::new(ContinuousSampler::new([-1.0; 2], [1.0; 2]),
+							   DiscreteSampler::new(),
+							   funcs);
+		prm.n_worlds = 2;
+		prm.graph.add_node([0.0; 2], bitvec![1, 1]);
+		prm.graph.add_node([1.0, 0.0], bitvec![1, 1]);
+		prm.graph.add_bi_edge(0, 1, bitvec![1, 1]);
+		prm.final_node_ids.push(1);
+		prm
+	};
+
+	let single_funcs = Funcs{ n_observations: std::cell::Cell::new(0) };
+	create_prm(&single_funcs).plan_belief_space(&vec![0.5, 0.5]).expect("invalid start belief state");
+
+	let funcs = Funcs{ n_observations: std::cell::Cell::new(0) };
+	let mut prm = create_prm(&funcs);
+	let beliefs = vec![vec![0.2, 0.8], vec![0.5, 0.5], vec![0.9, 0.1]];
+	let policies = prm.plan_belief_sweep(&beliefs).expect("invalid start belief state");
+
+	// built once
+	assert!(single_funcs.n_observations.get() > 0);
+	assert_eq!(funcs.n_observations.get(), single_funcs.n_observations.get());
+
+	assert_eq!(policies.len(), 3);
+	for (policy, belief) in policies.iter().zip(&beliefs) {
+		assert_eq!(policy.validate(), Ok(()));
+		assert_eq!(&policy.nodes[0].belief_state, belief);
+	}
+}
+
+#[test]
+fn test_used_prm_edges() {
+	let funcs = MockFuncs{};
+	let mut prm = mock_prm(&funcs);
+
+	// likely open, the robot checks the zone at 2
+	let policy = prm.plan_belief_space(&vec![0.1, 0.9]).expect("invalid start belief state");
+	let edges = prm.used_prm_edges(&policy);
+	assert!(!edges.is_empty());
+
+	let paths: Vec<Vec<[f64; 2]>> = (0..policy.leafs.len()).map(|i| policy.path_to_leaf(i)).collect();
+	for &(from, to) in &edges {
+		assert!(prm.graph.nodes[from].children.iter().any(|edge| edge.id == to));
+
+		let (from_state, to_state) = (prm.graph.nodes[from].state, prm.graph.nodes[to].state);
+		assert!(paths.iter().any(|path| pairwise_iter(path).any(|(a, b)| *a == from_state && *b == to_state)));
+	}
+
+	// through the zone if open, back and around otherwise
+	assert_eq!(edges, vec![(0, 1), (1, 2), (1, 4), (2, 1), (2, 3), (3, 5), (4, 5)]);
+}
+
+#[test]
+fn test_compact_belief_graph() {
+	let funcs = MockFuncs{};
+	let mut prm = mock_prm(&funcs);
+	prm.graph.add_node([0.9, -0.9], bitvec![1, 1]); // isolated
+
+	prm.build_full_belief_graph(&vec![0.5, 0.5]).unwrap();
+	prm.compute_expected_costs_to_goals();
+	let root_cost = prm.expected_costs()[0];
+	let (n_unknown, _, _) = prm.belief_graph().node_count_by_type();
+	assert_eq!(n_unknown, 3);
+
+	let n_nodes = prm.belief_graph().nodes.len();
+	assert_eq!(prm.compact_belief_graph(), 3);
+	prm.compute_expected_costs_to_goals();
+
+	let (n_unknown, _, _) = prm.belief_graph().node_count_by_type();
+	assert_eq!(n_unknown, 0);
+	assert_eq!(prm.belief_graph().nodes.len(), n_nodes - 3);
+	assert_eq!(prm.expected_costs()[0], root_cost);
+	assert!(prm.node_to_belief_nodes[6].iter().all(|id| id.is_none()));
+
+	for (id, node) in prm.belief_graph().nodes.iter().enumerate() {
+		assert!(node.children.iter().all(|&child_id| prm.belief_graph().nodes[child_id].parents.contains(&id)));
+	}
 
-		println!("grow graph..");
+	let policy = prm.extract_policy();
+	assert_eq!(policy.validate(), Ok(()));
+}
 
-		let root_validity = self.fns.state_validity(&start).expect("Start from a valid state!");
-		self.n_worlds = root_validity.len();
-		self.graph.add_node(start, root_validity.clone());
-		self.conservative_reachability.set_root(root_validity);
-		self.kdtree.reset(start);
+#[test]
+fn test_custom_terminal_belief_nodes() {
+	let funcs = MockFuncs{};
+	let mut prm = mock_prm(&funcs);
 
-		let mut i = 0;
-		while i < n_iter_min || !self.conservative_reachability.is_final_set_complete() && i < n_iter_max {
-			i+=1;
-	
-			// First sample state and world
-			let mut new_state = self.continuous_sampler.sample();
-			let world = self.discrete_sampler.sample(self.n_worlds);
+	prm.build_belief_graph(&vec![0.5, 0.5]).unwrap();
 
-			// Second, retrieve closest node for sampled world and steer from there
-			let kd_from = self.kdtree.nearest_neighbor_filtered(new_state, |id|{self.conservative_reachability.reachability(id)[world]}); // log n
-			steer(&kd_from.state, &mut new_state, max_step); 
+	// stop at 4 instead of 5
+	let terminal_ids: Vec<usize> = prm.belief_nodes(4).iter().flatten().cloned().collect();
+	prm.compute_expected_costs_to_terminals(&terminal_ids);
 
-			if let Some(state_validity) = self.fns.state_validity(&new_state) {
-				// Third, add node
-				let new_node_id = self.graph.add_node(new_state, state_validity.clone());
-				let new_node = &self.graph.nodes[new_node_id];
-				self.conservative_reachability.add_node(state_validity.clone());
-
-				// Fourth, we find the neighbors in a specific radius of new_state.
-				let radius = {
-					let n = self.graph.nodes.len() as f64;
-					let s = search_radius * (n.ln()/n).powf(1.0/(N as f64));
-					if s < max_step { s } else { max_step }
-				};
+	let nodes = &prm.graph.nodes;
+	let expected_cost = norm2(&nodes[0].state, &nodes[1].state) + norm2(&nodes[1].state, &nodes[4].state);
+	assert!((prm.expected_costs()[0] - expected_cost).abs() < 0.000001);
+	for &id in &terminal_ids {
+		assert_eq!(prm.expected_costs()[id], 0.0);
+	}
 
-				// Fifth we connect to neighbors 
-				let mut neighbour_ids: Vec<usize> = self.kdtree.nearest_neighbors(new_state, radius).iter()
-				.map(|&kd_node| kd_node.id)
-				.collect();
+	let policy = prm.extract_policy();
+	assert_eq!(policy.leaf(0).state, nodes[4].state);
 
-				if neighbour_ids.is_empty() { neighbour_ids.push(kd_from.id); }
+	// default terminals
+	prm.compute_expected_costs_to_goals();
+	assert!(prm.expected_costs()[0] > expected_cost);
+}
 
+#[test]
+fn test_observation_cost() {
+	let funcs = MockFuncs{};
+	let mut prm = mock_prm(&funcs);
+
+	let n_observations = |policy: &Policy<2>| policy.nodes.iter().filter(|node| node.children.len() > 1).count();
+
+	// 3 is likely open, worth checking
+	let policy = prm.plan_belief_space(&vec![0.1, 0.9]).expect("invalid start belief state");
+	assert_eq!(n_observations(&policy), 1);
+	let observing_cost = policy.nodes[0].cost_to_goal;
+
+	prm.set_observation_cost(0.2);
+	let policy = prm.plan_belief_space(&vec![0.1, 0.9]).expect("invalid start belief state");
+	assert_eq!(n_observations(&policy), 1);
+	assert!((policy.nodes[0].cost_to_goal - (observing_cost + 0.2)).abs() < 0.000001);
+
+	// more expensive than going around
+	prm.set_observation_cost(1.0);
+	let policy = prm.plan_belief_space(&vec![0.1, 0.9]).expect("invalid start belief state");
+	assert_eq!(n_observations(&policy), 0);
+	assert!(policy.nodes[0].cost_to_goal < observing_cost + 1.0);
+}
 
-				//let b = Vec<(usize, Option<WorldMask>)>::new();
-				// Idea: sample which ones we rewire to?
-				let fwd_edges: Vec<(usize, Option<WorldMask>)> = neighbour_ids.iter()
-					.map(|&id| (id, &self.graph.nodes[id]))
-					.map(|(id, node)| (id, self.fns.transition_validator(node, new_node)))
-					.filter(|(_, validity)| validity.is_some())
-					.collect();
+#[test]
+fn test_build_belief_graph_has_no_unknown_nodes() {
+	let funcs = MockFuncs{};
+	let mut prm = mock_prm(&funcs);
 
-				let bwd_edges: Vec<(usize, Option<WorldMask>)> = neighbour_ids.iter()
-					.map(|&id| (id, &self.graph.nodes[id]))
-					.map(|(id, node)| (id, self.fns.transition_validator(node, new_node)))
-					.filter(|(_, validity)| validity.is_some())
-					.collect();
-							
-				// connect neighbors to new node
-				for (id, validity) in fwd_edges {
-					self.graph.add_edge(id, new_node_id, validity.expect("None validity should be filtered at this stage"));
-					self.conservative_reachability.add_edge(id, new_node_id);
-				}
+	let _policy = prm.plan_belief_space(&vec![0.5, 0.5]).expect("invalid start belief state");
 
-				// connect new node to neighbor
-				for (id, validity) in bwd_edges {
-					self.graph.add_edge(new_node_id, id, validity.expect("None validity should be filtered at this stage"));
-					self.conservative_reachability.add_edge(new_node_id, id);
-				}
+	let (n_unknown, n_action, n_observation) = prm.belief_graph().node_count_by_type();
+	assert_eq!(n_unknown, 0);
+	assert_eq!(n_observation, 1);
+	assert_eq!(n_action + n_observation, prm.belief_graph().nodes.len());
+	assert_eq!(prm.expected_costs().len(), prm.belief_graph().nodes.len());
+}
 
-				let finality = goal(&new_state);
-				let is_final = finality.iter().any(|w|{*w});
-				if is_final {
-					self.conservative_reachability.add_final_node(new_node_id, finality);
-				}
+#[test]
+fn test_rebuild_belief_graph_incremental() {
+	let funcs = MockFuncs{};
+	let mut prm = mock_prm(&funcs);
+
+	assert_eq!(prm.rebuild_belief_graph_incremental(&[3]), Err(PlanError::BeliefGraphNotBuilt));
+	prm.build_belief_graph(&vec![0.5, 0.5]).unwrap();
+
+	// (node, belief) of each edge of the belief graph, the ids of the belief nodes aren't compared
+	fn belief_edges(prm: &PRM<MockFuncs, 2>) -> Vec<(usize, usize, usize, usize)> {
+		let belief_node_to_node = prm.belief_node_to_node();
+		let nodes = &prm.belief_graph().nodes;
+		let mut edges: Vec<_> = nodes.iter().enumerate()
+			.flat_map(|(id, node)| node.children.iter().map(move |&child_id| (id, child_id)))
+			.map(|(id, child_id)| (belief_node_to_node[id], nodes[id].belief_id, belief_node_to_node[child_id], nodes[child_id].belief_id))
+			.collect();
+		edges.sort_unstable();
+		edges
+	}
 
-				self.kdtree.add(new_state, new_node_id);
+	// node 3 becomes valid in both worlds, then is restored
+	for validity in vec![bitvec![1, 1], bitvec![0, 1]] {
+		prm.graph.nodes[3].validity = validity.clone();
+		for (id, node) in prm.graph.nodes.iter_mut().enumerate() {
+			for edge in node.children.iter_mut().chain(node.parents.iter_mut()).filter(|edge| id == 3 || edge.id == 3) {
+				edge.validity = validity.clone();
 			}
 		}
 
-		self.n_it += i;
-
-		match self.conservative_reachability.is_final_set_complete() {
-			true => {
-				self.final_node_ids = self.conservative_reachability.final_node_ids();
-				Ok(())
-			},
-			_ => Err(&"final nodes are not reached for each world")
+		let untouched: Vec<Vec<Option<usize>>> = [0, 1, 4].iter().map(|&id| prm.belief_nodes(id).to_vec()).collect();
+		let n_belief_nodes = prm.belief_graph().nodes.len();
+		prm.rebuild_belief_graph_incremental(&[3]).unwrap();
+		prm.compute_expected_costs_to_goals();
+
+		// only the belief nodes of node 3 are added or removed
+		assert_eq!(untouched, [0, 1, 4].iter().map(|&id| prm.belief_nodes(id).to_vec()).collect::<Vec<_>>());
+		let n_added = prm.belief_graph().nodes.len() as i64 - n_belief_nodes as i64;
+		assert_eq!(n_added, if validity.all() { 2 } else { -2 }); // in the start belief and in world 0
+
+		let mut full_prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+									DiscreteSampler::new(),
+									&funcs);
+		full_prm.n_worlds = 2;
+		full_prm.graph = prm.graph.clone();
+		full_prm.final_node_ids.push(5);
+		full_prm.build_belief_graph(&vec![0.5, 0.5]).unwrap();
+		full_prm.compute_expected_costs_to_goals();
+
+		assert_eq!(prm.belief_graph().node_count_by_type(), full_prm.belief_graph().node_count_by_type());
+		assert_eq!(belief_edges(&prm), belief_edges(&full_prm));
+		for id in 0..prm.graph.n_nodes() {
+			let is_some = |belief_nodes: &[Option<usize>]| belief_nodes.iter().map(Option::is_some).collect::<Vec<_>>();
+			assert_eq!(is_some(prm.belief_nodes(id)), is_some(full_prm.belief_nodes(id)));
 		}
+		assert_eq!(prm.expected_costs()[0], full_prm.expected_costs()[0]);
 	}
+}
 
-	#[allow(clippy::style)]
-	pub fn plan_belief_space(&mut self, start_belief_state: &BeliefState) -> Policy<N> {
-		assert_belief_state_validity(start_belief_state);
-		
-		println!("build belief graph..");
+#[test]
+fn test_prune_unreachable() {
+	let funcs = MockFuncs{};
+	let mut prm = mock_prm(&funcs);
+	prm.graph.add_node([0.9, -0.9], bitvec![1, 1]); // 6, isolated
+	prm.graph.add_node([0.9, 0.9], bitvec![1, 1]);  // 7, connected to the goal
+	prm.graph.add_bi_edge(5, 7, bitvec![1, 1]);
+
+	assert_eq!(prm.prune_unreachable(), 1);
+	assert_eq!(prm.graph.nodes.len(), 7);
+	assert_eq!(prm.final_node_ids, vec![5]);
+	assert_eq!(prm.kdtree.nearest_neighbor([0.9, 0.8]).id, 6);
+	assert_eq!(prm.kdtree.nearest_neighbor([0.9, -0.9]).id, 0);
+}
 
-		self.build_belief_graph(start_belief_state);
+#[test]
+fn test_plan_belief_space_normalizes_start_belief_state() {
+	let funcs = MockFuncs{};
+	let mut prm = mock_prm(&funcs);
 
-		println!("compute expected costs to goal..");
+	let policy = prm.plan_belief_space(&vec![0.45, 0.45]).expect("belief state should be normalized");
+	assert_eq!(policy.nodes[0].belief_state, vec![0.5, 0.5]);
+}
 
-		self.compute_expected_costs_to_goals();
-		
-		println!("extract policy..");
+#[test]
+fn test_single_belief_fast_path_matches_belief_graph_path() {
+	// same graph, but the belief can't change: world 1 is already known
+	struct Funcs {}
+	impl PRMFuncs<2> for Funcs {}
+
+	let (mock_funcs, funcs) = (MockFuncs{}, Funcs{});
+
+	let mut general_prm = mock_prm(&mock_funcs);
+	// plan_belief_space would fall back to the shortest path as well (no observation splits the belief), the belief graph is planned directly
+	general_prm.build_belief_graph(&vec![0.0, 1.0]).unwrap();
+	general_prm.compute_expected_costs_to_goals();
+	let general = general_prm.extract_policy();
+	assert!(!general_prm.belief_graph().nodes.is_empty());
+	assert!(!general_prm.has_observations());
+
+	let mut fast_prm = mock_prm(&funcs);
+	let fast = fast_prm.plan_belief_space(&vec![0.0, 1.0]).unwrap();
+	assert!(fast_prm.belief_graph().nodes.is_empty());
+
+	assert_eq!(fast.leafs.len(), 1);
+	assert_eq!(general.leafs.len(), fast.leafs.len());
+	assert_eq!(general.path_to_leaf(0), fast.path_to_leaf(0));
+	assert_eq!(fast.path_to_leaf(0), vec![[0.55, -0.8], [-0.42, -0.38], [0.54, 0.0], [0.54, 0.1], [0.55, 0.9]]);
+
+	// in world 0, node 3 isn't usable
+	let mut fast_prm = mock_prm(&funcs);
+	let policy = fast_prm.plan_belief_space(&vec![1.0, 0.0]).unwrap();
+	assert_eq!(policy.path_to_leaf(0), vec![[0.55, -0.8], [-0.42, -0.38], [-0.97, 0.65], [0.55, 0.9]]);
+}
 
-		let policy = self.extract_policy();
+#[test]
+fn test_no_observation_falls_back_to_shortest_path() {
+	// several reachable belief states, but observe never splits the belief
+	let funcs = BlindFuncs{};
+	let mut prm = mock_prm(&funcs);
+
+	let policy = prm.plan_belief_space(&vec![0.5, 0.5]).unwrap();
+	assert!(!prm.has_observations());
+	assert!(prm.belief_graph().nodes.is_empty());
+
+	// node 3 is only valid in world 1, the path has to work in both worlds
+	assert_eq!(policy.validate(), Ok(()));
+	assert_eq!(policy.leafs.len(), 1);
+	assert_eq!(policy.path_to_leaf(0), vec![[0.55, -0.8], [-0.42, -0.38], [-0.97, 0.65], [0.55, 0.9]]);
+
+	// not planned per world: the shortest path of world 1 goes through 3, without knowing the world it can't be taken
+	let world_1_policy = mock_prm(&funcs).plan_belief_space(&vec![0.0, 1.0]).unwrap();
+	assert_eq!(world_1_policy.path_to_leaf(0)[3], [0.54, 0.1]);
+	assert!(world_1_policy.nodes[0].cost_to_goal < policy.nodes[0].cost_to_goal);
+}
 
-		println!("success!");
+#[test]
+fn test_plan_belief_space_rejects_invalid_start_belief_state() {
+	let funcs = MockFuncs{};
+	let mut prm = mock_prm(&funcs);
 
-		policy
-	}
+	assert_eq!(prm.plan_belief_space(&vec![0.1, 0.1]).err(), Some(PlanError::InvalidBelief(BeliefError::InvalidSum(0.2))));
+}
 
-	#[allow(clippy::style)]
-	pub fn build_belief_graph(&mut self, start_belief_state: &BeliefState) {
-		// build belief state graph
-		let reachable_belief_states = self.fns.reachable_belief_states(start_belief_state);
-		let mut belief_space_graph: BeliefGraph<N> = BeliefGraph{nodes: Vec::new(), reachable_belief_states: reachable_belief_states.clone()};
-		let mut node_to_belief_nodes: Vec<Vec<Option<usize>>> = vec![vec![None; reachable_belief_states.len()]; self.graph.n_nodes()];
-		
-		// build nodes
-		for (id, node) in self.graph.nodes.iter().enumerate() {
-			for (belief_id, belief_state) in reachable_belief_states.iter().enumerate() {
-				let belief_node_id = belief_space_graph.add_node(node.state, belief_state.clone(), belief_id, BeliefNodeType::Unknown);
+#[test]
+fn test_belief_graph_budget() {
+	let funcs = MockFuncs{};
+	let mut prm = mock_prm(&funcs);
 
-				if is_compatible(belief_state, &node.validity) {
-					node_to_belief_nodes[id][belief_id] = Some(belief_node_id);
-				}
-			}
-		}
+	// 6 nodes, 3 beliefs
+	prm.set_max_belief_nodes(10);
+	assert_eq!(prm.plan_belief_space(&vec![0.5, 0.5]).err(), Some(PlanError::BeliefGraphTooLarge{ estimated: 18 }));
+	assert!(prm.belief_graph().nodes.is_empty());
 
-		// build transitions due to observations (observation edges)
-		for (id, node) in self.graph.nodes.iter().enumerate() {
-			for (belief_id, belief_state) in reachable_belief_states.iter().enumerate() {
-				let children_belief_states = self.fns.observe(&node.state, &belief_state);
-				let parent_belief_node_id = node_to_belief_nodes[id][belief_id];
+	prm.set_max_belief_nodes(18);
+	assert!(prm.plan_belief_space(&vec![0.5, 0.5]).is_ok());
+}
 
-				for child_belief_state in &children_belief_states {
-					if belief_state != child_belief_state {
-						// debug
-						//let p = transition_probability(&belief_state, &child_belief_state);
-						//assert!(p > 0.0);
-						//
+#[test]
+fn test_parallel_belief_node_construction() {
+	struct Funcs {}
+	impl PRMFuncs<2> for Funcs {
+		fn state_validity(&self, state: &[f64; 2]) -> Option<WorldMask> {
+			Some(if state[0] > 0.5 { bitvec![0, 1, 1] } else { bitvec![1, 1, 1] })
+		}
 
-						let child_belief_state_id = belief_space_graph.belief_id(&child_belief_state);
-						let child_belief_node_id = node_to_belief_nodes[id][child_belief_state_id];
+		fn reachable_belief_states(&self, belief_state: &BeliefState) -> Vec<BeliefState> {
+			vec![belief_state.clone(), vec![1.0, 0.0, 0.0], vec![0.0, 0.5, 0.5], vec![0.0, 1.0, 0.0], vec![0.0, 0.0, 1.0]]
+		}
+	}
 
-						if let (Some(parent_id), Some(child_id)) = (parent_belief_node_id, child_belief_node_id) {
-							belief_space_graph.nodes[parent_id].node_type = BeliefNodeType::Observation;
-							belief_space_graph.add_edge(parent_id, child_id);
-						}
-					}
-				}
+	let funcs = Funcs{};
+	let mut prm = PRMBuilder::new(&funcs, [-1.0, -1.0], [1.0, 1.0])
+		.max_step(0.1)
+		.iter_bounds(2000, 2000)
+		.grow(&[-0.9, -0.9], ball_goal([0.0, 0.9], 0.1, 3))
+		.unwrap();
+
+	let start_belief_state = vec![0.2, 0.4, 0.4];
+	prm.build_full_belief_graph(&start_belief_state).unwrap();
+
+	// sequential construction
+	let reachable_belief_states = funcs.reachable_belief_states(&start_belief_state);
+	let mut expected = Vec::new();
+	for (id, node) in prm.graph.nodes.iter().enumerate() {
+		for (belief_id, belief_state) in reachable_belief_states.iter().enumerate() {
+			if is_compatible(belief_state, &node.validity) {
+				assert_eq!(prm.belief_nodes(id)[belief_id], Some(expected.len()));
+				expected.push((node.state, belief_id));
+			} else {
+				assert_eq!(prm.belief_nodes(id)[belief_id], None);
 			}
 		}
+	}
 
-		// build possible geometric edges (action edges)
-		for (id, node) in self.graph.nodes.iter().enumerate() {
-			for (belief_id, _) in reachable_belief_states.iter().enumerate() {
-				let parent_belief_node_id = node_to_belief_nodes[id][belief_id];
+	let nodes: Vec<_> = prm.belief_graph().nodes.iter().map(|node| (node.state, node.belief_id)).collect();
+	assert_eq!(nodes, expected);
+	assert!(prm.belief_graph().nodes.iter().all(|node| node.belief_state == reachable_belief_states[node.belief_id]));
+}
 
-				if parent_belief_node_id.is_some() && belief_space_graph.nodes[parent_belief_node_id.unwrap()].node_type == BeliefNodeType::Observation {
-					continue;
-				}
+#[test]
+fn test_per_world_goals() {
+	// the goal is on the right in world 0 and on the left in world 1, the world is observed at the top
+	struct Funcs {}
+	impl PRMFuncs<2> for Funcs {
+		fn state_validity(&self, _state: &[f64; 2]) -> Option<WorldMask> {
+			Some(bitvec![1, 1])
+		}
 
-				for child_edge in &node.children {
-					let child_belief_node_id = node_to_belief_nodes[child_edge.id][belief_id];
+		fn reachable_belief_states(&self, belief_state: &BeliefState) -> Vec<BeliefState> {
+			vec![belief_state.clone(), vec![1.0, 0.0], vec![0.0, 1.0]]
+		}
 
-					if let (Some(parent_id), Some(child_id)) = (parent_belief_node_id, child_belief_node_id) {
-						if is_compatible(&belief_space_graph.nodes[parent_id].belief_state, &child_edge.validity) {
-							belief_space_graph.nodes[parent_id].node_type = BeliefNodeType::Action;
-							belief_space_graph.add_edge(parent_id, child_id);
-						}
-					}
-				}
+		fn observe(&self, state: &[f64; 2], belief_state: &BeliefState) -> Vec<BeliefState> {
+			if state[1] > 0.7 && belief_state[0] > 0.0 && belief_state[1] > 0.0 {
+				vec![vec![1.0, 0.0], vec![0.0, 1.0]]
+			} else {
+				vec![belief_state.clone()]
 			}
 		}
-
-		self.node_to_belief_nodes = node_to_belief_nodes;
-		self.belief_graph = belief_space_graph;
 	}
 
-	pub fn compute_expected_costs_to_goals(&mut self) {
-		//let mut final_belief_state_node_ids = final_node_ids.iter().fold(Vec::new(), |finals, final_id| { finals.extend(node_to_belief_nodes[final_id]); finals } );
-		let mut final_belief_state_node_ids: Vec<usize> = Vec::new();
-		for &final_id in &self.final_node_ids {
-			for belief_node_id in &self.node_to_belief_nodes[final_id] {
-				if belief_node_id.is_some() {
-					final_belief_state_node_ids.push(belief_node_id.unwrap());
-				}
-			}
-		}
+	let goals = [[0.8, -0.8], [-0.8, -0.8]];
+	let goal = |state: &[f64; 2]| goals.iter().map(|g| norm2(state, g) < 0.1).collect::<WorldMask>();
 
-		// DP in belief state
-		self.expected_costs_to_goals = conditional_dijkstra(&self.belief_graph, &final_belief_state_node_ids, |a: &[f64; N], b: &[f64;N]| self.fns.cost_evaluator(a, b));
-	}
+	let funcs = Funcs{};
+	let mut prm = PRMBuilder::new(&funcs, [-1.0, -1.0], [1.0, 1.0])
+		.max_step(0.1)
+		.iter_bounds(1000, 1000)
+		.grow(&[0.0, -0.8], goal)
+		.unwrap();
 
-	pub fn extract_policy(&self) -> Policy<N> {
-		extract_policy(&self.belief_graph, &self.expected_costs_to_goals)
-	}
+	let policy = prm.plan_belief_space(&vec![0.5, 0.5]).unwrap();
+	assert_eq!(policy.leafs.len(), 2);
 
-	pub fn print_summary(&self) {
-		println!("number of iterations:{}", self.n_it);
-		self.graph.print_summary();
+	for leaf_id in 0..policy.leafs.len() {
+		let leaf = policy.leaf(leaf_id);
+		let world = if leaf.belief_state[0] == 1.0 { 0 } else { 1 };
+		assert!(norm2(&leaf.state, &goals[world]) < 0.1);
 	}
 }
 
-#[cfg(test)]
-mod tests {
+#[test]
+fn test_zero_probability_worlds_are_pruned() {
+	// 4 worlds, the right half is only free in the worlds 2 and 3, the observation on the left resolves the worlds 0 and 1
+	struct Funcs {}
+	impl PRMFuncs<2> for Funcs {
+		fn state_validity(&self, state: &[f64; 2]) -> Option<WorldMask> {
+			Some(if state[0] > 0.5 { bitvec![0, 0, 1, 1] } else { bitvec![1, 1, 1, 1] })
+		}
 
-use crate::belief_graph;
+		fn reachable_belief_states(&self, belief_state: &BeliefState) -> Vec<BeliefState> {
+			let mut reachable_belief_states = vec![belief_state.clone()];
+			for world in (0..4).filter(|&world| belief_state[world] > 0.0) {
+				let mut resolved = vec![0.0; 4];
+				resolved[world] = 1.0;
+				reachable_belief_states.push(resolved);
+			}
+			reachable_belief_states
+		}
 
-    use super::*;
+		fn observe(&self, state: &[f64; 2], belief_state: &BeliefState) -> Vec<BeliefState> {
+			if state[0] < -0.5 && belief_state.iter().filter(|&&p| p > 0.0).count() > 1 {
+				self.reachable_belief_states(belief_state).into_iter().skip(1).collect()
+			} else {
+				vec![belief_state.clone()]
+			}
+		}
+	}
 
-#[test]
-fn test_plan_on_map2_pomdp() {
-	let mut m = Map::open("data/map2.pgm", [-1.0, -1.0], [1.0, 1.0]);
-	m.add_zones("data/map2_zone_ids.pgm", 0.2);
+	let funcs = Funcs{};
+	let mut prm = PRMBuilder::new(&funcs, [-1.0, -1.0], [1.0, 1.0])
+		.max_step(0.1)
+		.iter_bounds(1000, 1000)
+		.grow(&[-0.9, -0.9], ball_goal([0.0, 0.9], 0.1, 4))
+		.unwrap();
 
-	fn goal(state: &[f64; 2]) -> WorldMask {
-		bitvec![if (state[0] - 0.55).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05 { 1 } else { 0 }; 4]
-	}
+	let start_belief_state = vec![0.5, 0.5, 0.0, 0.0];
+	let n_right_nodes = prm.graph.nodes.iter().filter(|node| node.state[0] > 0.5).count();
+	assert!(n_right_nodes > 0);
 
-	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
-						   DiscreteSampler::new(),
-						   &m);
+	let complexity = prm.estimate_plan_complexity(&start_belief_state);
+	assert_eq!(complexity.n_belief_states, 3);
+	assert_eq!(complexity.max_belief_nodes, (prm.graph.n_nodes() - n_right_nodes) * 3);
 
-	prm.grow_graph(&[0.55, -0.8], goal, 0.1, 5.0, 2000, 100000).expect("graph not grown up to solution");
-	prm.print_summary();
-	let policy = prm.plan_belief_space(&vec![0.1, 0.1, 0.1, 0.7]);
+	prm.set_max_belief_nodes(complexity.max_belief_nodes);
+	let policy = prm.plan_belief_space(&start_belief_state).unwrap();
 
-	let mut m2 = m.clone();
-	m2.resize(5);
-	m2.draw_full_graph(&prm.graph);
-	m2.draw_zones_observability();
-	m2.draw_policy(&policy);
-	m2.save("results/test_prm_on_map2_pomdp");
+	for node in &prm.belief_graph().nodes {
+		assert!(node.state[0] <= 0.5);
+		assert_eq!((node.belief_state[2], node.belief_state[3]), (0.0, 0.0));
+	}
+	assert_eq!(policy.validate(), Ok(()));
+	assert_eq!(policy.leafs.len(), 2);
 }
 
 #[test]
-fn test_plan_on_map4_pomdp() {
-	let mut m = Map::open("data/map4.pgm", [-1.0, -1.0], [1.0, 1.0]);
-	m.add_zones("data/map4_zone_ids.pgm", 0.15);
-
-	fn goal(state: &[f64; 2]) -> WorldMask {
-		bitvec![if (state[0] + 0.55).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05 { 1 } else { 0 }; 16]
-	}
+fn test_estimate_plan_complexity() {
+	let funcs = MockFuncs{};
+	let mut prm = mock_prm(&funcs);
 
-	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
-						   DiscreteSampler::new(),
-						   &m);
+	// node 3 is only compatible with the belief [0, 1]
+	let complexity = prm.estimate_plan_complexity(&vec![0.5, 0.5]);
+	assert_eq!(complexity, PlanComplexity{ n_belief_states: 3, max_belief_nodes: 18, n_belief_nodes: 16, n_belief_edges: 28 });
+	assert!(prm.belief_graph().nodes.is_empty());
 
-	prm.grow_graph(&[0.55, -0.8], goal, 0.05, 5.0, 1000, 100000).expect("graph not grown up to solution");
-	prm.print_summary();
-	let policy = prm.plan_belief_space( &vec![1.0/16.0; 16]);
+	prm.build_full_belief_graph(&vec![0.5, 0.5]).unwrap();
+	assert_eq!(prm.belief_graph().nodes.len(), complexity.n_belief_nodes);
 
-	let mut m2 = m.clone();
-	m2.resize(5);
-	m2.draw_full_graph(&prm.graph);
-	m2.draw_zones_observability();
-	m2.draw_policy(&policy);
-	m2.save("results/test_prm_on_map4_pomdp");
+	prm.build_belief_graph(&vec![0.5, 0.5]).unwrap();
+	assert!(prm.belief_graph().nodes.len() <= complexity.n_belief_nodes);
 }
 
 #[test]
-fn test_plan_on_map1_fov_pomdp() {
-	let mut m = Map::open("data/map1_fov.pgm", [-1.0, -1.0], [1.0, 1.0]);
-	m.add_zones("data/map1_fov_zone_ids.pgm", 1.5);
+fn test_heuristic_weight() {
+	// the goal is north east in world 0 and south east in world 1, which one is observed around the origin
+	struct Funcs {}
+	impl PRMFuncs<2> for Funcs {
+		fn state_validity(&self, _state: &[f64; 2]) -> Option<WorldMask> {
+			Some(bitvec![1, 1])
+		}
 
-	fn goal(state: &[f64; 2]) -> WorldMask {
-		bitvec![if (state[0] - 0.85).abs() < 0.05 && (state[1] - 0.37).abs() < 0.05 { 1 } else { 0 }; 2]
-	}
+		fn reachable_belief_states(&self, belief_state: &BeliefState) -> Vec<BeliefState> {
+			vec![belief_state.clone(), vec![1.0, 0.0], vec![0.0, 1.0]]
+		}
 
-	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
-						   DiscreteSampler::new(),
-						   &m);
+		fn observe(&self, state: &[f64; 2], belief_state: &BeliefState) -> Vec<BeliefState> {
+			if norm2(state, &[0.0, 0.0]) < 0.2 && belief_state[0] > 0.0 && belief_state[1] > 0.0 {
+				vec![vec![1.0, 0.0], vec![0.0, 1.0]]
+			} else {
+				vec![belief_state.clone()]
+			}
+		}
+	}
 
-	prm.grow_graph(&[-0.37, 0.37], goal, 0.05, 5.0, 5000, 100000).expect("graph not grown up to solution");
-	prm.print_summary();
-	let policy = prm.plan_belief_space(&vec![0.5, 0.5]);
+	fn goal(state: &[f64; 2]) -> WorldMask {
+		let mut goal = bitvec![0, 0];
+		goal.set(0, norm2(state, &[0.9, 0.9]) < 0.1);
+		goal.set(1, norm2(state, &[0.9, -0.9]) < 0.1);
+		goal
+	}
 
-	let mut m2 = m.clone();
-	m2.resize(5);
-	m2.draw_full_graph(&prm.graph);
-	m2.draw_zones_observability();
-	m2.draw_policy(&policy);
-	m2.save("results/test_prm_on_map1_fov_pomdp");
+	let funcs = Funcs{};
+	let mut prm = PRMBuilder::new(&funcs, [-1.0, -1.0], [1.0, 1.0])
+		.max_step(0.1)
+		.iter_bounds(1000, 5000)
+		.seed(1)
+		.grow(&[-0.9, 0.0], goal)
+		.unwrap();
+	prm.build_belief_graph(&vec![0.5, 0.5]).unwrap();
+	assert!(prm.has_observations());
+
+	// (root cost, expansions, policy)
+	let mut plan = |weight: Option<f64>| {
+		prm.heuristic_weight = weight;
+		let n_expansions = prm.compute_expected_costs_to_goals();
+		(prm.expected_costs()[0], n_expansions, prm.extract_policy())
+	};
+
+	let (optimal_cost, exhaustive_expansions, _) = plan(None);
+	assert!(optimal_cost.is_finite());
+
+	// no suboptimality bound, even with weight 1: the costs are the ones of valid policies
+	let (astar_cost, astar_expansions, _) = plan(Some(1.0));
+	assert!(astar_cost >= optimal_cost);
+	assert!(astar_expansions < exhaustive_expansions);
+
+	let (weighted_cost, weighted_expansions, policy) = plan(Some(2.0));
+	assert!(weighted_expansions < astar_expansions);
+	assert!(weighted_cost >= optimal_cost);
+	assert_eq!(policy.validate(), Ok(()));
+	assert_eq!(policy.nodes[0].cost_to_goal, weighted_cost);
+	assert_eq!(policy.leafs.len(), 2);
 }
 
 #[test]
-fn test_plan_on_map2_fov_pomdp() {
-	let mut m = Map::open("data/map2_fov.pgm", [-1.0, -1.0], [1.0, 1.0]);
-	m.add_zones("data/map2_fov_zone_ids.pgm", 1.1);
+fn test_unresolvable_world_pairs() {
+	let funcs = MockFuncs{};
+	let mut prm = mock_prm(&funcs);
 
-	fn goal(state: &[f64; 2]) -> WorldMask {
-		bitvec![if (state[0] - 0.775).abs() < 0.05 && (state[1] - 0.3).abs() < 0.05 { 1 } else { 0 }; 4]
-	}
+	// observation in 2
+	assert_eq!(prm.unresolvable_world_pairs(&vec![0.5, 0.5]), Ok(vec![]));
 
-	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
-						   DiscreteSampler::new(),
-						   &m);
+	// no observation anywhere
+	let funcs = BlindFuncs{};
+	let mut prm = mock_prm(&funcs);
 
-	prm.grow_graph(&[0.35, -0.125], goal, 0.05, 5.0, 5000, 100000).expect("graph not grown up to solution");
-	prm.print_summary();
-	let policy = prm.plan_belief_space(&vec![0.25, 0.25, 0.25, 0.25]);
+	assert_eq!(prm.unresolvable_world_pairs(&vec![0.5, 0.5]), Ok(vec![(0, 1)]));
+	assert_eq!(prm.unresolvable_world_pairs(&vec![1.0, 0.0]), Ok(vec![]));
+}
 
-	let mut m2 = m.clone();
-	m2.resize(5);
-	m2.draw_full_graph(&prm.graph);
-	m2.draw_zones_observability();
-	m2.draw_policy(&policy);
-	m2.save("results/test_prm_on_map2_fov_pomdp");
+#[test]
+fn test_snap_to_goal() {
+	// every sample is in the goal, farther than max_step from the start
+	let (low, up) = ([0.25, -0.05], [0.35, 0.05]);
+	let goal_sample = ContinuousSampler::new(low, up).sample();
+
+	let funcs = FreeSpaceFuncs{n_worlds: 1};
+	let mut parameters = GrowthParameters{ max_step: 0.05, n_iter_min: 1, n_iter_max: 1, ..Default::default() };
+
+	let mut prm = PRMBuilder::new(&funcs, low, up).build();
+	assert!(prm.grow_graph_with(&[0.0, 0.0], ball_goal([0.3, 0.0], 0.1, 1), &parameters).is_err());
+	assert!((norm2(&prm.graph.nodes[1].state, &[0.0, 0.0]) - 0.05).abs() < 1e-9);
+
+	parameters.snap_distance = Some(0.5);
+	let mut prm = PRMBuilder::new(&funcs, low, up).build();
+	prm.grow_graph_with(&[0.0, 0.0], ball_goal([0.3, 0.0], 0.1, 1), &parameters).unwrap();
+	assert_eq!(prm.graph.nodes[1].state, goal_sample);
+	assert_eq!(prm.final_node_ids, vec![1]);
 }
 
 #[test]
-fn test_build_belief_graph() {
-	let mut m = Map::open("data/map1.pgm", [-1.0, -1.0], [1.0, 1.0]);
-	m.add_zones("data/map1_zone_ids.pgm", 0.1);
+fn test_belief_graph_construction_is_deterministic() {
+	struct Funcs {}
+	impl PRMFuncs<2> for Funcs {
+		fn state_validity(&self, _state: &[f64; 2]) -> Option<WorldMask> {
+			Some(bitvec![1, 1])
+		}
 
-	fn goal(state: &[f64; 2]) -> WorldMask {
-		bitvec![if (state[0] - 0.55).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05 { 1 } else { 0 }; 4]
+		fn reachable_belief_states(&self, belief_state: &BeliefState) -> Vec<BeliefState> {
+			vec![belief_state.clone(), vec![1.0, 0.0], vec![0.0, 1.0]]
+		}
+
+		fn observe(&self, state: &[f64; 2], belief_state: &BeliefState) -> Vec<BeliefState> {
+			if state[0] > 0.5 && belief_state[0] > 0.0 && belief_state[1] > 0.0 { vec![vec![1.0, 0.0], vec![0.0, 1.0]] } else { vec![belief_state.clone()] }
+		}
 	}
 
-	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
-						   DiscreteSampler::new(),
-						   &m);
-	// mock graph growth
-	prm.n_worlds = 2;
+	let funcs = Funcs{};
+	let mut prm = PRMBuilder::new(&funcs, [-1.0, -1.0], [1.0, 1.0])
+		.max_step(0.1)
+		.iter_bounds(1000, 5000)
+		.grow(&[-0.9, -0.9], ball_goal([0.9, 0.9], 0.1, 2))
+		.unwrap();
+
+	let mut plan = || {
+		let policy = prm.plan_belief_space(&vec![0.5, 0.5]).unwrap();
+		let belief_nodes: Vec<_> = prm.belief_graph().nodes.iter()
+			.map(|node| (node.state, node.belief_id, node.node_type, node.parents.clone(), node.children.clone()))
+			.collect();
+		let policy_nodes: Vec<_> = policy.nodes.iter()
+			.map(|node| (node.state, node.belief_state.clone(), node.parent, node.children.clone(), node.cost_to_goal))
+			.collect();
+		(belief_nodes, policy_nodes)
+	};
+
+	let (belief_nodes, policy_nodes) = plan();
+	assert!(belief_nodes.iter().any(|(_, _, node_type, _, _)| *node_type == BeliefNodeType::Observation));
+	assert_eq!(plan(), (belief_nodes, policy_nodes));
+}
 
-	prm.graph.add_node([0.55, -0.8], bitvec![1, 1]); // 0
-	prm.graph.add_node([-0.42, -0.38], bitvec![1, 1]); // 1
-	prm.graph.add_node([0.54, 0.0], bitvec![1, 1]);   // 2
-	prm.graph.add_node([0.54, 0.1], bitvec![0, 1]);   // 3
-	prm.graph.add_node([-0.97, 0.65], bitvec![1, 1]); // 4
-	prm.graph.add_node([0.55, 0.9], bitvec![1, 1]);   // 5
+#[test]
+fn test_max_degree() {
+	let funcs = FreeSpaceFuncs{n_worlds: 1};
+	let builder = PRMBuilder::new(&funcs, [-1.0, -1.0], [1.0, 1.0])
+		.max_step(0.2)
+		.search_radius(5.0)
+		.iter_bounds(2000, 2000);
+	let max_out_degree = |prm: &PRM<FreeSpaceFuncs, 2>| prm.graph.nodes.iter().map(|node| node.children.len()).max().unwrap();
+
+	let prm = builder.grow(&[0.0, 0.0], ball_goal([0.9, 0.9], 0.1, 1)).unwrap();
+	assert!(max_out_degree(&prm) > 6);
+
+	let prm = builder.max_degree(6).grow(&[0.0, 0.0], ball_goal([0.9, 0.9], 0.1, 1)).unwrap();
+	assert!(max_out_degree(&prm) <= 6);
+	assert!(prm.final_node_ids.len() > 0);
+}
 
-	prm.graph.add_bi_edge(0, 1, bitvec![1, 1]);
-	prm.graph.add_bi_edge(1, 2, bitvec![1, 1]);
-	prm.graph.add_bi_edge(2, 3, bitvec![0, 1]);
-	prm.graph.add_bi_edge(3, 5, bitvec![0, 1]);
+#[test]
+fn test_cost_field_for_belief() {
+	let funcs = MockFuncs{};
+	let mut prm = mock_prm(&funcs);
 
-	prm.graph.add_bi_edge(1, 4, bitvec![1, 1]);
-	prm.graph.add_bi_edge(4, 5, bitvec![1, 1]);
+	prm.plan_belief_space(&vec![0.5, 0.5]).unwrap();
 
-	prm.final_node_ids.push(5);
-	//
+	let cost_field = prm.cost_field_for_belief(0);
+	assert_eq!(cost_field.len(), prm.graph.n_nodes());
 
-	let _policy = prm.plan_belief_space(&vec![0.5, 0.5]);	
-	assert_eq!(prm.belief_graph.nodes[6].children, vec![7, 8]); // observation transitions
-	assert!(!prm.belief_graph.nodes[7].children.contains(&6)); // observation is irreversible
-	assert!(!prm.belief_graph.nodes[8].children.contains(&6)); // observation is irreversible
+	let root_belief_node_id = prm.belief_nodes(0)[0].unwrap();
+	assert_eq!(cost_field[0], ([0.55, -0.8], prm.expected_costs()[root_belief_node_id]));
+	assert_eq!(cost_field[5].1, 0.0);
+	assert_eq!(cost_field[3].1, std::f64::INFINITY); // only valid in world 1
+}
 
-	for (id, node) in prm.belief_graph.nodes.iter().enumerate() { // belief jump only at observation points
-		if id == 6 {
-			continue;
+#[test]
+fn test_belief_cost_evaluator_forbids_edges() {
+	// 0 -> 1 -> 3 is the short way, 0 -> 2 -> 3 the long one, 0 -> 1 is forbidden when world 0 is likely
+	struct Funcs {
+		single_belief: bool,
+	}
+	impl PRMFuncs<2> for Funcs {
+		fn belief_cost_evaluator(&self, a: &[f64; 2], b: &[f64; 2], cost: f64, belief_state: &BeliefState) -> Option<f64> {
+			if *a == [0.0, 0.0] && *b == [0.5, 0.1] && belief_state[0] > 0.5 { None } else { Some(cost) }
 		}
-		for &child_id in &node.children {
-			assert_eq!(node.belief_id, prm.belief_graph.nodes[child_id].belief_id);
+
+		fn reachable_belief_states(&self, belief_state: &BeliefState) -> Vec<BeliefState> {
+			if self.single_belief { vec![belief_state.clone()] } else { vec![belief_state.clone(), vec![0.5, 0.5]] }
 		}
 	}
 
-	// draw
-	//let mut full = m.clone();
-	//full.resize(5);
-	//full.draw_full_graph(&prm.graph);
-	//full.draw_policy(&policy);
-	//full.save("results/test_build_belief_graph.pgm");
+	for single_belief in [false, true] {
+		let funcs = Funcs{single_belief};
+		let plan = |belief_state: BeliefState| {
+			let mut prm = PRM::new(ContinuousSampler::new([-1.0; 2], [1.0; 2]),
+								   DiscreteSampler::new(),
+								   &funcs);
+			prm.n_worlds = 2;
+			for state in [[0.0, 0.0], [0.5, 0.1], [0.5, 1.0], [1.0, 0.0]] {
+				prm.graph.add_node(state, bitvec![1, 1]);
+			}
+			for (from, to) in [(0, 1), (1, 3), (0, 2), (2, 3)] {
+				prm.graph.add_bi_edge(from, to, bitvec![1, 1]);
+			}
+			prm.final_node_ids.push(3);
+
+			prm.plan_belief_space(&belief_state).expect("invalid start belief state")
+		};
+
+		let policy = plan(vec![1.0, 0.0]);
+		assert_eq!(policy.path_to_leaf(0), vec![[0.0, 0.0], [0.5, 1.0], [1.0, 0.0]]);
+		assert!(policy.nodes[0].cost_to_goal.is_finite());
+
+		let policy = plan(vec![0.0, 1.0]);
+		assert_eq!(policy.path_to_leaf(0), vec![[0.0, 0.0], [0.5, 0.1], [1.0, 0.0]]);
+	}
 }
 }
 
@@ -435,43 +3156,5 @@ fn test_build_belief_graph() {
 // - optimize nearest neighbor (avoid sqrt)
 // - actual PRM roadmap
 
-// tentative propagation code
-/*
-extern crate queues;
-use queues::*;
-use std::collections::HashSet;
-
-let mut visited = HashSet::new();
-let mut queue: Queue<usize> = queue![];
-//
-
-// connect new node to neighbor
-for &id in &bwd_ids {
-	self.graph.add_edge(new_node_id, id);
-	self.conservative_reachability.add_edge(new_node_id, id);
-
-	
-	// propagate
-	visited.insert(id);
-	queue.add(id).expect("Overflow!");
-}
-
-//
-
-while queue.size() > 0 {
-	let from_id = queue.remove().unwrap();
-
-	for to_id in self.graph.nodes[from_id].children.clone() {
-		if !contains(&self.conservative_reachability.reachability(to_id), &state_validity) {
-			self.conservative_reachability.add_edge(from_id, to_id);
-
-			if !visited.contains(&to_id) {
-				//println!("add node:{}", to_id);
-				queue.add(to_id).expect("Overflow");
-				visited.insert(to_id);
-			}
-		}
-	}
-}*/
 //println!("---");
 //
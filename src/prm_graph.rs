@@ -1,6 +1,7 @@
 use itertools::{all, enumerate, izip, merge, zip};
 
 use crate::common::*;
+use crate::belief_graph::transition_probability;
 use crate::nearest_neighbor::*;
 use crate::sample_space::*;
 use crate::map_io::*;
@@ -15,6 +16,8 @@ use serde::{Serialize, Deserialize};
 
 use priority_queue::PriorityQueue;
 use bitvec::prelude::*;
+extern crate queues;
+use queues::*;
 use minilp::{ComparisonOp, OptimizationDirection, Problem, Variable};
 
 /***************************IO*****************************/
@@ -31,6 +34,8 @@ pub struct SerializablePRMNode {
 pub struct SerializablePRMEdge {
 	pub id: usize,
 	pub validity: Vec<bool>,
+	#[serde(default)]
+	pub cost: Option<f64>, // missing in older files, the length is used
 }
 
 impl SerializablePRMEdge {
@@ -39,6 +44,7 @@ impl SerializablePRMEdge {
 		Self{
 			id: edge.id,			
 			validity: edge.validity.iter().map(|b| !!b).collect(),
+			cost: Some(edge.cost),
 		}
 	}
 
@@ -46,6 +52,7 @@ impl SerializablePRMEdge {
 		PRMEdge {
 			id: self.id,
 			validity: self.validity.iter().collect(),
+			cost: self.cost.unwrap_or(std::f64::NAN),
 		}
 	}
 }
@@ -100,9 +107,22 @@ pub fn load(filename: &str) -> PRMGraph<2> {
 	let reader = BufReader::new(File::open(filename).expect("impossible to open file"));
 	let graph: SerializablePRMGraph = serde_json::from_reader(reader).unwrap();
 
-	PRMGraph {
+	let mut prm_graph = PRMGraph {
 		nodes: graph.nodes.into_iter().map(|node| node.to_prm_node()).collect()
+	};
+
+	// costs missing in the file
+	let states: Vec<[f64; 2]> = prm_graph.nodes.iter().map(|node| node.state).collect();
+	for (id, node) in prm_graph.nodes.iter_mut().enumerate() {
+		for edge in &mut node.children {
+			if edge.cost.is_nan() { edge.cost = norm2(&states[id], &states[edge.id]); }
+		}
+		for edge in &mut node.parents {
+			if edge.cost.is_nan() { edge.cost = norm2(&states[edge.id], &states[id]); }
+		}
 	}
+
+	prm_graph
 }
 
 /****************************PRM Graph******************************/
@@ -112,22 +132,35 @@ pub trait PRMFuncs<const N: usize> {
 	}
 
 	fn transition_validator(&self, from: &PRMNode<N>, to: &PRMNode<N>) -> Option<WorldMask> {
-		//let validity = from.validity.iter().zip(&to.validity)
-		//.any(|(a, b)| *a && *b);
-
-		let validity: WorldMask = from.validity.iter().zip(&to.validity)
-		.map(|(a, b)| *a && *b)
-		.collect();
+		let validity = from.shared_validity(to);
 
-		if validity.any() {
+		if validity.any_valid() {
 			return Some(validity);
 		}
 		
 		None
 	}
 
+	/// metric of the steps, of the connection radius and of the default costs
+	fn norm(&self) -> Norm {
+		Norm::L2
+	}
+
 	fn cost_evaluator(&self, a: &[f64; N], b: &[f64; N]) -> f64 {
-		norm2(a,b)
+		self.norm().distance(a, b)
+	}
+
+	/// lower bound of the cost of going from a to b, the heuristic of the weighted A* (see PRM::set_heuristic_weight)
+	/// to be overridden along with cost_evaluator or belief_cost_evaluator when they can be cheaper than the norm
+	fn cost_lower_bound(&self, a: &[f64; N], b: &[f64; N]) -> f64 {
+		self.norm().distance(a, b)
+	}
+
+	// cost of a transition taken in the given belief state, used in belief space
+	// cost is the cost_evaluator value cached in the graph edge, None forbids the transition in this belief state
+	#[allow(clippy::style)]
+	fn belief_cost_evaluator(&self, _a: &[f64; N], _b: &[f64; N], cost: f64, _belief_state: &BeliefState) -> Option<f64> {
+		Some(cost)
 	}
 
 	#[allow(clippy::style)]
@@ -139,6 +172,17 @@ pub trait PRMFuncs<const N: usize> {
 	fn observe(&self, _state: &[f64; N], belief_state: &BeliefState) -> Vec<BeliefState> {
 		vec![belief_state.to_owned()]
 	}
+
+	/// outcomes of observe with their probabilities, by default the mass of belief_state on their support (see transition_probability)
+	/// to be overridden by the soft observations (e.g. RangeDetectionModel), whose outcomes keep the support of belief_state
+	fn observe_with_probabilities(&self, state: &[f64; N], belief_state: &BeliefState) -> Vec<(BeliefState, f64)> {
+		self.observe(state, belief_state).into_iter()
+			.map(|child_belief_state| {
+				let p = transition_probability(belief_state, &child_belief_state);
+				(child_belief_state, p)
+			})
+			.collect()
+	}
 }
 
 #[derive(Clone)]
@@ -152,7 +196,20 @@ pub struct PRMNode<const N: usize> {
 #[derive(Clone)]
 pub struct PRMEdge {
 	pub id: usize,
-	pub validity: WorldMask
+	pub validity: WorldMask,
+	pub cost: f64, // cost_evaluator, computed once when the edge is added
+}
+
+impl<const N: usize> WorldValidity for PRMNode<N> {
+	fn validity(&self) -> &WorldMask {
+		&self.validity
+	}
+}
+
+impl WorldValidity for PRMEdge {
+	fn validity(&self) -> &WorldMask {
+		&self.validity
+	}
 }
 
 impl<const N: usize> GraphNode<N> for PRMNode<N> {
@@ -174,9 +231,21 @@ impl<const N: usize> PRMGraph<N> {
 		id
 	}
 
+	/// the cost of the edge is its length, see add_edge_with_cost
 	pub fn add_edge(&mut self, from_id: usize, to_id: usize, validity: WorldMask) {
-		self.nodes[from_id].children.push(PRMEdge{id: to_id, validity: validity.clone()});
-		self.nodes[to_id].parents.push(PRMEdge{id:from_id, validity});
+		let cost = norm2(&self.nodes[from_id].state, &self.nodes[to_id].state);
+		self.add_edge_with_cost(from_id, to_id, validity, cost);
+	}
+
+	pub fn add_edge_with_cost(&mut self, from_id: usize, to_id: usize, validity: WorldMask, cost: f64) {
+		self.nodes[from_id].children.push(PRMEdge{id: to_id, validity: validity.clone(), cost});
+		self.nodes[to_id].parents.push(PRMEdge{id:from_id, validity, cost});
+	}
+
+	pub fn edge_cost(&self, from_id: usize, to_id: usize) -> Option<f64> {
+		self.nodes[from_id].children.iter()
+			.find(|edge| edge.id == to_id)
+			.map(|edge| edge.cost)
 	}
 
 	pub fn add_bi_edge(&mut self, id1: usize, id2: usize, validity: WorldMask) {
@@ -189,6 +258,103 @@ impl<const N: usize> PRMGraph<N> {
 		self.nodes[to_id].parents.retain(|edge|{edge.id != from_id});
 	}
 
+	/// removes the worlds that aren't possible anymore from the validities, returns the number of removed (directed) edges
+	pub fn restrict_worlds(&mut self, possible_worlds: &WorldMask) -> usize {
+		let mut n_removed_edges = 0;
+
+		for node in &mut self.nodes {
+			node.validity &= possible_worlds.iter().map(|w| *w);
+
+			for edge in node.children.iter_mut().chain(node.parents.iter_mut()) {
+				edge.validity &= possible_worlds.iter().map(|w| *w);
+			}
+
+			let n_children = node.children.len();
+			node.children.retain(|edge| edge.any_valid());
+			node.parents.retain(|edge| edge.any_valid());
+			n_removed_edges += n_children - node.children.len();
+		}
+
+		n_removed_edges
+	}
+
+	/// worlds in which each node can be reached from the root (multi-world BFS)
+	pub fn reachability_from(&self, root_id: usize) -> Vec<WorldMask> {
+		let n_worlds = self.nodes[root_id].validity.len();
+		let mut reachability = vec![bitvec![0; n_worlds]; self.nodes.len()];
+		let mut queue: Queue<usize> = queue![];
+
+		reachability[root_id] = self.nodes[root_id].validity.clone();
+		queue.add(root_id).expect("Overflow!");
+
+		while queue.size() > 0 {
+			let from_id = queue.remove().unwrap();
+
+			for to_edge in &self.nodes[from_id].children {
+				let to = &self.nodes[to_edge.id];
+				let mut expanded = false;
+
+				for world in 0..n_worlds {
+					if reachability[from_id].valid_in(world) && to_edge.valid_in(world) && to.valid_in(world) && !reachability[to_edge.id].valid_in(world) {
+						reachability[to_edge.id].set(world, true);
+						expanded = true;
+					}
+				}
+
+				if expanded {
+					queue.add(to_edge.id).expect("Overflow");
+				}
+			}
+		}
+
+		reachability
+	}
+
+	/// removes the nodes that are not reachable from the root in any world, returns the number of removed nodes
+	pub fn prune_unreachable(&mut self, root_id: usize) -> usize {
+		let new_ids = self.prune_unreachable_impl(root_id);
+		new_ids.iter().filter(|id| id.is_none()).count()
+	}
+
+	/// same as prune_unreachable but returns the new id of each node (None if removed)
+	pub fn prune_unreachable_impl(&mut self, root_id: usize) -> Vec<Option<usize>> {
+		let keep: Vec<bool> = self.reachability_from(root_id).iter()
+			.map(|reachability| reachability.any())
+			.collect();
+
+		self.retain_nodes(&keep)
+	}
+
+	/// keeps the nodes flagged in keep (order is preserved) and remaps the edges, returns the new id of each node
+	pub fn retain_nodes(&mut self, keep: &[bool]) -> Vec<Option<usize>> {
+		let mut new_ids = vec![None; self.nodes.len()];
+		let mut n_kept = 0;
+		for (id, &k) in keep.iter().enumerate() {
+			if k {
+				new_ids[id] = Some(n_kept);
+				n_kept += 1;
+			}
+		}
+
+		let remap = |edges: &Vec<PRMEdge>| -> Vec<PRMEdge> {
+			edges.iter()
+				.filter_map(|edge| new_ids[edge.id].map(|id| PRMEdge{id, validity: edge.validity.clone(), cost: edge.cost}))
+				.collect()
+		};
+
+		let nodes = std::mem::take(&mut self.nodes);
+		self.nodes = nodes.into_iter().zip(keep)
+			.filter(|(_, &k)| k)
+			.map(|(node, _)| PRMNode{
+				parents: remap(&node.parents),
+				children: remap(&node.children),
+				..node
+			})
+			.collect();
+
+		new_ids
+	}
+
 	pub fn print_summary(&self) {
 		let (sum, max) = self.nodes.iter()
 			.map(|node| node.children.len())
@@ -200,18 +366,24 @@ impl<const N: usize> PRMGraph<N> {
 	}
 }
 
+fn edge_id(edge: &PRMEdge) -> usize {
+	edge.id
+}
+
 impl<const N: usize> Graph<N> for PRMGraph<N> {
+	type Neighbors<'a> = std::iter::Map<std::slice::Iter<'a, PRMEdge>, fn(&PRMEdge) -> usize>;
+
 	fn node(&self, id:usize) -> &dyn GraphNode<N> {
 		&self.nodes[id]
 	}
 	fn n_nodes(&self) -> usize {
 		self.nodes.len()
 	}
-	fn children(&self, id: usize) -> Vec<usize> {
-		self.nodes[id].children.iter().map(|edge| edge.id).collect()
+	fn children(&self, id: usize) -> Self::Neighbors<'_> {
+		self.nodes[id].children.iter().map(edge_id)
 	}
-	fn parents(&self, id:usize) -> Vec<usize> {
-		self.nodes[id].parents.iter().map(|edge| edge.id).collect()
+	fn parents(&self, id:usize) -> Self::Neighbors<'_> {
+		self.nodes[id].parents.iter().map(edge_id)
 	}
 }
 
@@ -220,26 +392,38 @@ pub struct PRMGraphWorldView<'a, const N: usize> {
 	pub world: usize
 }
 
+/// neighbors of a node which are valid in the world of the view
+pub struct WorldNeighbors<'a, const N: usize> {
+	nodes: &'a [PRMNode<N>],
+	world: usize,
+	edges: std::slice::Iter<'a, PRMEdge>,
+}
+
+impl<'a, const N: usize> Iterator for WorldNeighbors<'a, N> {
+	type Item = usize;
+
+	fn next(&mut self) -> Option<usize> {
+		let (nodes, world) = (self.nodes, self.world);
+		self.edges.by_ref()
+			.map(edge_id)
+			.find(|&id| nodes[id].valid_in(world))
+	}
+}
+
 impl<'a, const N: usize> Graph<N> for PRMGraphWorldView<'a, N> {
+	type Neighbors<'b> = WorldNeighbors<'b, N> where Self: 'b;
+
 	fn node(&self, id:usize) -> &dyn GraphNode<N> {
 		self.graph.node(id)
 	}
 	fn n_nodes(&self) -> usize {
 		self.graph.n_nodes()
 	}
-	fn children(&self, id: usize) -> Vec<usize> {
-		//panic!("deprecated!");
-		self.graph.nodes[id].children.iter()
-			.map(|edge| edge.id)
-			.filter(|&id| self.graph.nodes[id].validity[self.world])
-			.collect()
+	fn children(&self, id: usize) -> Self::Neighbors<'_> {
+		WorldNeighbors{ nodes: &self.graph.nodes, world: self.world, edges: self.graph.nodes[id].children.iter() }
 	}
-	fn parents(& self, id:usize) -> Vec<usize> {
-		//panic!("deprecated!");
-		self.graph.nodes[id].parents.iter()
-			.map(|edge| edge.id)
-			.filter(|&id| self.graph.nodes[id].validity[self.world])
-			.collect()
+	fn parents(& self, id:usize) -> Self::Neighbors<'_> {
+		WorldNeighbors{ nodes: &self.graph.nodes, world: self.world, edges: self.graph.nodes[id].parents.iter() }
 	}
 }
 
@@ -480,12 +664,82 @@ fn create_diamond_graph_2_worlds() -> PRMGraph<2> {
 	graph
 }
 
+#[test]
+fn test_world_validity() {
+	let mut graph = PRMGraph{nodes: Vec::new()};
+	graph.add_node([0.0, 0.0], bitvec![1, 0, 1, 1]);
+	graph.add_node([1.0, 0.0], bitvec![1, 1, 1, 1]);
+	graph.add_node([2.0, 0.0], bitvec![0, 0, 0, 0]);
+	graph.add_edge(0, 1, bitvec![0, 0, 1, 0]);
+
+	let node = &graph.nodes[0];
+	assert!(node.valid_in(0));
+	assert!(!node.valid_in(1));
+	assert!(node.valid_in(2));
+	assert!(node.any_valid());
+	assert!(!node.all_valid());
+
+	assert!(graph.nodes[1].all_valid());
+	assert!(!graph.nodes[2].any_valid());
+
+	let edge = &graph.nodes[0].children[0];
+	assert!(edge.valid_in(2));
+	assert!(!edge.valid_in(0));
+	assert!(edge.any_valid());
+	assert!(!edge.all_valid());
+
+	assert_eq!(node.shared_validity(edge), bitvec![0, 0, 1, 0]);
+	assert!(node.possible_in(&vec![0.0, 0.5, 0.5, 0.0]));
+	assert!(!node.possible_in(&vec![0.0, 1.0, 0.0, 0.0]));
+	assert!(bitvec![0, 1].valid_in(1));
+}
+
+#[test]
+fn test_prune_isolated_nodes() {
+	let mut graph = create_grid_graph();
+	graph.add_node([5.0, 5.0], bitvec![1]); // 9, isolated
+	graph.add_node([6.0, 5.0], bitvec![1]); // 10, only connected to 9
+	graph.add_bi_edge(9, 10, bitvec![1]);
+
+	assert_eq!(graph.prune_unreachable(0), 2);
+	assert_eq!(graph.nodes.len(), 9);
+
+	for (id, node) in graph.nodes.iter().enumerate() {
+		for edge in &node.children {
+			assert!(graph.nodes[edge.id].parents.iter().any(|parent| parent.id == id));
+		}
+		for edge in &node.parents {
+			assert!(graph.nodes[edge.id].children.iter().any(|child| child.id == id));
+		}
+	}
+	assert_eq!(to_ids(&graph.nodes[4].children), vec![1, 3, 5, 7]);
+	assert_eq!(to_ids(&graph.nodes[8].parents), vec![5, 7]);
+}
+
+#[test]
+fn test_prune_nodes_unreachable_in_any_world() {
+	let mut graph = create_diamond_graph_2_worlds();
+	graph.add_node([1.0, -2.0], bitvec![1, 0]); // 4, connected to 2 which is only valid in world 1
+	graph.add_bi_edge(2, 4, bitvec![1, 0]);
+
+	assert_eq!(graph.reachability_from(0)[4], bitvec![0, 0]);
+	assert_eq!(graph.prune_unreachable(0), 1);
+	assert_eq!(graph.nodes.len(), 4);
+	assert_eq!(to_ids(&graph.nodes[2].children), vec![0, 3]);
+}
+
 #[test]
 fn test_graph_serialization() {
 	let graph = create_minimal_graph();
 
-	save(&graph, "results/test_graph_serialization.json");
-	let _ = load("results/test_graph_serialization.json");
+	let filepath = std::env::temp_dir().join("po_rrt_test_graph_serialization.json");
+	save(&graph, filepath.to_str().unwrap());
+	let loaded = load(filepath.to_str().unwrap());
+	std::fs::remove_file(&filepath).unwrap();
+
+	assert_eq!(loaded.nodes.len(), graph.nodes.len());
+	assert_eq!(to_ids(&loaded.nodes[0].children), vec![1]);
+	assert_eq!(loaded.edge_cost(0, 1), graph.edge_cost(0, 1));
 }
 
 #[test]
@@ -33,23 +33,53 @@ impl Reachability {
 		self.finality.push(finality);
 	}
 
-	pub fn add_edge(&mut self, from: usize, to: usize) {
+	/// returns whether the reachability of to changed
+	pub fn add_edge(&mut self, from: usize, to: usize) -> bool {
 		// this version appears to be the fastest, see cargo bench
+		let mut changed = false;
 		for i in 0..self.reachability[to].len() {
 			let r_to = self.reachability[to][i];
 			let r_from = self.reachability[from][i];
 			let v_to = self.validity[to][i];
+			changed |= !r_to && r_from && v_to;
 			self.reachability[to].set(i, r_to || r_from && v_to);
 		}
+		changed
+	}
+
+	/// follows a node removal, new_ids gives the new id of each node (None if removed)
+	pub fn remap(&mut self, new_ids: &[Option<usize>]) {
+		let kept = |id: &usize| new_ids.get(*id).map_or(false, |new_id| new_id.is_some());
+
+		self.validity = self.validity.drain(..).enumerate().filter(|(id, _)| kept(id)).map(|(_, v)| v).collect();
+		self.reachability = self.reachability.drain(..).enumerate().filter(|(id, _)| kept(id)).map(|(_, r)| r).collect();
+
+		let (final_node_ids, finality) = self.final_node_ids.iter().zip(self.finality.drain(..))
+			.filter_map(|(id, finality)| new_ids[*id].map(|new_id| (new_id, finality)))
+			.unzip();
+		self.final_node_ids = final_node_ids;
+		self.finality = finality;
+	}
+
+	/// keeps the possible worlds only, reachability in the other worlds is unchanged
+	pub fn restrict_worlds(&mut self, possible_worlds: &WorldMask) {
+		for mask in self.validity.iter_mut().chain(self.reachability.iter_mut()).chain(self.finality.iter_mut()) {
+			*mask &= possible_worlds.iter().map(|w| *w);
+		}
 	}
 
 	pub fn reachability(&self, id: usize) -> &WorldMask {
 		&self.reachability[id]
 	}
 
+	/// worlds in which the node is a goal, None if it isn't a final node
+	pub fn finality(&self, id: usize) -> Option<&WorldMask> {
+		self.final_node_ids.iter().position(|&final_id| final_id == id).map(|i| &self.finality[i])
+	}
+
 	pub fn final_nodes_for_world(&self, world: usize) -> Vec<usize> {
 		self.final_node_ids.iter().enumerate()
-			.filter(|(i, &id)| self.reachability[id][world] && self.finality[*i][world])
+			.filter(|(i, &id)| self.reachability[id].valid_in(world) && self.finality[*i].valid_in(world))
 			.map(|(_, &id)| id)
 			.collect()
 	}
@@ -69,8 +99,13 @@ impl Reachability {
 	pub fn is_final_set_complete(&self) -> bool {
 		if self.final_node_ids.is_empty() { return false; }
 
-		// get first elements as starting point..
-		let mut finality = bitvec![0; self.finality[0].len()];
+		self.reached_worlds().all()
+	}
+
+	/// worlds in which a final node is reached
+	pub fn reached_worlds(&self) -> WorldMask {
+		let n_worlds = self.validity.first().map_or(0, |validity| validity.len());
+		let mut finality = bitvec![0; n_worlds];
 
 		for (&final_node_id, node_finality) in self.final_node_ids.iter().zip(self.finality.iter()) {
 			let node_reachability = &self.reachability[final_node_id];
@@ -80,7 +115,17 @@ impl Reachability {
 			}
 		}
 
-		finality.all()
+		finality
+	}
+
+	/// fraction of the worlds in which a final node is reached
+	pub fn completion_fraction(&self) -> f64 {
+		let reached_worlds = self.reached_worlds();
+		if reached_worlds.is_empty() {
+			return 0.0;
+		}
+
+		reached_worlds.count_ones() as f64 / reached_worlds.len() as f64
 	}
 }
 
@@ -142,6 +187,39 @@ fn test_reachability_diamond_shape() {
 	assert_eq!(reachability.reachability(3), &bitvec![1,1]);
 }
 
+#[test]
+fn test_completion_fraction() {
+	/*
+		0
+	   /|\
+	  1 2 3
+	*/
+	let mut reachability = Reachability::new();
+
+	reachability.set_root(bitvec![1,1,1,1]); // 0
+	reachability.add_node(bitvec![1,0,0,0]); // 1
+	reachability.add_node(bitvec![0,1,1,0]); // 2
+	reachability.add_node(bitvec![0,0,0,1]); // 3
+
+	reachability.add_edge(0, 1);
+	reachability.add_edge(0, 2);
+	reachability.add_edge(0, 3);
+
+	assert_eq!(reachability.completion_fraction(), 0.0);
+
+	let mut previous = 0.0;
+	for &(id, ref finality) in &[(1, bitvec![1,1,1,1]), (2, bitvec![1,1,1,1]), (3, bitvec![1,1,1,1])] {
+		reachability.add_final_node(id, finality.clone());
+		let fraction = reachability.completion_fraction();
+		assert!(fraction > previous);
+		previous = fraction;
+	}
+
+	assert_eq!(reachability.reached_worlds(), bitvec![1,1,1,1]);
+	assert_eq!(previous, 1.0);
+	assert!(reachability.is_final_set_complete());
+}
+
 #[test]
 fn test_final_nodes_completness() {
 	/*
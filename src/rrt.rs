@@ -5,7 +5,7 @@ use crate::nearest_neighbor::*;
 use crate::sample_space::*;
 use crate::map_io::*;
 use crate::belief_graph::*;
-use std::{cmp::min, collections::{self, HashSet}};
+use std::{cmp::min, collections::{self, HashMap, HashSet}};
 
 #[derive(PartialEq, Eq, Hash)]
 pub enum Reachable<'a> {
@@ -30,6 +30,16 @@ pub struct RRTDefaultSamplers<const N: usize> {
 	pub belief_state_sampler: DiscreteSampler,
 }
 
+impl<const N: usize> RRTDefaultSamplers<N> {
+	/// uniform states in the [low, up] box, default seeds
+	pub fn new(low: [f64; N], up: [f64; N]) -> Self {
+		Self {
+			state_sampler: ContinuousSampler::new(low, up),
+			belief_state_sampler: DiscreteSampler::new(),
+		}
+	}
+}
+
 /*
 impl Default for RRTDefaultSamplers<2> {
 	fn default() -> Self {
@@ -47,6 +57,15 @@ impl<const N: usize> SampleFuncs<N> for RRTDefaultSamplers<N> {
     }
 }
 
+/// growth of plan_within_budget, which stops at the first goal node costing at most cost_budget
+#[derive(Clone, Copy, Debug)]
+pub struct BudgetParameters {
+	pub max_step: f64,
+	pub search_radius: f64,
+	pub n_iter_max: u32,
+	pub cost_budget: f64,
+}
+
 #[derive(Clone, Copy)]
 pub struct ParentLink {
 	pub id: usize,
@@ -54,14 +73,19 @@ pub struct ParentLink {
 	//pub world_mask: Reachable<'a>,
 }
 
+#[derive(Clone)]
 pub struct RRTNode<const N: usize> {
 	pub id: usize,
 	pub state: [f64; N],
 	pub belief_state_id: usize,
 	pub node_type: BeliefNodeType,
 	pub parent: Option<ParentLink>,
+	pub children: Vec<usize>, // kept in sync with the parent links
+	pub cost_from_root: f64, // sum of the parent link distances up to the root
+	pub segment: Vec<[f64; N]>, // intermediate states of the edge from the parent, see RRTFuncs::extend
 }
 
+#[derive(Clone)]
 pub struct RRTTree<const N: usize> {
 	pub nodes: Vec<RRTNode<N>>,
 	pub belief_states: Vec<BeliefState>
@@ -74,8 +98,12 @@ impl<'a, const N: usize> RRTTree<N> {
 
 	fn add_node(&mut self, state: [f64; N], belief_state_id: usize, node_type: BeliefNodeType, parent: Option<ParentLink>) -> usize {
 		let id = self.nodes.len();
-		let node = RRTNode { id, state, belief_state_id, node_type, parent };
+		let cost_from_root = parent.map_or(0.0, |parent| self.nodes[parent.id].cost_from_root + parent.dist);
+		let node = RRTNode { id, state, belief_state_id, node_type, parent, children: Vec::new(), cost_from_root, segment: Vec::new() };
 		self.nodes.push(node);
+		if let Some(parent) = parent {
+			self.nodes[parent.id].children.push(id);
+		}
 		assert!(belief_state_id < self.belief_states.len());
 		id
 	}
@@ -95,9 +123,19 @@ impl<'a, const N: usize> RRTTree<N> {
 			})
 	}
 
+	/// the cost_from_root of the whole subtree of node_id is shifted accordingly
 	fn reparent_node(&mut self, node_id: usize, parent: ParentLink) {
-		let node = &mut self.nodes[node_id];
-		node.parent = Some(parent);
+		let delta = self.nodes[parent.id].cost_from_root + parent.dist - self.nodes[node_id].cost_from_root;
+		if let Some(previous_parent) = self.nodes[node_id].parent.replace(parent) {
+			self.nodes[previous_parent.id].children.retain(|&id| id != node_id);
+		}
+		self.nodes[parent.id].children.push(node_id);
+
+		let mut lifo = vec![node_id];
+		while let Some(id) = lifo.pop() {
+			self.nodes[id].cost_from_root += delta;
+			lifo.extend(&self.nodes[id].children);
+		}
 	}
 
 	fn distances_from_common_ancestor(&self, leaf_ids: &[usize]) -> Vec<f64> {
@@ -109,25 +147,17 @@ impl<'a, const N: usize> RRTTree<N> {
 			return vec![0.0];
 		}
 
-		let compute_distance_from_root = |mut node_id: usize| {
-			let mut cost = 0.0;
-			while let Some(parent_link) = &self.nodes[node_id].parent {
-				cost += parent_link.dist;
-				node_id = parent_link.id;
-
-			}
-			cost
-		};
-
 		leaf_ids.iter()
-			.map(|id| compute_distance_from_root(*id))
+			.map(|&id| self.nodes[id].cost_from_root)
 			.collect()
 	}
 
+	/// the intermediate states of the edges are included
 	fn get_path_to(&self, mut node_id: usize) -> Vec<[f64; N]> {
 		let mut path = vec![self.nodes[node_id].state];
 
 		while let Some(parent_link) = &self.nodes[node_id].parent {
+			path.extend(self.nodes[node_id].segment.iter().rev());
 			node_id = parent_link.id;
 			path.push(self.nodes[node_id].state);
 		}
@@ -146,8 +176,20 @@ pub trait RRTFuncs<const N: usize> {
 		Reachable::Always
 	}
 
+	/// metric of the steps, of the connection radius and of the default costs
+	fn norm(&self) -> Norm {
+		Norm::L2
+	}
+
 	fn cost_evaluator(&self, a: &[f64; N], b: &[f64; N]) -> f64 {
-		norm2(a,b)
+		self.norm().distance(a, b)
+	}
+
+	/// curved or variable-cost connections (e.g. Dubins): intermediate states from `from` to `to` (both excluded) and cost of the edge,
+	/// None if they can't be connected, the validity is still given by transition_validator
+	/// defaults to the straight segment, at the cost of cost_evaluator
+	fn extend(&self, from: &[f64; N], to: &[f64; N]) -> Option<(Vec<[f64; N]>, f64)> {
+		Some((Vec::new(), self.cost_evaluator(from, to)))
 	}
 
 	#[allow(clippy::style)]
@@ -165,27 +207,125 @@ pub trait SampleFuncs<const N: usize> {
 pub struct RRT<'a, FS: SampleFuncs<N>,  F: RRTFuncs<N>, const N: usize> {
 	samplers: &'a mut FS,
 	fns: &'a F,
+	// persistent search, kept between extend() calls
+	rrttree: RRTTree<N>,
+	kdtrees: HashMap<usize, KdTree<N>>, // one per belief state
+	transition_set: HashSet<(usize, Vec<usize>)>,
+	final_node_ids: Vec<usize>,
+	goal: Option<fn(&[f64; N]) -> bool>,
+	max_step: f64,
+	search_radius: f64,
+	snap_distance: Option<f64>,
+	n_it: u32,
 }
 
 impl<'a, FS: SampleFuncs<N>,  F: RRTFuncs<N>, const N: usize> RRT<'a, FS, F, N> {
 	pub fn new(samplers: &'a mut FS, fns: &'a F) -> Self {
-		Self { samplers, fns }
+		Self { samplers,
+			   fns,
+			   rrttree: RRTTree::new(),
+			   kdtrees: HashMap::new(),
+			   transition_set: HashSet::new(),
+			   final_node_ids: Vec::new(),
+			   goal: None,
+			   max_step: 0.0,
+			   search_radius: 0.0,
+			   snap_distance: None,
+			   n_it: 0 }
+	}
+
+	/// goal samples closer than snap_distance to the tree are reached in one step, beyond max_step
+	pub fn set_snap_distance(&mut self, snap_distance: f64) {
+		self.snap_distance = Some(snap_distance);
 	}
 
 	#[allow(clippy::style, clippy::type_complexity)]
 	pub fn plan(&mut self, start: [f64; N], start_belief_state: &BeliefState, goal: fn(&[f64; N]) -> bool,
 				 max_step: f64, search_radius: f64, n_iter_max: u32) -> (RRTTree<N>, Policy<N>, Vec<(usize, Vec<[f64; N]>)>) {
-		let mut transition_set = HashSet::new();
-		let mut final_node_ids = Vec::<usize>::new();
-		let mut rrttree = RRTTree::new();
+		self.init(start, start_belief_state, goal, max_step, search_radius);
+		self.extend(n_iter_max);
 
-		let mut kdtrees = std::collections::HashMap::new();
+		let (policy, best_paths) = self.extract_policy();
 
-		{
-			let belief_id = rrttree.maybe_add_belief_state(start_belief_state);
-			rrttree.add_node(start, belief_id, BeliefNodeType::Action, None); // root node
-			kdtrees.insert(belief_id, KdTree::new(start));
-		}
+		(self.rrttree.clone(), policy, best_paths)
+	}
+
+	/// same as plan, the best path of each belief state comes with its cost (see get_path_cost)
+	#[allow(clippy::style, clippy::type_complexity)]
+	pub fn plan_with_cost(&mut self, start: [f64; N], start_belief_state: &BeliefState, goal: fn(&[f64; N]) -> bool,
+				 max_step: f64, search_radius: f64, n_iter_max: u32) -> (RRTTree<N>, Policy<N>, Vec<(usize, Vec<[f64; N]>, f64)>) {
+		let (rrttree, policy, best_paths) = self.plan(start, start_belief_state, goal, max_step, search_radius, n_iter_max);
+
+		let best_paths = best_paths.into_iter()
+			.map(|(belief_id, path)| { let cost = self.get_path_cost(&path); (belief_id, path, cost) })
+			.collect();
+
+		(rrttree, policy, best_paths)
+	}
+
+	/// stops growing as soon as a goal node costs at most parameters.cost_budget, and returns its path and cost
+	/// None if no such node was found within parameters.n_iter_max iterations
+	#[allow(clippy::style)]
+	pub fn plan_within_budget(&mut self, start: [f64; N], start_belief_state: &BeliefState, goal: fn(&[f64; N]) -> bool,
+				 parameters: &BudgetParameters) -> Option<(Vec<[f64; N]>, f64)> {
+		self.init(start, start_belief_state, goal, parameters.max_step, parameters.search_radius);
+		let goal_id = self.extend_impl(parameters.n_iter_max, Some(parameters.cost_budget))?;
+
+		Some((self.rrttree.get_path_to(goal_id), self.rrttree.nodes[goal_id].cost_from_root))
+	}
+
+	/// sum of the cost_evaluator values along the path
+	pub fn get_path_cost(&self, path: &[[f64; N]]) -> f64 {
+		pairwise_iter(path).map(|(a, b)| self.fns.cost_evaluator(a, b)).sum()
+	}
+
+	/// resets the tree to its root, the tree is then grown with extend
+	#[allow(clippy::style)]
+	pub fn init(&mut self, start: [f64; N], start_belief_state: &BeliefState, goal: fn(&[f64; N]) -> bool, max_step: f64, search_radius: f64) {
+		self.rrttree = RRTTree::new();
+		self.kdtrees = HashMap::new();
+		self.transition_set = HashSet::new();
+		self.final_node_ids = Vec::new();
+		self.goal = Some(goal);
+		self.max_step = max_step;
+		self.search_radius = search_radius;
+		self.n_it = 0;
+
+		let belief_id = self.rrttree.maybe_add_belief_state(start_belief_state);
+		self.rrttree.add_node(start, belief_id, BeliefNodeType::Action, None); // root node
+		self.kdtrees.insert(belief_id, KdTree::new(start));
+	}
+
+	/// rebuilds the tree from a new start, with the same root belief state, goal and number of iterations
+	pub fn replan_from(&mut self, new_start: [f64; N]) {
+		let goal = self.goal.expect("the tree should be initialized before replanning");
+		let start_belief_state = self.rrttree.belief_states[self.rrttree.nodes[0].belief_state_id].clone();
+		let n_it = self.n_it;
+
+		self.init(new_start, &start_belief_state, goal, self.max_step, self.search_radius);
+		self.extend(n_it);
+	}
+
+	pub fn tree(&self) -> &RRTTree<N> {
+		&self.rrttree
+	}
+
+	/// number of iterations the tree has been grown with since init
+	pub fn n_iterations(&self) -> u32 {
+		self.n_it
+	}
+
+	/// grows the tree by n_iter more iterations
+	pub fn extend(&mut self, n_iter_max: u32) {
+		self.extend_impl(n_iter_max, None);
+	}
+
+	/// with a cost_budget, stops at the first goal node whose cost from the root is within it and returns its id
+	#[allow(clippy::style)]
+	fn extend_impl(&mut self, n_iter_max: u32, cost_budget: Option<f64>) -> Option<usize> {
+		let goal = self.goal.expect("the tree should be initialized before being extended");
+		let (max_step, search_radius, snap_distance) = (self.max_step, self.search_radius, self.snap_distance);
+		let Self { samplers, fns, rrttree, kdtrees, transition_set, final_node_ids, n_it, .. } = self;
 
 		let mut last_status_update_time = std::time::Instant::now();
 
@@ -195,22 +335,26 @@ impl<'a, FS: SampleFuncs<N>,  F: RRTFuncs<N>, const N: usize> RRT<'a, FS, F, N>
 				last_status_update_time = std::time::Instant::now();
 			}
 
-			let mut new_state = self.samplers.sample_state();
-			let sampled_belief_id = self.samplers.sample_discrete(rrttree.belief_states.len());
+			let mut new_state = samplers.sample_state();
+			let sampled_belief_id = samplers.sample_discrete(rrttree.belief_states.len());
 
 			let kdtree = kdtrees.get_mut(&sampled_belief_id).expect("kdtree missing");
 
-			// XXX nearest_neighbor_filtered can return the root even if the filter closure disagrees.
-			let canonical_neighbor = kdtree
-				.nearest_neighbor_filtered(new_state, |id| rrttree.nodes[id].node_type != BeliefNodeType::Observation); // n log n
-			steer(&canonical_neighbor.state, &mut new_state, max_step);
+			let canonical_neighbor = match kdtree
+				.nearest_neighbor_filtered(new_state, |id| rrttree.nodes[id].node_type != BeliefNodeType::Observation) { // n log n
+				Some(canonical_neighbor) => canonical_neighbor,
+				None => continue,
+			};
+			if !snap_distance.map_or(false, |snap_distance| fns.norm().distance(&canonical_neighbor.state, &new_state) <= snap_distance && goal(&new_state)) {
+				fns.norm().steer(&canonical_neighbor.state, &mut new_state, max_step);
+			}
 
 			//
 			//assert!(rrttree.nodes[canonical_neighbor.id].node_type != BeliefNodeType::Observation);
 			//
 
 			let belief_state = &rrttree.belief_states[sampled_belief_id];
-			if self.fns.state_validator(&new_state).is_compatible(&belief_state) {
+			if fns.state_validator(&new_state).is_compatible(&belief_state) {
 				// RRT* algorithm
 				// Step 1: Find all the neighbors near of new_state. The radius we use is from papers of RRT*
 
@@ -222,7 +366,7 @@ impl<'a, FS: SampleFuncs<N>,  F: RRTFuncs<N>, const N: usize> RRT<'a, FS, F, N>
 				};
 
 				let mut neighbor_ids: Vec<usize> = kdtree
-					.nearest_neighbors_filtered(new_state, radius, |id| rrttree.nodes[id].node_type != BeliefNodeType::Observation)
+					.nearest_neighbors_filtered_with_norm(new_state, radius, fns.norm(), |id| rrttree.nodes[id].node_type != BeliefNodeType::Observation)
 					.iter()
 					.map(|&kd_node| kd_node.id)
 					.collect();
@@ -237,11 +381,11 @@ impl<'a, FS: SampleFuncs<N>,  F: RRTFuncs<N>, const N: usize> RRT<'a, FS, F, N>
 				//
 
 				// Step 2: Retain only the neighbors that have valid transitions and are compatible with our belief
-				let neighbor_ids: Vec<usize> = neighbor_ids.iter()
-					.map(|&id| (id, self.fns.transition_validator(&rrttree.nodes[id].state, &new_state) ) )
+				let (neighbor_ids, neighbor_extensions): (Vec<usize>, Vec<(Vec<[f64; N]>, f64)>) = neighbor_ids.iter()
+					.map(|&id| (id, fns.transition_validator(&rrttree.nodes[id].state, &new_state) ) )
 					.filter(|(id, transition)| transition.is_compatible(belief_state) && rrttree.nodes[*id].node_type != BeliefNodeType::Observation)
-					.map(|(id, _)| id)
-					.collect();
+					.filter_map(|(id, _)| fns.extend(&rrttree.nodes[id].state, &new_state).map(|extension| (id, extension)))
+					.unzip();
 
 				if neighbor_ids.is_empty() {
 					continue;
@@ -251,16 +395,17 @@ impl<'a, FS: SampleFuncs<N>,  F: RRTFuncs<N>, const N: usize> RRT<'a, FS, F, N>
 				// distance between root (or common ancestor) and each neighbor
 				let root_to_neighbor_distances = rrttree.distances_from_common_ancestor(&neighbor_ids);
 				// distance between each neighbor and the new_state node
-				let neighbor_to_new_state_distances = neighbor_ids.iter().cloned()
-					.map(|id| self.fns.cost_evaluator(&rrttree.nodes[id].state, &new_state))
+				let neighbor_to_new_state_distances = neighbor_extensions.iter()
+					.map(|(_, cost)| *cost)
 					.collect::<Vec<_>>();
 
 				// Step 4: Find the best parent we can get.
-				let (parent_id, _, parent_to_new_state_dist, root_to_new_state_distance) =
-					izip!(&neighbor_ids, &root_to_neighbor_distances, &neighbor_to_new_state_distances)
-						.map(|(&id, &rnd, &nnd)| (id, rnd, nnd, rnd+nnd))
+				let (parent_index, _, parent_to_new_state_dist, root_to_new_state_distance) =
+					izip!(0.., &root_to_neighbor_distances, &neighbor_to_new_state_distances)
+						.map(|(index, &rnd, &nnd)| (index, rnd, nnd, rnd+nnd))
 						.min_by(|(_, _, _, a), (_, _, _, b)| a.partial_cmp(b).unwrap())
 						.unwrap();
+				let parent_id = neighbor_ids[parent_index];
 
 				//
 				//assert!(rrttree.nodes[parent_id].node_type != BeliefNodeType::Observation);
@@ -268,23 +413,28 @@ impl<'a, FS: SampleFuncs<N>,  F: RRTFuncs<N>, const N: usize> RRT<'a, FS, F, N>
 
 				// Step 5: Add the node new_state in the trees
 				let belief_state = &rrttree.belief_states[sampled_belief_id];
-				let children_belief_states = self.fns.observe_new_beliefs(&new_state, &belief_state);
+				let children_belief_states = fns.observe_new_beliefs(&new_state, &belief_state);
 				let new_node_type =  if children_belief_states.len() > 1 { BeliefNodeType::Observation } else { BeliefNodeType::Action };
 				let parent_link = ParentLink { id: parent_id, dist: parent_to_new_state_dist };
 				let new_node_id = rrttree.add_node(new_state, sampled_belief_id, new_node_type, Some(parent_link));
+				rrttree.nodes[new_node_id].segment = neighbor_extensions[parent_index].0.clone();
 				kdtree.add(new_state, new_node_id);
 
 				// Step 6: Reparent neighbors that could be better with the new_state node as a parent.
 				if rrttree.nodes[new_node_id].node_type == BeliefNodeType::Action {
 					if true {
-						for (&neighbor_id, &root_to_neighbor_distance, &neighbor_to_new_state_distance) in
-								izip!(&neighbor_ids, &root_to_neighbor_distances, &neighbor_to_new_state_distances) {
+						for (&neighbor_id, &root_to_neighbor_distance) in izip!(&neighbor_ids, &root_to_neighbor_distances) {
 							if neighbor_id == parent_id { continue; }
 
 							// XXX We should call self.fns.transition_validator() again if the transition validator is not symetric.
-							// XXX We also assume that cost from A to B is the same from B to A.
 
-							let root_distance_if_new_state_parent = root_to_new_state_distance + neighbor_to_new_state_distance;
+							// the connection is extended in the new direction, it may differ from the reversed one (or be impossible)
+							let (new_state_to_neighbor_segment, new_state_to_neighbor_distance) = match fns.extend(&new_state, &rrttree.nodes[neighbor_id].state) {
+								Some(extension) => extension,
+								None => continue,
+							};
+
+							let root_distance_if_new_state_parent = root_to_new_state_distance + new_state_to_neighbor_distance;
 							if root_distance_if_new_state_parent < root_to_neighbor_distance {
 								// reparent
 								if let Some(previous_parent) =  rrttree.nodes[neighbor_id].parent {
@@ -310,8 +460,9 @@ impl<'a, FS: SampleFuncs<N>,  F: RRTFuncs<N>, const N: usize> RRT<'a, FS, F, N>
 									}
 								} 
 								
-								let parent_link = ParentLink { id: new_node_id, dist: neighbor_to_new_state_distance };
-								rrttree.nodes[neighbor_id].parent = Some(parent_link);
+								let parent_link = ParentLink { id: new_node_id, dist: new_state_to_neighbor_distance };
+								rrttree.reparent_node(neighbor_id, parent_link);
+								rrttree.nodes[neighbor_id].segment = new_state_to_neighbor_segment;
 							}
 						}
 					}
@@ -341,10 +492,25 @@ impl<'a, FS: SampleFuncs<N>,  F: RRTFuncs<N>, const N: usize> RRT<'a, FS, F, N>
 
 					let belief_state = &rrttree.belief_states[sampled_belief_id];
 					println!("found leaf for belief:{}, {:?}", sampled_belief_id, &belief_state);
+
+					if cost_budget.map_or(false, |cost_budget| rrttree.nodes[new_node_id].cost_from_root <= cost_budget) {
+						*n_it += i + 1;
+						return Some(new_node_id);
+					}
 				}
 			}
 		}
 
+		*n_it += n_iter_max;
+		None
+	}
+
+	/// policy and best path for each belief state in the current tree
+	#[allow(clippy::type_complexity)]
+	pub fn extract_policy(&self) -> (Policy<N>, Vec<(usize, Vec<[f64; N]>)>) {
+		let rrttree = &self.rrttree;
+		let mut final_node_ids = self.final_node_ids.clone();
+
 		// paths to leafs
 		let best_goal_ids = {
 			final_node_ids.sort_by_key(|&id| rrttree.nodes[id].belief_state_id);
@@ -363,14 +529,65 @@ impl<'a, FS: SampleFuncs<N>,  F: RRTFuncs<N>, const N: usize> RRT<'a, FS, F, N>
 			.collect();
 		//
 
-		let belief_graph = BeliefGraph::from(&rrttree);
-		let expected_costs_to_goal = conditional_dijkstra(&belief_graph, &final_node_ids, |a: &[f64; N], b: &[f64;N]| self.fns.cost_evaluator(a, b));
+		let belief_graph = BeliefGraph::from(rrttree);
+		// the costs of the tree edges, as given by RRTFuncs::extend
+		let expected_costs_to_goal = conditional_dijkstra_with_edge_costs(&belief_graph, &final_node_ids, |_, v_id| rrttree.nodes[v_id].parent.map_or(0.0, |parent| parent.dist), 0.0);
 		let policy = extract_policy(&belief_graph, &expected_costs_to_goal);
 
-		(rrttree, policy, best_paths)
+		(policy, best_paths)
+	}
+
+	/// cheapest path to the goal in each homotopy class, at most max_classes of them, cheapest first
+	/// two paths are in the same class if the loop they form doesn't wind around any of the obstacle centroids
+	pub fn distinct_solutions(&self, obstacle_centroids: &[[f64; 2]], max_classes: usize) -> Vec<Vec<[f64; N]>> {
+		let rrttree = &self.rrttree;
+		let mut final_node_ids = self.final_node_ids.clone();
+		final_node_ids.sort_unstable();
+		final_node_ids.dedup();
+
+		let costs = rrttree.distances_from_common_ancestor(&final_node_ids);
+		let mut candidates: Vec<_> = zip(final_node_ids, costs).collect();
+		candidates.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+		let mut representatives: Vec<Vec<[f64; N]>> = Vec::new();
+		for (id, _) in candidates {
+			if representatives.len() >= max_classes {
+				break;
+			}
+
+			let path = rrttree.get_path_to(id);
+			let is_new_class = representatives.iter().all(|representative| {
+				let closed_loop: Vec<_> = path.iter().chain(representative.iter().rev()).collect();
+				obstacle_centroids.iter().any(|centroid| winding_number(&closed_loop, centroid) != 0)
+			});
+
+			if is_new_class {
+				representatives.push(path);
+			}
+		}
+
+		representatives
 	}
 }
 
+/// number of turns the closed polygon makes around center, in the plane of the 2 first coordinates
+fn winding_number<const N: usize>(polygon: &[&[f64; N]], center: &[f64; 2]) -> i32 {
+	let angle = |p: &[f64; N]| (p[1] - center[1]).atan2(p[0] - center[0]);
+
+	let mut total = 0.0;
+	for (a, b) in polygon.iter().zip(polygon.iter().cycle().skip(1)) {
+		let mut delta = angle(b) - angle(a);
+		if delta > std::f64::consts::PI {
+			delta -= 2.0 * std::f64::consts::PI;
+		} else if delta < -std::f64::consts::PI {
+			delta += 2.0 * std::f64::consts::PI;
+		}
+		total += delta;
+	}
+
+	(total / (2.0 * std::f64::consts::PI)).round() as i32
+}
+
 impl <const N: usize> From<&RRTTree<N>> for BeliefGraph<N> {
 	#[allow(clippy::style)]
     fn from(rrttree: &RRTTree<N>) -> Self {
@@ -384,6 +601,7 @@ impl <const N: usize> From<&RRTTree<N>> for BeliefGraph<N> {
 				parents: n.parent.map(|p| vec![p.id] ).unwrap_or_else(|| vec![]),
 				children: vec![],
 				node_type: n.node_type,
+				outcome_probabilities: collections::BTreeMap::new(),
 			}).collect();
 
 		for (id, n) in rrttree.nodes.iter().enumerate() {
@@ -494,6 +712,287 @@ fn test_plan_on_map2() {
 	m.save("results/test_rrt_on_map")
 }
 
+#[test]
+fn test_extend_and_replan() {
+	struct Funcs {}
+	impl RRTFuncs<2> for Funcs {}
+
+	fn goal(state: &[f64; 2]) -> bool {
+		(state[0] - 0.9).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05
+	}
+
+	let mut samplers = RRTDefaultSamplers {
+		state_sampler: ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+		belief_state_sampler: DiscreteSampler::new(),
+	};
+
+	let mut rrt = RRT::new(&mut samplers, &Funcs{});
+	rrt.init([0.0, 0.0], &vec![1.0], goal, 0.1, 1.0);
+	rrt.extend(500);
+
+	let states: Vec<[f64; 2]> = rrt.tree().nodes.iter().map(|node| node.state).collect();
+	assert!(states.len() > 1);
+
+	rrt.extend(500);
+
+	// old nodes remain, new ones appear
+	let tree = rrt.tree();
+	assert!(tree.nodes.len() > states.len());
+	for (node, state) in tree.nodes.iter().zip(&states) {
+		assert_eq!(node.state, *state);
+	}
+
+	let (policy, _) = rrt.extract_policy();
+	assert_eq!(policy.nodes[0].state, [0.0, 0.0]);
+	assert!(goal(&policy.leaf(0).state));
+
+	// replan from a moved start
+	rrt.replan_from([-0.5, 0.0]);
+	assert_eq!(rrt.tree().nodes[0].state, [-0.5, 0.0]);
+	assert!(rrt.tree().nodes[0].parent.is_none());
+	assert!(rrt.tree().nodes.len() > 1);
+	let (policy, _) = rrt.extract_policy();
+	assert_eq!(policy.nodes[0].state, [-0.5, 0.0]);
+}
+
+#[test]
+fn test_distinct_solutions_around_central_obstacle() {
+	struct Funcs {}
+	impl RRTFuncs<2> for Funcs {
+		fn state_validator(&self, state: &[f64; 2]) -> Reachable {
+			if state[0].abs() < 0.3 && state[1].abs() < 0.3 { Reachable::Never } else { Reachable::Always }
+		}
+
+		fn transition_validator(&self, from: &[f64; 2], to: &[f64; 2]) -> Reachable {
+			if sample_segment(from, to, 0.01).all(|state| self.state_validator(&state) == Reachable::Always) { Reachable::Always } else { Reachable::Never }
+		}
+	}
+
+	fn goal(state: &[f64; 2]) -> bool {
+		state[1] > 0.8
+	}
+
+	let mut samplers = RRTDefaultSamplers {
+		state_sampler: ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+		belief_state_sampler: DiscreteSampler::new(),
+	};
+
+	let mut rrt = RRT::new(&mut samplers, &Funcs{});
+	rrt.init([0.0, -0.8], &vec![1.0], goal, 0.1, 1.0);
+	rrt.extend(3000);
+
+	let solutions = rrt.distinct_solutions(&[[0.0, 0.0]], 5);
+	assert_eq!(solutions.len(), 2);
+
+	let passes_left = |path: &Vec<[f64; 2]>| path.iter().any(|state| state[0] < -0.3 && state[1].abs() < 0.3);
+	let passes_right = |path: &Vec<[f64; 2]>| path.iter().any(|state| state[0] > 0.3 && state[1].abs() < 0.3);
+	assert!(solutions.iter().any(passes_left));
+	assert!(solutions.iter().any(passes_right));
+	assert_eq!(rrt.distinct_solutions(&[[0.0, 0.0]], 1).len(), 1);
+}
+
+#[test]
+fn test_snap_to_goal() {
+	struct Funcs {}
+	impl RRTFuncs<2> for Funcs {}
+
+	fn goal(state: &[f64; 2]) -> bool {
+		(state[0] - 0.3).abs() < 0.1 && state[1].abs() < 0.1
+	}
+
+	// every sample is in the goal, farther than max_step from the start
+	let (low, up) = ([0.25, -0.05], [0.35, 0.05]);
+	let goal_sample = ContinuousSampler::new(low, up).sample();
+
+	let mut samplers = RRTDefaultSamplers {
+		state_sampler: ContinuousSampler::new(low, up),
+		belief_state_sampler: DiscreteSampler::new(),
+	};
+
+	let mut rrt = RRT::new(&mut samplers, &Funcs{});
+	rrt.set_snap_distance(0.5);
+	rrt.init([0.0, 0.0], &vec![1.0], goal, 0.05, 1.0);
+	rrt.extend(1);
+
+	assert_eq!(rrt.tree().nodes[1].state, goal_sample);
+}
+
+#[test]
+fn test_plan_with_cost() {
+	struct Funcs {}
+	impl RRTFuncs<2> for Funcs {}
+
+	fn goal(state: &[f64; 2]) -> bool {
+		(state[0] - 0.9).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05
+	}
+
+	let mut samplers = RRTDefaultSamplers {
+		state_sampler: ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+		belief_state_sampler: DiscreteSampler::new(),
+	};
+
+	let mut rrt = RRT::new(&mut samplers, &Funcs{});
+	let (_rrttree, _policy, best_paths) = rrt.plan_with_cost([0.0, 0.0], &vec![1.0], goal, 0.1, 1.0, 1000);
+	assert_eq!(best_paths.len(), 1);
+
+	let (_, path, cost) = &best_paths[0];
+	assert_eq!(*cost, rrt.get_path_cost(path));
+	assert!(*cost >= norm2(&path[0], path.last().unwrap()));
+	assert!(goal(path.last().unwrap()));
+}
+
+#[test]
+fn test_plan_with_default_samplers_on_map() {
+	let m = Map::open("data/map0.pgm", [-1.0, -1.0], [1.0, 1.0]);
+
+	fn goal(state: &[f64; 2]) -> bool {
+		(state[0] - 0.9).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05
+	}
+
+	let mut samplers = RRTDefaultSamplers::new([-1.0, -1.0], [1.0, 1.0]);
+	let mut rrt = RRT::new(&mut samplers, &m);
+	let (_rrttree, policy, _paths) = rrt.plan([0.0, -0.8], &vec![1.0], goal, 0.1, 5.0, 5000);
+
+	assert_eq!(policy.leafs.len(), 1);
+	assert!(goal(&policy.leaf(0).state));
+}
+
+#[test]
+fn test_cost_from_root() {
+	struct Funcs {}
+	impl RRTFuncs<2> for Funcs {}
+
+	fn goal(state: &[f64; 2]) -> bool {
+		(state[0] - 0.9).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05
+	}
+
+	let mut samplers = RRTDefaultSamplers {
+		state_sampler: ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+		belief_state_sampler: DiscreteSampler::new(),
+	};
+
+	let mut rrt = RRT::new(&mut samplers, &Funcs{});
+	let (rrttree, _policy, _best_paths) = rrt.plan([0.0, 0.0], &vec![1.0], goal, 0.1, 1.0, 1000);
+
+	assert_eq!(rrttree.nodes[0].cost_from_root, 0.0);
+	for node in &rrttree.nodes {
+		let path = rrttree.get_path_to(node.id);
+		assert!((node.cost_from_root - rrt.get_path_cost(&path)).abs() < 1e-9);
+
+		// the children lists follow the reparentings
+		for &child_id in &node.children {
+			assert_eq!(rrttree.nodes[child_id].parent.unwrap().id, node.id);
+		}
+		if let Some(parent) = node.parent {
+			assert_eq!(rrttree.nodes[parent.id].children.iter().filter(|&&id| id == node.id).count(), 1);
+		}
+	}
+}
+
+#[test]
+fn test_plan_with_extender() {
+	// manhattan moves: along x, then along y
+	struct Funcs {}
+	impl RRTFuncs<2> for Funcs {
+		fn extend(&self, from: &[f64; 2], to: &[f64; 2]) -> Option<(Vec<[f64; 2]>, f64)> {
+			Some((vec![[to[0], from[1]]], (to[0] - from[0]).abs() + (to[1] - from[1]).abs()))
+		}
+	}
+
+	fn goal(state: &[f64; 2]) -> bool {
+		(state[0] - 0.9).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05
+	}
+
+	let mut samplers = RRTDefaultSamplers::new([-1.0, -1.0], [1.0, 1.0]);
+	let mut rrt = RRT::new(&mut samplers, &Funcs{});
+	let (rrttree, _policy, best_paths) = rrt.plan([0.0, 0.0], &vec![1.0], goal, 0.1, 1.0, 1000);
+	assert_eq!(best_paths.len(), 1);
+
+	let goal_id = *rrt.final_node_ids.iter()
+		.min_by(|&&a, &&b| rrttree.nodes[a].cost_from_root.partial_cmp(&rrttree.nodes[b].cost_from_root).unwrap())
+		.unwrap();
+	let path = rrttree.get_path_to(goal_id);
+	assert_eq!(path, best_paths[0].1);
+
+	// each edge comes with its corner, the cost is the one of the extender
+	let (mut n_edges, mut id) = (0, goal_id);
+	while let Some(parent) = rrttree.nodes[id].parent {
+		n_edges += 1;
+		id = parent.id;
+	}
+	assert_eq!(path.len(), 2 * n_edges + 1);
+	let manhattan: f64 = pairwise_iter(&path).map(|(a, b)| (b[0] - a[0]).abs() + (b[1] - a[1]).abs()).sum();
+	for (a, b) in pairwise_iter(&path) {
+		assert!(a[0] == b[0] || a[1] == b[1]);
+	}
+	assert!((rrttree.nodes[goal_id].cost_from_root - manhattan).abs() < 1e-9);
+
+	// the rewired nodes too: their corner is extended from the new parent (along x first), not the reversed extension
+	for node in &rrttree.nodes {
+		if let Some(parent) = node.parent {
+			let parent_state = rrttree.nodes[parent.id].state;
+			assert_eq!(node.segment, vec![[node.state[0], parent_state[1]]]);
+		}
+	}
+}
+
+#[test]
+fn test_plan_within_budget_on_map0() {
+	let m = Map::open("data/map0.pgm", [-1.0, -1.0], [1.0, 1.0]);
+
+	fn goal(state: &[f64; 2]) -> bool {
+		(state[0] - 0.9).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05
+	}
+
+	let seeded_samplers = || RRTDefaultSamplers {
+		state_sampler: ContinuousSampler::new_with_seed([-1.0, -1.0], [1.0, 1.0], 1),
+		belief_state_sampler: DiscreteSampler::new_with_seed(1),
+	};
+
+	// full planning, all the iterations are run
+	let mut samplers = seeded_samplers();
+	let mut rrt = RRT::new(&mut samplers, &m);
+	rrt.plan([0.0, -0.8], &vec![1.0], goal, 0.1, 5.0, 5000);
+	let full_iterations = rrt.n_iterations();
+	assert_eq!(full_iterations, 5000);
+
+	let mut samplers = seeded_samplers();
+	let mut rrt = RRT::new(&mut samplers, &m);
+	let parameters = BudgetParameters{ max_step: 0.1, search_radius: 5.0, n_iter_max: 5000, cost_budget: 10.0 };
+	let (path, cost) = rrt.plan_within_budget([0.0, -0.8], &vec![1.0], goal, &parameters)
+		.expect("no path within budget");
+
+	assert!(cost <= 10.0);
+	assert!(goal(path.last().unwrap()));
+	assert!((rrt.get_path_cost(&path) - cost).abs() < 1e-9);
+	assert!(rrt.n_iterations() < full_iterations);
+}
+
+#[test]
+fn test_plan_within_budget() {
+	struct Funcs {}
+	impl RRTFuncs<2> for Funcs {}
+
+	fn goal(state: &[f64; 2]) -> bool {
+		(state[0] - 0.9).abs() < 0.05 && (state[1] - 0.9).abs() < 0.05
+	}
+
+	let mut samplers = RRTDefaultSamplers::new([-1.0, -1.0], [1.0, 1.0]);
+	let mut rrt = RRT::new(&mut samplers, &Funcs{});
+
+	// a budget below the straight line distance can't be met
+	let parameters = BudgetParameters{ max_step: 0.1, search_radius: 1.0, n_iter_max: 1000, cost_budget: 1.0 };
+	assert!(rrt.plan_within_budget([0.0, 0.0], &vec![1.0], goal, &parameters).is_none());
+	assert_eq!(rrt.n_iterations(), 1000);
+
+	let parameters = BudgetParameters{ cost_budget: 10.0, ..parameters };
+	let (path, cost) = rrt.plan_within_budget([0.0, 0.0], &vec![1.0], goal, &parameters).unwrap();
+	assert!(cost <= 10.0);
+	assert!(goal(path.last().unwrap()));
+	assert!(rrt.n_iterations() < 1000);
+	assert_eq!(rrt.final_node_ids.len(), 1);
+}
+
 #[test]
 fn test_plan_empty_space() {
 	struct Funcs {}
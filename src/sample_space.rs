@@ -5,34 +5,74 @@ use itertools::izip;
 pub struct ContinuousSampler<const N: usize> {
 	pub low: [f64; N],
 	pub up: [f64; N],
+	seed: Option<u64>,
 	rng: Pcg64,
 }
 
+/// the bounds should be finite and low <= up on every axis, or the samples would be NaN
+fn check_bounds<const N: usize>(low: &[f64; N], up: &[f64; N]) -> Result<(), &'static str> {
+	if low.iter().chain(up.iter()).any(|v| !v.is_finite()) {
+		return Err("the sampler bounds should be finite");
+	}
+
+	if low.iter().zip(up).any(|(l, u)| l > u) {
+		return Err("the sampler lower bounds should be below the upper bounds");
+	}
+
+	Ok(())
+}
+
 impl<const N: usize> ContinuousSampler<N> {
 	pub fn new(low: [f64; N], up: [f64; N]) -> Self {
 		// TODO use environment variable to seed the rng
+		Self::new_with_seed(low, up, 0)
+	}
+
+	/// same as new, the invalid bounds are reported instead of panicking
+	pub fn try_new(low: [f64; N], up: [f64; N]) -> Result<Self, &'static str> {
+		check_bounds(&low, &up)?;
+		Ok(Self::new(low, up))
+	}
+
+	pub fn new_with_seed(low: [f64; N], up: [f64; N], seed: u64) -> Self {
+		check_bounds(&low, &up).expect("invalid sampler bounds");
 		Self {
 			low,
 			up,
-			rng: Pcg64::seed_from_u64(0)
+			seed: Some(seed),
+			rng: Pcg64::seed_from_u64(seed)
 		}
 	}
 
 	pub fn new_true_random(low: [f64; N], up: [f64; N]) -> Self {
+		check_bounds(&low, &up).expect("invalid sampler bounds");
 		Self {
 			low,
 			up,
+			seed: None,
 			rng: Pcg64::from_rng(rand::thread_rng()).unwrap()
 		}
 	}
 
+	/// None for new_true_random
+	pub fn seed(&self) -> Option<u64> {
+		self.seed
+	}
+
 	pub fn sample(&mut self) -> [f64; N] {
 		let mut s = [0.0; N];
 		for (v, l, u) in izip!(s.iter_mut(), self.low.iter(), self.up.iter()) {
-			*v = self.rng.gen_range(*l..*u);
+			*v = if l < u { self.rng.gen_range(*l..*u) } else { *l }; // flat axis
 		}
 		s	
 	}
+
+	/// moves the state back into the sampler bounds, axis by axis
+	pub fn clamp(&self, state: &mut [f64; N]) {
+		for (v, l, u) in izip!(state.iter_mut(), self.low.iter(), self.up.iter()) {
+			*v = v.clamp(*l, *u);
+		}
+	}
 }
 
 pub struct DiscreteSampler{
@@ -42,8 +82,12 @@ pub struct DiscreteSampler{
 impl DiscreteSampler {
 	#[allow(clippy::new_without_default)]
 	pub fn new() -> Self {
+		Self::new_with_seed(0)
+	}
+
+	pub fn new_with_seed(seed: u64) -> Self {
 		Self {
-			rng: Pcg64::seed_from_u64(0)
+			rng: Pcg64::seed_from_u64(seed)
 		}
 	}
 
@@ -56,6 +100,11 @@ impl DiscreteSampler {
 	pub fn sample(&mut self, n: usize) -> usize {
 		self.rng.gen_range(0..n)
 	}
+
+	/// uniform in [0, 1)
+	pub fn sample_unit(&mut self) -> f64 {
+		self.rng.gen_range(0.0..1.0)
+	}
 }
 
 #[cfg(test)]
@@ -86,6 +135,16 @@ fn draw_sample() {
 		}
 	}
 
+#[test]
+fn invalid_bounds_are_rejected() {
+	assert!(ContinuousSampler::try_new([1.0, -1.0], [-1.0, 1.0]).is_err());
+	assert!(ContinuousSampler::try_new([f64::NAN, -1.0], [1.0, 1.0]).is_err());
+
+	// a flat axis is valid
+	let mut space = ContinuousSampler::try_new([-1.0, 0.5], [1.0, 0.5]).unwrap();
+	assert_eq!(space.sample()[1], 0.5);
+}
+
 #[test]
 fn draw_discrete_sample() {
 	let mut space = DiscreteSampler::new();
@@ -0,0 +1,145 @@
+use crate::common::*;
+use crate::map_io::*;
+use crate::prm::*;
+use crate::prm_graph::*;
+use crate::sample_space::*;
+use bitvec::prelude::*;
+
+/// PRM on the [-1, 1] square of the test maps, with samplers seeded by seed
+pub fn deterministic_prm(map: &Map, seed: u64) -> PRM<Map, 2> {
+	PRM::new(ContinuousSampler::new_with_seed([-1.0, -1.0], [1.0, 1.0], seed),
+			 DiscreteSampler::new_with_seed(seed),
+			 map)
+}
+
+/// growth parameters of the map tests, same as grow_graph(start, goal, 0.05, 5.0, 5000, 100000)
+pub fn map_growth_parameters() -> GrowthParameters {
+	GrowthParameters{ max_step: 0.05, search_radius: 5.0, n_iter_min: 5000, n_iter_max: 100000, ..Default::default() }
+}
+
+/// each world possible in the root belief ends at one of its goals
+pub fn assert_reaches_goal_in_all_worlds(policy: &Policy<2>, goal: impl Fn(&[f64; 2]) -> WorldMask) {
+	assert_eq!(policy.validate(), Ok(()));
+
+	for (world, &p) in policy.nodes[0].belief_state.iter().enumerate() {
+		if p > 0.0 {
+			assert!(policy.leafs.iter().map(|&id| &policy.nodes[id]).any(|leaf| leaf.belief_state[world] > 0.0 && goal(&leaf.state)[world]),
+				"no leaf at a goal for world {}", world);
+		}
+	}
+}
+
+pub fn assert_finite_root_cost(policy: &Policy<2>) {
+	assert!(policy.nodes[0].cost_to_goal.is_finite(), "infinite root cost");
+}
+
+/// every state is valid in the n_worlds worlds, without observations
+pub struct FreeSpaceFuncs {
+	pub n_worlds: usize,
+}
+
+impl<const N: usize> PRMFuncs<N> for FreeSpaceFuncs {
+	fn state_validity(&self, _state: &[f64; N]) -> Option<WorldMask> {
+		Some(bitvec![1; self.n_worlds])
+	}
+}
+
+/// the belief state and the beliefs resolving each of its worlds
+fn resolved_belief_states(belief_state: &BeliefState) -> Vec<BeliefState> {
+	let mut belief_states = vec![belief_state.clone()];
+	for world in 0..belief_state.len() {
+		let mut resolved = vec![0.0; belief_state.len()];
+		resolved[world] = 1.0;
+		belief_states.push(resolved);
+	}
+	belief_states
+}
+
+/// observing at the origin resolves the world, there is nothing to observe elsewhere
+pub struct OriginObservationFuncs {}
+
+impl<const N: usize> PRMFuncs<N> for OriginObservationFuncs {
+	fn reachable_belief_states(&self, belief_state: &BeliefState) -> Vec<BeliefState> {
+		resolved_belief_states(belief_state)
+	}
+
+	fn observe(&self, state: &[f64; N], belief_state: &BeliefState) -> Vec<BeliefState> {
+		if *state == [0.0; N] && belief_state.iter().filter(|&&p| p > 0.0).count() > 1 {
+			resolved_belief_states(belief_state).split_off(1)
+		}
+		else {
+			vec![belief_state.clone()]
+		}
+	}
+}
+
+/// several reachable belief states, but observe never splits the belief
+pub struct BlindFuncs {}
+
+impl<const N: usize> PRMFuncs<N> for BlindFuncs {
+	fn reachable_belief_states(&self, belief_state: &BeliefState) -> Vec<BeliefState> {
+		resolved_belief_states(belief_state)
+	}
+}
+
+pub fn create_mock_graph() -> PRMGraph<2> {
+	/*
+	  5
+	 / \
+	4  (3)
+	|   |
+	|   2
+	 \ /
+	  1
+	  |
+	  0
+	*/
+	// 3 is only valid in world 1, observation happens in 2
+	let mut graph = PRMGraph{nodes: Vec::new()};
+
+	graph.add_node([0.55, -0.8], bitvec![1, 1]); // 0
+	graph.add_node([-0.42, -0.38], bitvec![1, 1]); // 1
+	graph.add_node([0.54, 0.0], bitvec![1, 1]);   // 2
+	graph.add_node([0.54, 0.1], bitvec![0, 1]);   // 3
+	graph.add_node([-0.97, 0.65], bitvec![1, 1]); // 4
+	graph.add_node([0.55, 0.9], bitvec![1, 1]);   // 5
+
+	graph.add_bi_edge(0, 1, bitvec![1, 1]);
+	graph.add_bi_edge(1, 2, bitvec![1, 1]);
+	graph.add_bi_edge(2, 3, bitvec![0, 1]);
+	graph.add_bi_edge(3, 5, bitvec![0, 1]);
+
+	graph.add_bi_edge(1, 4, bitvec![1, 1]);
+	graph.add_bi_edge(4, 5, bitvec![1, 1]);
+
+	graph
+}
+
+/// observation model of the mock graph, without the need of a map
+pub struct MockFuncs {}
+
+impl PRMFuncs<2> for MockFuncs {
+	fn reachable_belief_states(&self, belief_state: &BeliefState) -> Vec<BeliefState> {
+		vec![belief_state.clone(), vec![1.0, 0.0], vec![0.0, 1.0]]
+	}
+
+	fn observe(&self, state: &[f64; 2], belief_state: &BeliefState) -> Vec<BeliefState> {
+		if *state == [0.54, 0.0] && belief_state[0] > 0.0 && belief_state[1] > 0.0 {
+			vec![vec![1.0, 0.0], vec![0.0, 1.0]]
+		}
+		else {
+			vec![belief_state.clone()]
+		}
+	}
+}
+
+/// PRM on create_mock_graph (mock graph growth), 2 worlds and the goal at 5
+pub fn mock_prm<F: PRMFuncs<2>>(funcs: &F) -> PRM<F, 2> {
+	let mut prm = PRM::new(ContinuousSampler::new([-1.0, -1.0], [1.0, 1.0]),
+						   DiscreteSampler::new(),
+						   funcs);
+	prm.n_worlds = 2;
+	prm.graph = create_mock_graph();
+	prm.final_node_ids.push(5);
+	prm
+}